@@ -0,0 +1,120 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 房主挂机检测的设置，来自配置文件的 [afk_host]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AfkConfig {
+    /// 房主拿到房主或上次换图后多少秒没有选图时提醒，0 表示不检测
+    pub warn_after: u64,
+    /// 多少秒后自动跳过房主，需要大于 warn_after
+    pub skip_after: u64,
+    /// 因挂机被跳过或当房主时离开房间累计这么多次后，下次轮到时跳过一次，0 表示不跳过
+    pub max_strikes: u32,
+}
+
+impl Default for AfkConfig {
+    fn default() -> Self {
+        Self { warn_after: 90, skip_after: 150, max_strikes: 2 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AfkEvent {
+    Warn,
+    Skip,
+}
+
+/// 记录房主最后一次有动作的时间和各玩家的挂机次数
+#[derive(Debug, Clone, Default)]
+pub struct HostWatch {
+    /// 房主拿到房主、换图或对局结束的时间，对局进行中为空
+    active_at: Option<Instant>,
+    warned: bool,
+    strikes: HashMap<String, u32>,
+}
+
+impl HostWatch {
+    /// 换房主、换图或对局结束时重新计时
+    pub fn reset(&mut self, now: Instant) {
+        self.active_at = Some(now);
+        self.warned = false;
+    }
+
+    /// 对局进行中不检测
+    pub fn pause(&mut self) {
+        self.active_at = None;
+        self.warned = false;
+    }
+
+    pub fn poll(&mut self, config: &AfkConfig, now: Instant) -> Option<AfkEvent> {
+        let active_at = self.active_at?;
+        if config.warn_after == 0 {
+            return None;
+        }
+        let idle = now.saturating_duration_since(active_at);
+        if idle >= Duration::from_secs(config.skip_after.max(config.warn_after)) {
+            self.active_at = None;
+            Some(AfkEvent::Skip)
+        } else if idle >= Duration::from_secs(config.warn_after) && !self.warned {
+            self.warned = true;
+            Some(AfkEvent::Warn)
+        } else {
+            None
+        }
+    }
+
+    pub fn add_strike(&mut self, name: &str) {
+        *self.strikes.entry(name.to_string()).or_default() += 1;
+    }
+
+    /// 轮到 name 当房主时调用，次数达到上限时清零并返回 true，表示这次跳过他
+    pub fn take_penalty(&mut self, name: &str, config: &AfkConfig) -> bool {
+        if config.max_strikes == 0 || self.strikes.get(name).copied().unwrap_or(0) < config.max_strikes {
+            return false;
+        }
+        self.strikes.remove(name);
+        true
+    }
+
+    /// 房主完整地打完一局后清除记录
+    pub fn clear_strikes(&mut self, name: &str) {
+        self.strikes.remove(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warn_then_skip_and_strikes() {
+        let config = AfkConfig::default();
+        let now = Instant::now();
+        let secs = |s| now + Duration::from_secs(s);
+        let mut watch = HostWatch::default();
+
+        assert_eq!(watch.poll(&config, now), None);
+        watch.reset(now);
+        assert_eq!(watch.poll(&config, secs(89)), None);
+        assert_eq!(watch.poll(&config, secs(90)), Some(AfkEvent::Warn));
+        assert_eq!(watch.poll(&config, secs(100)), None);
+        assert_eq!(watch.poll(&config, secs(150)), Some(AfkEvent::Skip));
+        assert_eq!(watch.poll(&config, secs(300)), None);
+
+        // 换图后重新计时，对局中不检测
+        watch.reset(secs(300));
+        assert_eq!(watch.poll(&config, secs(389)), None);
+        watch.pause();
+        assert_eq!(watch.poll(&config, secs(1000)), None);
+
+        watch.add_strike("a");
+        assert!(!watch.take_penalty("a", &config));
+        watch.add_strike("a");
+        assert!(watch.take_penalty("a", &config));
+        assert!(!watch.take_penalty("a", &config));
+        assert!(!watch.take_penalty("a", &AfkConfig { max_strikes: 0, ..config }));
+    }
+}
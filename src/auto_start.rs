@@ -0,0 +1,106 @@
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 换图后的自动开始设置，来自配置文件的 [auto_start]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoStartConfig {
+    /// 换图后多少秒自动开始，0 表示不自动开始
+    pub delay: u64,
+    /// 剩余这些秒数时在房间里提醒
+    pub announce: Vec<u64>,
+    /// 每一张 !start 票把倒计时缩短多少秒
+    pub vote_shorten: u64,
+}
+
+impl Default for AutoStartConfig {
+    fn default() -> Self {
+        Self { delay: 120, announce: vec![60, 30, 10], vote_shorten: 15 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoStartEvent {
+    /// 剩余秒数到达提醒点
+    Announce(u64),
+    Start,
+}
+
+/// 一次自动开始倒计时，由房间每秒调用 [`AutoStart::poll`]
+#[derive(Debug, Clone)]
+pub struct AutoStart {
+    deadline: Instant,
+    /// 还没有提醒过的剩余秒数，从大到小
+    announce: Vec<u64>,
+}
+
+impl AutoStart {
+    /// delay 为 0 时不自动开始
+    pub fn new(config: &AutoStartConfig, now: Instant) -> Option<Self> {
+        if config.delay == 0 {
+            return None;
+        }
+        let mut announce: Vec<u64> = config.announce.iter().copied().filter(|s| *s < config.delay).collect();
+        announce.sort_unstable_by(|a, b| b.cmp(a));
+        Some(Self { deadline: now + Duration::from_secs(config.delay), announce })
+    }
+
+    /// 剩余秒数，向上取整
+    pub fn remaining(&self, now: Instant) -> u64 {
+        let remaining = self.deadline.saturating_duration_since(now);
+        remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)
+    }
+
+    /// 缩短倒计时，至少保留 min_remaining，返回缩短后的剩余秒数
+    pub fn shorten(&mut self, by: Duration, min_remaining: Duration, now: Instant) -> u64 {
+        let earliest = now + min_remaining;
+        self.deadline = self.deadline.checked_sub(by).unwrap_or(earliest).max(earliest.min(self.deadline));
+        let remaining = self.remaining(now);
+        // 跳过已经越过的提醒点
+        self.announce.retain(|s| *s < remaining);
+        remaining
+    }
+
+    /// 到时间时返回 Start，越过提醒点时返回其中最小的一个
+    pub fn poll(&mut self, now: Instant) -> Option<AutoStartEvent> {
+        if now >= self.deadline {
+            return Some(AutoStartEvent::Start);
+        }
+        let remaining = self.remaining(now);
+        let crossed = self.announce.iter().copied().filter(|s| *s >= remaining).min()?;
+        self.announce.retain(|s| *s < remaining);
+        Some(AutoStartEvent::Announce(crossed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_countdown() {
+        let config = AutoStartConfig::default();
+        let now = Instant::now();
+        let secs = |s| now + Duration::from_secs(s);
+        let mut timer = AutoStart::new(&config, now).unwrap();
+
+        assert_eq!(timer.remaining(now), 120);
+        assert_eq!(timer.poll(secs(30)), None);
+        assert_eq!(timer.poll(secs(60)), Some(AutoStartEvent::Announce(60)));
+        assert_eq!(timer.poll(secs(61)), None);
+        // 一次越过两个提醒点时只提醒最近的一个
+        assert_eq!(timer.poll(secs(111)), Some(AutoStartEvent::Announce(10)));
+        assert_eq!(timer.poll(secs(115)), None);
+        assert_eq!(timer.poll(secs(120)), Some(AutoStartEvent::Start));
+
+        // 缩短后越过的提醒点不再提醒，且至少保留最短时间
+        let mut timer = AutoStart::new(&config, now).unwrap();
+        assert_eq!(timer.shorten(Duration::from_secs(70), Duration::from_secs(5), now), 50);
+        assert_eq!(timer.poll(secs(21)), Some(AutoStartEvent::Announce(30)));
+        assert_eq!(timer.shorten(Duration::from_secs(100), Duration::from_secs(5), secs(21)), 5);
+        assert_eq!(timer.poll(secs(26)), Some(AutoStartEvent::Start));
+
+        assert!(AutoStart::new(&AutoStartConfig { delay: 0, ..config }, now).is_none());
+    }
+}
@@ -0,0 +1,344 @@
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+/// BanchoBot 在多人房间中发出的一行消息
+#[derive(Debug, Clone, PartialEq)]
+pub enum BanchoEvent {
+    /// Created the tournament match https://osu.ppy.sh/mp/123 name
+    RoomCreated { room_id: u32, name: String },
+    /// !mp settings: Room name: name, History: https://osu.ppy.sh/mp/123
+    RoomName { name: String, room_id: u32 },
+    /// !mp settings: Team mode: HeadToHead, Win condition: Score
+    TeamMode { team_mode: String, win_condition: String },
+    /// !mp settings: Active mods: Hidden, Freemod
+    ActiveMods { mods: Vec<String> },
+    /// !mp settings: Players: 3
+    PlayerCount { count: u32 },
+    /// !mp settings: Beatmap: https://osu.ppy.sh/b/123 Artist - Title [Diff]
+    CurrentBeatmap { beatmap_id: u32, title: String },
+    /// Beatmap changed to: Artist - Title [Diff] (https://osu.ppy.sh/b/123)
+    /// 或 !mp map 的回复: Changed beatmap to https://osu.ppy.sh/b/123 Artist - Title
+    BeatmapChanged { beatmap_id: u32, title: String },
+    HostChangingMap,
+    /// !mp settings: Slot 1  Not Ready https://osu.ppy.sh/u/123 name [Host / Team Blue]
+    Slot(SlotInfo),
+    PlayerJoined { name: String, slot: u32, team: Option<String> },
+    PlayerLeft { name: String },
+    PlayerMoved { name: String, slot: u32 },
+    TeamChanged { name: String, team: String },
+    /// name became the host. 或 Changed match host to name
+    HostChanged { name: String },
+    HostCleared,
+    AllPlayersReady,
+    MatchStarted,
+    MatchFinished,
+    MatchAborted,
+    /// name finished playing (Score: 123, PASSED).
+    PlayerFinished { name: String, score: u64, passed: bool },
+    PlayerKicked { name: String },
+    PasswordChanged,
+    PasswordRemoved,
+    /// !mp mods 的回复，例如 Enabled Hidden, HardRock, disabled FreeMod
+    /// 取消全部 mod 时 disabled 中为 "all mods"
+    ModsChanged { enabled: Vec<String>, disabled: Vec<String> },
+    /// Queued the match to start in 30 seconds
+    StartTimerQueued { seconds: u32 },
+    /// Match starts in 10 seconds
+    StartCountdown { seconds: u32 },
+    CountdownAborted,
+    MatchClosed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotStatus {
+    Ready,
+    NotReady,
+    NoMap,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotInfo {
+    pub slot: u32,
+    pub status: SlotStatus,
+    pub user_id: u32,
+    pub name: String,
+    /// 方括号内以 " / " 分隔的标记，例如 Host、Team Blue、Hidden, HardRock
+    pub tags: Vec<String>,
+}
+
+type Builder = fn(&Captures) -> Option<BanchoEvent>;
+
+// 每条规则都是整行匹配，避免玩家名或聊天内容中出现关键词时误判
+static RULES: LazyLock<Vec<(Regex, Builder)>> = LazyLock::new(|| {
+    let rules: Vec<(&str, Builder)> = vec![
+        (r"^Created the tournament match https://osu\.ppy\.sh/mp/(?P<id>\d+) (?P<name>.*)$", |c| {
+            Some(BanchoEvent::RoomCreated { room_id: num(c, "id")?, name: text(c, "name") })
+        }),
+        (r"^Room name: (?P<name>.*), History: https://osu\.ppy\.sh/mp/(?P<id>\d+)$", |c| {
+            Some(BanchoEvent::RoomName { name: text(c, "name"), room_id: num(c, "id")? })
+        }),
+        (r"^Team mode: (?P<mode>\w+), Win condition: (?P<win>\w+)$", |c| {
+            Some(BanchoEvent::TeamMode { team_mode: text(c, "mode"), win_condition: text(c, "win") })
+        }),
+        (r"^Active mods: (?P<mods>.+)$", |c| {
+            Some(BanchoEvent::ActiveMods { mods: split_list(&text(c, "mods"), ", ") })
+        }),
+        (r"^Players: (?P<count>\d+)$", |c| Some(BanchoEvent::PlayerCount { count: num(c, "count")? })),
+        (r"^Beatmap: https://osu\.ppy\.sh/b/(?P<id>\d+) (?P<title>.+)$", |c| {
+            Some(BanchoEvent::CurrentBeatmap { beatmap_id: num(c, "id")?, title: text(c, "title") })
+        }),
+        (r"^Beatmap changed to: (?P<title>.+) \(https://osu\.ppy\.sh/b/(?P<id>\d+)\)$", |c| {
+            Some(BanchoEvent::BeatmapChanged { beatmap_id: num(c, "id")?, title: text(c, "title") })
+        }),
+        (r"^Changed beatmap to https://osu\.ppy\.sh/b/(?P<id>\d+)(?: (?P<title>.+))?$", |c| {
+            Some(BanchoEvent::BeatmapChanged { beatmap_id: num(c, "id")?, title: text(c, "title") })
+        }),
+        (r"^Host is changing map\.\.\.$", |_| Some(BanchoEvent::HostChangingMap)),
+        (
+            r"^Slot (?P<slot>\d+)\s+(?P<status>Not Ready|Ready|No Map)\s+https://osu\.ppy\.sh/u/(?P<uid>\d+)\s+(?P<name>.+?)(?:\s+\[(?P<tags>[^\]]*)\])?$",
+            |c| {
+                let status = match &c["status"] {
+                    "Ready" => SlotStatus::Ready,
+                    "Not Ready" => SlotStatus::NotReady,
+                    _ => SlotStatus::NoMap,
+                };
+                Some(BanchoEvent::Slot(SlotInfo {
+                    slot: num(c, "slot")?,
+                    status,
+                    user_id: num(c, "uid")?,
+                    name: text(c, "name"),
+                    tags: split_list(&text(c, "tags"), " / "),
+                }))
+            },
+        ),
+        (r"^(?P<name>.+) joined in slot (?P<slot>\d+)(?: for team (?P<team>\w+))?\.?$", |c| {
+            Some(BanchoEvent::PlayerJoined {
+                name: text(c, "name"),
+                slot: num(c, "slot")?,
+                team: c.name("team").map(|m| m.as_str().to_string()),
+            })
+        }),
+        (r"^(?P<name>.+) left the game\.?$", |c| Some(BanchoEvent::PlayerLeft { name: text(c, "name") })),
+        (r"^(?P<name>.+) moved to slot (?P<slot>\d+)\.?$", |c| {
+            Some(BanchoEvent::PlayerMoved { name: text(c, "name"), slot: num(c, "slot")? })
+        }),
+        (r"^(?P<name>.+) changed to (?P<team>Red|Blue)\.?$", |c| {
+            Some(BanchoEvent::TeamChanged { name: text(c, "name"), team: text(c, "team") })
+        }),
+        (r"^(?P<name>.+) became the host\.?$", |c| Some(BanchoEvent::HostChanged { name: text(c, "name") })),
+        (r"^Changed match host to (?P<name>.+)$", |c| Some(BanchoEvent::HostChanged { name: text(c, "name") })),
+        (r"^Cleared match host$", |_| Some(BanchoEvent::HostCleared)),
+        (r"^All players are ready$", |_| Some(BanchoEvent::AllPlayersReady)),
+        (r"^The match has started!?$", |_| Some(BanchoEvent::MatchStarted)),
+        (r"^The match has finished!?$", |_| Some(BanchoEvent::MatchFinished)),
+        (r"^Aborted the match$", |_| Some(BanchoEvent::MatchAborted)),
+        (r"^(?P<name>.+) finished playing \(Score: (?P<score>\d+), (?P<result>PASSED|FAILED)\)\.?$", |c| {
+            Some(BanchoEvent::PlayerFinished {
+                name: text(c, "name"),
+                score: num(c, "score")?,
+                passed: &c["result"] == "PASSED",
+            })
+        }),
+        (r"^Kicked (?P<name>.+) from the match\.?$", |c| Some(BanchoEvent::PlayerKicked { name: text(c, "name") })),
+        (r"^Changed the match password$", |_| Some(BanchoEvent::PasswordChanged)),
+        (r"^Removed the match password$", |_| Some(BanchoEvent::PasswordRemoved)),
+        (r"^(?P<text>(?:Enabled|Disabled) .+)$", |c| Some(parse_mods_change(&text(c, "text")))),
+        (r"^Queued the match to start in (?P<n>\d+) (?P<unit>seconds?|minutes?)$", |c| {
+            Some(BanchoEvent::StartTimerQueued { seconds: seconds(c)? })
+        }),
+        (r"^Match starts in (?P<n>\d+) (?P<unit>seconds?|minutes?)$", |c| {
+            Some(BanchoEvent::StartCountdown { seconds: seconds(c)? })
+        }),
+        (r"^Countdown aborted$", |_| Some(BanchoEvent::CountdownAborted)),
+        (r"^Closed the match$", |_| Some(BanchoEvent::MatchClosed)),
+    ];
+
+    rules
+        .into_iter()
+        .map(|(pattern, build)| (Regex::new(pattern).expect("invalid BanchoBot pattern"), build))
+        .collect()
+});
+
+/// 将 BanchoBot 的一行消息解析为 [`BanchoEvent`]，无法识别时返回 `None`
+pub fn parse(msg: &str) -> Option<BanchoEvent> {
+    let msg = msg.trim();
+    RULES
+        .iter()
+        .find_map(|(re, build)| re.captures(msg).and_then(|c| build(&c)))
+}
+
+fn text(c: &Captures, name: &str) -> String {
+    c.name(name).map(|m| m.as_str().trim().to_string()).unwrap_or_default()
+}
+
+fn num<T: std::str::FromStr>(c: &Captures, name: &str) -> Option<T> {
+    c.name(name)?.as_str().parse().ok()
+}
+
+fn seconds(c: &Captures) -> Option<u32> {
+    let n: u32 = num(c, "n")?;
+    Some(if c["unit"].starts_with("minute") { n * 60 } else { n })
+}
+
+// 逗号分隔的各项沿用前面最近的 Enabled/Disabled
+fn parse_mods_change(text: &str) -> BanchoEvent {
+    let mut enabled = Vec::new();
+    let mut disabled = Vec::new();
+    let mut enabling = true;
+    for item in split_list(text, ", ") {
+        let lower = item.to_lowercase();
+        let name = if lower.starts_with("enabled ") {
+            enabling = true;
+            &item["enabled ".len()..]
+        } else if lower.starts_with("disabled ") {
+            enabling = false;
+            &item["disabled ".len()..]
+        } else {
+            &item[..]
+        };
+        if enabling {
+            enabled.push(name.to_string());
+        } else {
+            disabled.push(name.to_string());
+        }
+    }
+    BanchoEvent::ModsChanged { enabled, disabled }
+}
+
+fn split_list(s: &str, sep: &str) -> Vec<String> {
+    s.split(sep)
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(v: &str) -> String {
+        v.to_string()
+    }
+
+    #[test]
+    fn test_parse_bancho_messages() {
+        let test_cases = vec![
+            (
+                "Created the tournament match https://osu.ppy.sh/mp/114514 ATRI高性能mp房",
+                Some(BanchoEvent::RoomCreated { room_id: 114514, name: s("ATRI高性能mp房") }),
+            ),
+            (
+                "Room name: ATRI高性能mp房, History: https://osu.ppy.sh/mp/114514",
+                Some(BanchoEvent::RoomName { name: s("ATRI高性能mp房"), room_id: 114514 }),
+            ),
+            (
+                "Team mode: HeadToHead, Win condition: ScoreV2",
+                Some(BanchoEvent::TeamMode { team_mode: s("HeadToHead"), win_condition: s("ScoreV2") }),
+            ),
+            ("Active mods: Freemod", Some(BanchoEvent::ActiveMods { mods: vec![s("Freemod")] })),
+            (
+                "Active mods: Hidden, DoubleTime",
+                Some(BanchoEvent::ActiveMods { mods: vec![s("Hidden"), s("DoubleTime")] }),
+            ),
+            ("Players: 3", Some(BanchoEvent::PlayerCount { count: 3 })),
+            (
+                "Beatmap: https://osu.ppy.sh/b/75 Kenji Ninuma - DISCO PRINCE [Normal]",
+                Some(BanchoEvent::CurrentBeatmap { beatmap_id: 75, title: s("Kenji Ninuma - DISCO PRINCE [Normal]") }),
+            ),
+            (
+                "Beatmap changed to: Kenji Ninuma - DISCO PRINCE [Normal] (https://osu.ppy.sh/b/75)",
+                Some(BanchoEvent::BeatmapChanged { beatmap_id: 75, title: s("Kenji Ninuma - DISCO PRINCE [Normal]") }),
+            ),
+            (
+                "Changed beatmap to https://osu.ppy.sh/b/75 Kenji Ninuma - DISCO PRINCE",
+                Some(BanchoEvent::BeatmapChanged { beatmap_id: 75, title: s("Kenji Ninuma - DISCO PRINCE") }),
+            ),
+            ("Host is changing map...", Some(BanchoEvent::HostChangingMap)),
+            (
+                "Slot 1  Not Ready https://osu.ppy.sh/u/2 peppy           [Host / Team Blue / Hidden, HardRock]",
+                Some(BanchoEvent::Slot(SlotInfo {
+                    slot: 1,
+                    status: SlotStatus::NotReady,
+                    user_id: 2,
+                    name: s("peppy"),
+                    tags: vec![s("Host"), s("Team Blue"), s("Hidden, HardRock")],
+                })),
+            ),
+            (
+                "Slot 2  Ready     https://osu.ppy.sh/u/3 Some Player     ",
+                Some(BanchoEvent::Slot(SlotInfo {
+                    slot: 2,
+                    status: SlotStatus::Ready,
+                    user_id: 3,
+                    name: s("Some Player"),
+                    tags: vec![],
+                })),
+            ),
+            (
+                "Slot 16 No Map    https://osu.ppy.sh/u/4 [Bracket]       [Host]",
+                Some(BanchoEvent::Slot(SlotInfo {
+                    slot: 16,
+                    status: SlotStatus::NoMap,
+                    user_id: 4,
+                    name: s("[Bracket]"),
+                    tags: vec![s("Host")],
+                })),
+            ),
+            (
+                "peppy joined in slot 3.",
+                Some(BanchoEvent::PlayerJoined { name: s("peppy"), slot: 3, team: None }),
+            ),
+            (
+                "Some Player joined in slot 1 for team blue.",
+                Some(BanchoEvent::PlayerJoined { name: s("Some Player"), slot: 1, team: Some(s("blue")) }),
+            ),
+            // 名字叫 Beatmap 的玩家不应被当成换图
+            (
+                "Beatmap joined in slot 2.",
+                Some(BanchoEvent::PlayerJoined { name: s("Beatmap"), slot: 2, team: None }),
+            ),
+            ("peppy left the game.", Some(BanchoEvent::PlayerLeft { name: s("peppy") })),
+            ("peppy moved to slot 5", Some(BanchoEvent::PlayerMoved { name: s("peppy"), slot: 5 })),
+            ("peppy changed to Red", Some(BanchoEvent::TeamChanged { name: s("peppy"), team: s("Red") })),
+            ("peppy became the host.", Some(BanchoEvent::HostChanged { name: s("peppy") })),
+            ("Changed match host to peppy", Some(BanchoEvent::HostChanged { name: s("peppy") })),
+            ("Cleared match host", Some(BanchoEvent::HostCleared)),
+            ("All players are ready", Some(BanchoEvent::AllPlayersReady)),
+            ("The match has started!", Some(BanchoEvent::MatchStarted)),
+            ("The match has finished!", Some(BanchoEvent::MatchFinished)),
+            ("Aborted the match", Some(BanchoEvent::MatchAborted)),
+            (
+                "peppy finished playing (Score: 1234567, PASSED).",
+                Some(BanchoEvent::PlayerFinished { name: s("peppy"), score: 1234567, passed: true }),
+            ),
+            (
+                "Some Player finished playing (Score: 0, FAILED).",
+                Some(BanchoEvent::PlayerFinished { name: s("Some Player"), score: 0, passed: false }),
+            ),
+            ("Kicked peppy from the match.", Some(BanchoEvent::PlayerKicked { name: s("peppy") })),
+            ("Changed the match password", Some(BanchoEvent::PasswordChanged)),
+            ("Removed the match password", Some(BanchoEvent::PasswordRemoved)),
+            (
+                "Enabled Hidden, HardRock, disabled FreeMod",
+                Some(BanchoEvent::ModsChanged { enabled: vec![s("Hidden"), s("HardRock")], disabled: vec![s("FreeMod")] }),
+            ),
+            (
+                "Disabled all mods, enabled FreeMod",
+                Some(BanchoEvent::ModsChanged { enabled: vec![s("FreeMod")], disabled: vec![s("all mods")] }),
+            ),
+            ("Queued the match to start in 30 seconds", Some(BanchoEvent::StartTimerQueued { seconds: 30 })),
+            ("Match starts in 1 minute", Some(BanchoEvent::StartCountdown { seconds: 60 })),
+            ("Match starts in 10 seconds", Some(BanchoEvent::StartCountdown { seconds: 10 })),
+            ("Countdown aborted", Some(BanchoEvent::CountdownAborted)),
+            ("Closed the match", Some(BanchoEvent::MatchClosed)),
+            ("Good luck, have fun!", None),
+            ("Beatmap changed to: something without a link", None),
+            ("", None),
+        ];
+
+        for (input, expected) in test_cases {
+            assert_eq!(parse(input), expected, "Failed on: {}", input);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::i18n::Lang;
+use crate::permission::normalize_name;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 一条封禁记录，时间均为 unix 秒
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ban {
+    pub player: String,
+    pub reason: String,
+    pub issuer: String,
+    pub created_at: i64,
+    /// 为空时永久封禁
+    pub expires_at: Option<i64>,
+}
+
+impl Ban {
+    pub fn format(&self, now: i64, lang: Lang) -> String {
+        let remaining = match self.expires_at {
+            Some(expires_at) => lang.t("ban.remaining", &[("duration", &format_duration(expires_at - now, lang))]),
+            None => lang.t("ban.permanent", &[]),
+        };
+        let reason = if self.reason.is_empty() { lang.t("ban.no_reason", &[]) } else { self.reason.clone() };
+        lang.t("ban.format", &[("player", &self.player), ("remaining", &remaining), ("reason", &reason), ("issuer", &self.issuer)])
+    }
+}
+
+pub fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// 解析 30m、2h、7d、1w 形式的时长，返回秒数
+pub fn parse_duration(text: &str) -> Option<i64> {
+    let split = text.len().checked_sub(1)?;
+    let (number, unit) = text.split_at(split);
+    let number: i64 = number.parse().ok().filter(|n| *n > 0)?;
+    let unit = match unit.to_ascii_lowercase().as_str() {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    number.checked_mul(unit)
+}
+
+fn format_duration(seconds: i64, lang: Lang) -> String {
+    let minutes = (seconds.max(0) + 59) / 60;
+    if minutes >= 24 * 60 {
+        lang.t("ban.days", &[("days", &(minutes / (24 * 60))), ("hours", &(minutes % (24 * 60) / 60))])
+    } else if minutes >= 60 {
+        lang.t("ban.hours", &[("hours", &(minutes / 60)), ("minutes", &(minutes % 60))])
+    } else {
+        lang.t("ban.minutes", &[("minutes", &minutes)])
+    }
+}
+
+/// 持久化的封禁列表，过期的临时封禁在查询时自动删除
+pub struct BanList {
+    conn: Connection,
+}
+
+impl BanList {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, rusqlite::Error> {
+        Self::init(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, rusqlite::Error> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, rusqlite::Error> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS bans (
+                player TEXT PRIMARY KEY COLLATE NOCASE,
+                reason TEXT NOT NULL,
+                issuer TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER
+            );",
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// 封禁玩家，已有的封禁会被覆盖。duration 为秒数，为空时永久封禁
+    pub fn ban(&self, player: &str, reason: &str, issuer: &str, duration: Option<i64>, now: i64) -> Result<Ban, rusqlite::Error> {
+        let ban = Ban {
+            player: normalize_name(player),
+            reason: reason.to_string(),
+            issuer: issuer.to_string(),
+            created_at: now,
+            expires_at: duration.map(|d| now + d),
+        };
+        self.conn.execute(
+            "INSERT OR REPLACE INTO bans (player, reason, issuer, created_at, expires_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![ban.player, ban.reason, ban.issuer, ban.created_at, ban.expires_at],
+        )?;
+        Ok(ban)
+    }
+
+    /// 解除封禁，返回玩家之前是否被封禁
+    pub fn lift(&self, player: &str) -> Result<bool, rusqlite::Error> {
+        Ok(self.conn.execute("DELETE FROM bans WHERE player = ?1", params![normalize_name(player)])? > 0)
+    }
+
+    /// 玩家当前生效的封禁
+    pub fn active_ban(&self, player: &str, now: i64) -> Result<Option<Ban>, rusqlite::Error> {
+        self.remove_expired(now)?;
+        self.conn.query_row(
+            "SELECT player, reason, issuer, created_at, expires_at FROM bans WHERE player = ?1",
+            params![normalize_name(player)],
+            Self::read_ban,
+        ).optional()
+    }
+
+    /// 所有生效的封禁，最新的在前
+    pub fn list(&self, now: i64) -> Result<Vec<Ban>, rusqlite::Error> {
+        self.remove_expired(now)?;
+        let mut stmt = self.conn.prepare(
+            "SELECT player, reason, issuer, created_at, expires_at FROM bans ORDER BY created_at DESC, player",
+        )?;
+        stmt.query_map([], Self::read_ban)?.collect()
+    }
+
+    fn remove_expired(&self, now: i64) -> Result<(), rusqlite::Error> {
+        self.conn.execute("DELETE FROM bans WHERE expires_at IS NOT NULL AND expires_at <= ?1", params![now])?;
+        Ok(())
+    }
+
+    fn read_ban(row: &rusqlite::Row) -> Result<Ban, rusqlite::Error> {
+        Ok(Ban {
+            player: row.get(0)?,
+            reason: row.get(1)?,
+            issuer: row.get(2)?,
+            created_at: row.get(3)?,
+            expires_at: row.get(4)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bans_expire_and_lift() {
+        let bans = BanList::open_in_memory().unwrap();
+        bans.ban("Bad Player", "spam", "Alice", None, 1000).unwrap();
+        bans.ban("Late", "", "Alice", parse_duration("2h"), 1000).unwrap();
+
+        assert!(bans.active_ban("bad_player", 1000).unwrap().is_some());
+        let ban = bans.active_ban("Late", 1000 + 3600).unwrap().unwrap();
+        assert_eq!(ban.format(1000 + 3600, Lang::ZhCn), "Late (剩余 1小时0分钟, 原因: 无, 由 Alice 封禁)");
+        assert_eq!(ban.format(1000 + 3600, Lang::En), "Late (1h 0m left, reason: none, banned by Alice)");
+        assert_eq!(bans.list(1000).unwrap().len(), 2);
+
+        // 临时封禁到期后自动删除
+        assert_eq!(bans.active_ban("Late", 1000 + 7200).unwrap(), None);
+        assert_eq!(bans.list(1000).unwrap().len(), 1);
+
+        assert!(bans.lift("BAD_PLAYER").unwrap());
+        assert!(!bans.lift("BAD_PLAYER").unwrap());
+        assert!(bans.list(1000).unwrap().is_empty());
+
+        assert_eq!(parse_duration("30m"), Some(1800));
+        assert_eq!(parse_duration("1w"), Some(604800));
+        assert_eq!(parse_duration("spam"), None);
+        assert_eq!(parse_duration("0d"), None);
+    }
+}
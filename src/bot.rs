@@ -1,172 +1,298 @@
-use crate::{pp_calculator, BotSettings};
+use crate::BotSettings;
+use crate::config::{BotConfig, PathsConfig, ReloadSignal};
 use crate::osu_api::{self, User};
 
-use crate::charts;
-
 use irc::client::prelude::*;
 
 use std::error::Error;
-use futures::stream::StreamExt;
-use std::time::{Instant, Duration};
-use crate::commands::handle_command;
-
-use self::pp_calculator::PPCalculator;
-use self::osu_api::OsuApi;
-use std::sync::Arc;
-use tokio::sync::Mutex as TokioMutex;
-use crate::events::handle_event;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::time;
+use crate::commands::{self, handle_bot_pick, handle_command};
+use crate::cooldown::CommandLimiter;
+use crate::outbox::Outbox;
+use crate::transport::{MessageSender, Transport};
+
+use self::osu_api::OsuApiClient;
+use crate::events::{self, handle_event, handle_private_event, handle_results_ready};
+use futures::future::LocalBoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
 
 use std::collections::HashMap;
 
 use std::fs::File;
 use std::io::{Write, Read};
 
-use serde::{Serialize, Deserialize};
+use crate::bans::BanList;
+use crate::i18n::Lang;
 use crate::charts::ChartDatabase;
-
-#[derive(Serialize, Deserialize)]
-struct BotState {
-    beatmap_name: String,
-    beatmap_artist: String,
-    beatmap_star: f32,
-    player_list: Vec<String>,
-}
+use crate::stats::{MatchRecord, StatsDatabase};
+use crate::permission::Permission;
+use crate::pp_calculator;
+use crate::room::{Room, RoomRegistry};
+
+// 断线重连的退避时间
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(300);
+// 连接保持超过这个时间才认为是稳定的，重置退避时间
+const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+// 房间计时器 (自动开始等) 的检查间隔
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct MyBot {
-    client: Client,
+    transport: Box<dyn Transport>,
+    paths: PathsConfig,
+    config_file: Option<PathBuf>,
+    reload_signal: ReloadSignal,
+    pub admins: Vec<String>,
+    pub operators: Vec<String>,
+    /// 管理员使用了 !shutdown，处理完当前消息后退出
+    shutdown_requested: bool,
     pub chart_db :ChartDatabase,
+    pub stats_db: StatsDatabase,
+    pub ban_list: BanList,
     pub bot_name: String,
-    pub player_list: Vec<String>,
-    pub room_host_list: Vec<String>,
-    pub beatmap_start_time: Option<Instant>,
-    pub beatmap_end_time: Option<Instant>,
-    pub approved_abort_list: Vec<String>,
-    pub approved_start_list: Vec<String>,
-    pub approved_skip_list: Vec<String>,
-    pub approved_close_list: Vec<String>,
-    pub room_host: String,
-    pub room_id: Arc<TokioMutex<u32>>,
-    pub room_name: String,
-    pub room_password: String,
-    pub beatmap_id: u32,
-    pub beatmap_length: u64,
-    pub beatmap_path: String,
-    pub pp_calculator: PPCalculator,
-    pub osu_api: OsuApi,
+    pub osu_api: Box<dyn OsuApiClient>,
     pub player_info: HashMap<String, User>,
-    pub beatmap_title_unicode: String,
-    pub beatmap_artist_unicode: String,
-    pub beatmap_difficulty_rating: f32,
-    pub beatmap_info: String,
-    pub beatmap_pp_info: String,
-    pub is_channel_exist: bool,
-    pub is_game_started: bool,
+    pub rooms: RoomRegistry,
+    /// 所有房间共用的限速发送队列
+    pub outbox: MessageSender,
+    /// 玩家指令的冷却和防刷屏
+    pub limiter: CommandLimiter,
+    /// 对局结束后在后台查询 pp 的任务，完成时返回房间频道和对局记录
+    pending_results: FuturesUnordered<LocalBoxFuture<'static, (String, MatchRecord)>>,
 }
 
 impl MyBot {
-    pub async fn new(config: Config, client_id: String, client_secret: String,bot_settings: BotSettings) -> Result<Self, Box<dyn Error>> {
-        let nickname = config.nickname.clone();
-        let client = Client::from_config(config).await?;
-        
+    /// 只准备房间和数据库，连接在 run 中建立
+    pub async fn new(bot_name: String, transport: Box<dyn Transport>, osu_api: Box<dyn OsuApiClient>, bot_settings: BotSettings) -> Result<Self, Box<dyn Error>> {
+        let paths = bot_settings.paths;
+
+        let outbox = MessageSender::new(Outbox::new(bot_settings.outbox, time::Instant::now()));
+
         // 尝试读取上次保存的房间ID
-        let last_room_id = Self::read_last_room_id().unwrap_or(0);
-        
+        let last_room_ids = Self::read_last_room_ids(&paths).unwrap_or_default();
+
+        let rooms = bot_settings.rooms.into_iter()
+            .enumerate()
+            .map(|(index, settings)| {
+                let room_id = last_room_ids.get(index).copied().unwrap_or(0);
+                let mut room = Room::new(outbox.clone(), index, room_id, settings, &paths.data_dir);
+                if room_id != 0 && let Err(e) = room.load_state_from_file() {
+                    println!("Failed to restore state of #mp_{}: {}", room_id, e);
+                }
+                room
+            })
+            .collect();
+
         let bot = MyBot {
-            client,
-            chart_db: ChartDatabase::open("charts.sqlite").unwrap(),
-            bot_name: nickname.unwrap(),
-            player_list: Vec::new(),
-            room_host_list: Vec::new(),
-            beatmap_start_time: None,
-            beatmap_end_time: None,
-            approved_abort_list: Vec::new(),
-            approved_start_list: Vec::new(),
-            approved_skip_list: Vec::new(),
-            approved_close_list: Vec::new(),
-            room_host: String::new(),
-            room_id: Arc::new(TokioMutex::new(last_room_id)),
-            room_name: bot_settings.room_name,
-            room_password: bot_settings.room_password,
-            beatmap_id: 0,
-            is_channel_exist: false,
-            is_game_started:false,
-            beatmap_length: 0,
-            beatmap_path: String::new(),
-            pp_calculator: PPCalculator::new(String::new()),
-            osu_api: OsuApi::new(client_id, client_secret),
+            transport,
+            chart_db: ChartDatabase::open(paths.resolve(&paths.charts_db))?,
+            stats_db: StatsDatabase::open(paths.resolve(&paths.stats_db))?,
+            ban_list: BanList::open(paths.resolve(&paths.bans_db))?,
+            paths,
+            config_file: bot_settings.config_file,
+            reload_signal: ReloadSignal::new(),
+            admins: bot_settings.admins,
+            operators: bot_settings.operators,
+            shutdown_requested: false,
+            bot_name,
+            osu_api,
             player_info: HashMap::new(),
-            beatmap_title_unicode: String::new(),
-            beatmap_artist_unicode: String::new(),
-            beatmap_difficulty_rating: 0.0,
-            beatmap_info: String::new(),
-            beatmap_pp_info: String::new(),
+            rooms: RoomRegistry::new(rooms),
+            outbox,
+            limiter: CommandLimiter::new(commands::USER_BURST, commands::GLOBAL_BURST),
+            pending_results: FuturesUnordered::new(),
         };
 
         Ok(bot)
     }
 
-    pub fn read_last_room_id() -> Result<u32, Box<dyn Error>> {
-        let mut file = File::open("last_room_id.txt")?;
+    /// last_room_id.txt 每行一个房间ID，按配置中房间的顺序排列
+    pub fn read_last_room_ids(paths: &PathsConfig) -> Result<Vec<u32>, Box<dyn Error>> {
+        let mut file = File::open(paths.resolve(&paths.room_ids_file))?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
-        Ok(contents.trim().parse()?)
-    }
-
-    pub async fn join_last_room(&self) -> Result<(), Box<dyn Error>> {
-        let room_id = *self.room_id.lock().await;
-        self.join_channel(&format!("#mp_{}", room_id)).await?;
-        println!("Joined last room: #mp_{}", room_id);
-        Ok(())
+        contents.lines()
+            .map(|line| Ok(line.trim().parse()?))
+            .collect()
     }
 
+    /// 保持与 irc 服务器的连接，连接失败或断线后按指数退避重连，直到所有房间都已关闭或管理员关闭 bot
     pub async fn run(&mut self) -> Result<(), Box<dyn Error>> {
-        
+        let mut delay = RECONNECT_INITIAL_DELAY;
+        self.connect(&mut delay, false).await;
+        loop {
+            let connected_at = Instant::now();
+            match self.run_connection().await {
+                Ok(_) => println!("IRC connection closed"),
+                Err(e) => println!("IRC connection error: {:?}", e),
+            }
+            if self.shutdown_requested {
+                // 房间保持打开，下次启动时通过 last_room_id.txt 重新加入
+                self.save_room_ids_to_file()?;
+                println!("Shutdown requested by admin");
+                return Ok(());
+            }
+            if self.rooms.is_empty() {
+                println!("All rooms closed");
+                return Ok(());
+            }
+            if connected_at.elapsed() >= STABLE_CONNECTION {
+                delay = RECONNECT_INITIAL_DELAY;
+            }
+            // 排队中的消息会在重连后发出
+            self.connect(&mut delay, true).await;
+        }
+    }
 
-        self.client.identify()?;
+    /// 建立连接，失败时等待 delay 后重试，每次失败等待时间加倍。wait 为 true 时第一次连接前也先等待
+    async fn connect(&mut self, delay: &mut Duration, mut wait: bool) {
+        loop {
+            if wait {
+                println!("Reconnecting in {}s", delay.as_secs());
+                tokio::time::sleep(*delay).await;
+                *delay = (*delay * 2).min(RECONNECT_MAX_DELAY);
+            }
+            wait = true;
+            match self.transport.connect().await {
+                Ok(_) => return,
+                Err(e) => println!("Connect failed: {:?}", e),
+            }
+        }
+    }
 
-        let mut stream = self.client.stream()?;
+    async fn run_connection(&mut self) -> Result<(), Box<dyn Error>> {
+        self.transport.identify()?;
+        self.join_rooms().await?;
+        self.process_messages().await
+    }
 
-        let room_id = *self.room_id.lock().await;
-        if room_id == 0 {
+    /// 创建还没有房间ID的房间，重新加入已有的房间
+    pub async fn join_rooms(&mut self) -> Result<(), Box<dyn Error>> {
+        for room in self.rooms.pending_mut() {
             // 如果没有上次的房间ID,创建新房间
-            self.create_room().await?;
-        } else {
-            println!("Using existing room: #mp_{}", room_id);
-            // 尝试加入上次的房间
-            self.join_last_room().await?;
-            self.send_message(&format!("#mp_{}", *self.room_id.lock().await), "!mp settings").await?;
+            room.create_room().await?;
+        }
+        for room in self.rooms.rooms_mut() {
+            println!("Using existing room: {}", room.channel());
+            // 尝试加入上次的房间，并通过 !mp settings 重建玩家列表
+            room.rejoin().await?;
         }
+        Ok(())
+    }
 
-        while let Some(message) = stream.next().await.transpose()? {
+    /// 处理收到的消息，直到连接关闭或所有房间都已关闭
+    pub async fn process_messages(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut ticker = time::interval(TICK_INTERVAL);
+        let mut next_send = self.flush_outbox();
+        loop {
+            let message = tokio::select! {
+                // 有令牌时先发出排队的消息，再处理收到的消息，空闲时再处理计时
+                biased;
+                _ = time::sleep_until(next_send.unwrap_or_else(time::Instant::now)), if next_send.is_some() => {
+                    next_send = self.flush_outbox();
+                    continue;
+                }
+                Some((channel, record)) = self.pending_results.next(), if !self.pending_results.is_empty() => {
+                    if let Err(e) = handle_results_ready(self, &channel, record).await {
+                        println!("Failed to send results of {}: {:?}", channel, e);
+                    }
+                    next_send = self.flush_outbox();
+                    continue;
+                }
+                message = self.transport.next_message() => message?,
+                _ = ticker.tick() => {
+                    self.tick(time::Instant::now()).await;
+                    next_send = self.flush_outbox();
+                    continue;
+                }
+                _ = self.reload_signal.recv() => {
+                    println!("SIGHUP received, reloading config");
+                    if let Err(e) = self.reload_config(None).await {
+                        println!("Failed to reload config: {}", e);
+                    }
+                    continue;
+                }
+            };
+            let Some(message) = message else {
+                break;
+            };
             match self.handle_message(message).await {
                 Ok(_) => {},
                 Err(e) => {
                     println!("Error handling message: {:?}", e);
                 }
             }
+            next_send = self.flush_outbox();
+            if self.rooms.is_empty() || self.shutdown_requested {
+                break;
+            }
         }
 
-
         Ok(())
     }
 
+    /// 在后台查询本局成绩的 pp，完成后记录对局并把结果发到 channel
+    pub fn fetch_result_pp(&mut self, channel: String, record: MatchRecord, beatmap_path: String) {
+        let osu_api = self.osu_api.background_client();
+        self.pending_results.push(Box::pin(async move {
+            (channel, events::fetch_result_pp(osu_api, beatmap_path, record).await)
+        }));
+    }
+
+    /// 等待所有后台查询完成并发出结果，没有后台任务时返回 false。模拟器没有新消息时使用
+    #[cfg(test)]
+    pub async fn finish_pending_results(&mut self) -> bool {
+        if self.pending_results.is_empty() {
+            return false;
+        }
+        while let Some((channel, record)) = self.pending_results.next().await {
+            if let Err(e) = handle_results_ready(self, &channel, record).await {
+                println!("Failed to send results of {}: {:?}", channel, e);
+            }
+        }
+        true
+    }
+
+    /// 按限速发出排队的消息，返回下一条消息可以发送的时间
+    pub fn flush_outbox(&mut self) -> Option<time::Instant> {
+        match self.outbox.flush(self.transport.sink().as_ref(), time::Instant::now()) {
+            Ok(next_send) => next_send,
+            Err(e) => {
+                println!("Failed to send message: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// 处理各房间的计时器
+    pub async fn tick(&mut self, now: time::Instant) {
+        for room in self.rooms.rooms_mut() {
+            if let Err(e) = room.tick(now).await {
+                println!("Error in timer of {}: {:?}", room.channel(), e);
+            }
+        }
+    }
+
     async fn handle_message(&mut self, message: Message) -> Result<(), Box<dyn Error>> {
         match &message.command {
             Command::PRIVMSG(target, msg) => {
                 let sender = self.get_nickname(&message.prefix).unwrap_or("unknown".to_string());
                 println!("收到消息: {} <- {} from {}", target, msg,sender);
-                if msg.contains("Match settings") {
-                    self.is_channel_exist = true;
-                }
-                if msg.starts_with("help"){
-                    self.send_menu().await?;
+                // 按消息目标找到对应房间，找不到说明是私聊
+                let Some(mut room) = self.rooms.take(target) else {
+                    return handle_private_event(self, &sender, msg).await;
+                };
+                let mut result = self.handle_room_message(&mut room, &sender, target, msg, &message.prefix).await;
+                if result.is_ok() && room.bot_pick_requested {
+                    result = handle_bot_pick(self, &mut room).await;
                 }
-                if msg.starts_with("!") || msg.starts_with("！") {
-                    let prefix = self.get_nickname(&message.prefix);
-                    handle_command(self, &sender,target, msg, prefix).await?;
-                } else {
-                    handle_event(self, &sender, msg).await?;
+                if let Err(e) = room.save_latest_info_to_file() {
+                    println!("Failed to save state of {}: {}", room.channel(), e);
                 }
+                self.rooms.insert(room);
+                result?;
             }
             Command::JOIN(channel, _, _) => {
                 if let Some(nick) = self.get_nickname(&message.prefix) {
@@ -176,22 +302,27 @@ impl MyBot {
             Command::PART(channel, _) => {
                 if let Some(nick) = self.get_nickname(&message.prefix) {
                     println!("{} left {}", nick, channel);
-                    if nick == self.bot_name {
+                    if nick == self.bot_name && let Some(mut room) = self.rooms.take(channel) {
                         println!("Bot was kicked from the channel");
-                        // 清空队列
-                        self.player_list.clear();
-                        self.save_latest_info_to_file().expect("无法写入bot state");
-                        // 退出终止进程
-                        !panic!("End.")
+                        // 清空队列，房间不再由本bot管理
+                        room.player_list.clear();
+                        if let Err(e) = room.save_latest_info_to_file() {
+                            println!("Failed to save state of {}: {}", room.channel(), e);
+                        }
+                        self.save_room_ids_to_file()?;
                     }
                 }
             }
-            
+
             Command::Response(Response::ERR_NOSUCHCHANNEL,args) => {
-                self.is_channel_exist = false;          
-                println!("{:?},{:?}",Response::ERR_NOSUCHCHANNEL,args);       
-                println!("Not found channel, Recreate");
-                self.create_room().await?;
+                println!("{:?},{:?}",Response::ERR_NOSUCHCHANNEL,args);
+                if let Some(mut room) = args.get(1).and_then(|channel| self.rooms.take(channel)) {
+                    println!("Not found channel, Recreate");
+                    room.is_channel_exist = false;
+                    room.room_id = 0;
+                    room.create_room().await?;
+                    self.rooms.add_pending(room);
+                }
             }
 
             _ => {}
@@ -199,30 +330,29 @@ impl MyBot {
         Ok(())
     }
 
-    pub async fn send_message(&self, target: &str, message: &str) -> Result<(), irc::error::Error> {
-        self.client.send_privmsg(target, message)?;
-        println!("发送消息: {} -> {}", target, message);
+    async fn handle_room_message(&mut self, room: &mut Room, sender: &str, target: &str, msg: &str, prefix: &Option<Prefix>) -> Result<(), Box<dyn Error>> {
+        if msg.contains("Match settings") {
+            room.is_channel_exist = true;
+        }
+        if msg.starts_with("help"){
+            handle_command(self, room, target, "!help", self.get_nickname(prefix)).await?;
+        }
+        if msg.starts_with("!") || msg.starts_with("！") {
+            let prefix = self.get_nickname(prefix);
+            handle_command(self, room, target, msg, prefix).await?;
+        } else {
+            handle_event(self, room, sender, msg).await?;
+        }
         Ok(())
     }
 
-    pub async fn join_channel(&self, channel: &str) -> Result<(), irc::error::Error> {
-        self.client.send_join(channel)
-    }
-
-    pub async fn rotate_host(&mut self) -> Result<(), Box<dyn Error>> {
-        //轮换房主前，删除不在player_list中的玩家
-        self.remove_player_not_in_list();
-        if !self.room_host_list.is_empty() {
-            let old_host = self.room_host_list.remove(0);
-            self.room_host_list.push(old_host);
-            let new_host = self.room_host_list[0].clone();
-            self.set_host(&new_host).await?;
-            println!("Rotated host to: {}", new_host);
-        }
+    /// 私聊消息直接发送给目标，房间消息请使用 [`Room::send_message`]
+    pub async fn send_message(&self, target: &str, message: &str) -> Result<(), Box<dyn Error>> {
+        self.outbox.send_privmsg(target, message);
+        println!("发送消息: {} -> {}", target, message);
         Ok(())
     }
 
-
     fn get_nickname(&self, prefix: &Option<Prefix>) -> Option<String> {
         prefix.as_ref().and_then(|p| {
             if let Prefix::Nickname(nick, _, _) = p {
@@ -233,250 +363,87 @@ impl MyBot {
         })
     }
 
-    pub fn calculate_pp(&self, beatmap_id: u32, mods: u32, combo: u32, accuracy: f64) -> Result<(f64, f64, f64), Box<dyn Error>> {
-        self.pp_calculator.calculate_pp(beatmap_id, mods, combo, accuracy, 0)
-    }
-
-    pub fn add_player(&mut self, name: String) {
-        if !self.player_list.contains(&name) {
-            self.player_list.push(name.clone());
-        }
-        if !self.room_host_list.contains(&name) {
-            self.room_host_list.push(name);
-        }
-    }
-
-    pub fn remove_player(&mut self, name: &str) {
-        self.player_list.retain(|n| n != name);
-    }
-
-    pub fn remove_player_not_in_list(&mut self) {
-        // 取player_list和room_host_list的交集，更新room_host_list
-        self.room_host_list = self.room_host_list.iter()
-            .filter(|player| self.player_list.contains(player))
-            .cloned()
-            .collect();
-    }
-
-    pub async fn create_room(&mut self) -> Result<(), Box<dyn Error>> {
-        self.send_message("BanchoBot", &format!("!mp make {}", self.room_name)).await?;
-        println!("Sent room creation request to BanchoBot");
-        Ok(())
-    }
-
-    pub async fn get_mp_settings(&mut self) -> Result<(), Box<dyn Error>> {
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await), "!mp settings").await?;
-        Ok(())
-    }
-
-    pub async fn set_room_password(&mut self, password: String) -> Result<(), Box<dyn Error>> {
-        let room_id = *self.room_id.lock().await;
-        self.send_message(&format!("#mp_{}", room_id), &format!("!mp password {}", password)).await?;
-        Ok(())
+    /// 玩家的 osu! 账号，第一次使用时通过 api 查询，查询失败时不缓存
+    pub async fn get_user_mut(&mut self, irc_name: &str) -> Result<&mut User, Box<dyn Error>> {
+        let user = match self.player_info.remove(irc_name) {
+            Some(user) => user,
+            None => {
+                let mut user = User::new(irc_name.to_string(), 0, "".to_string());
+                user.update(self.osu_api.as_mut()).await?;
+                user
+            }
+        };
+        Ok(self.player_info.entry(irc_name.to_string()).or_insert(user))
     }
 
-    pub async fn calculate_total_time_left(&self) -> Result<(String), Box<dyn Error>> {
-        let now = Instant::now();
-        let elapsed = now.duration_since(self.beatmap_start_time.unwrap_or(now));
-        if elapsed == Duration::from_secs(0) {
-            let msg_not_started = "游戏尚未开始".to_string();
-            self.send_message(&format!("#mp_{}", *self.room_id.lock().await), &msg_not_started).await?;
-            Ok(msg_not_started)
-        }
-        else {
-            let total_time_left = self.beatmap_length - elapsed.as_secs();
-            let msg_started = format!("剩余游玩时间: {}s", total_time_left);
-            self.send_message(&format!("#mp_{}", *self.room_id.lock().await),&msg_started).await?;
-            Ok(msg_started)
-        }
+    /// 谱面 .osu 文件的保存位置，在 paths.maps_dir 中
+    pub fn beatmap_path(&self, beatmap_id: u32) -> String {
+        pp_calculator::beatmap_path(&self.paths.resolve(&self.paths.maps_dir), beatmap_id)
     }
 
-    pub async fn send_welcome(&mut self, player_name: String) -> Result<(), Box<dyn Error>> {
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await),&format!("欢迎{}酱~＼(≧▽≦)／ 输入help获取指令详情", player_name)).await?;
-
-        if self.is_game_started{
-            let remain_time_text = self.calculate_total_time_left().await?;
-            self.send_message(&format!("#mp_{}", *self.room_id.lock().await),&remain_time_text).await?;
+    pub fn save_room_ids_to_file(&self) -> Result<(), Box<dyn Error>> {
+        let room_ids = self.rooms.room_ids();
+        let mut file = File::create(self.paths.resolve(&self.paths.room_ids_file))?;
+        for room_id in &room_ids {
+            writeln!(file, "{}", room_id)?;
         }
-
-        Ok(())
-    }
-
-    pub async fn send_menu(&mut self) -> Result<(), Box<dyn Error>> {
-        let help_text = "!queue(!q) 查看队列 | !abort 投票丢弃游戏 | !start 投票开始游戏 | !skip 投票跳过房主 | !pr(!p) 查询最近pass成绩 | !re(!r) 查询最近成绩 | !s 查询当前谱面最好成绩| !info(!i) 返回当前谱面信息| !pick 挑选一张赛图| !ttl 查询剩余时间 | help(!h) 查看帮助 | !about 关于机器人";
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await),help_text).await?;
-        Ok(())
-    }
-
-    pub async fn send_about(&mut self) -> Result<(), Box<dyn Error>> {
-        let about_text = "https://github.com/Ohdmire/osu-ircbot-rust ATRI高性能bot with Rust";
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await),about_text).await?;
-        Ok(())
-    }
-
-    pub async fn set_host(&mut self, player_name: &str) -> Result<(), Box<dyn Error>> {
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await), &format!("!mp host {}", player_name)).await?;
-        self.room_host = player_name.to_string();
-        Ok(())
-    }
-
-    pub async fn set_free_mod(&mut self) -> Result<(), Box<dyn Error>> {
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await), "!mp mods FreeMod").await?;
-        Ok(())
-    }
-
-    pub async fn set_map(&mut self,map_id: i32) -> Result<(), Box<dyn Error>> {
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await), &format!("!mp map {}", map_id.to_string())).await?;
-        Ok(())
-    }
-
-    pub async fn start_game(&mut self) -> Result<(), Box<dyn Error>> {
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await), "!mp start").await?;
-        Ok(())
-    }
-
-    pub async fn abort_game(&mut self) -> Result<(), Box<dyn Error>> {
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await), "!mp abort").await?;
+        println!("Room IDs {:?} saved to {}", room_ids, self.paths.room_ids_file.display());
         Ok(())
     }
 
-    pub async fn close_room(&mut self) -> Result<(), Box<dyn Error>> {
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await), "!mp close").await?;
-        Ok(())
-    }
-    
-    pub async fn cleanup_after_match(&mut self) -> Result<(), Box<dyn Error>> {
-        self.approved_abort_list.clear();
-        self.approved_skip_list.clear();
-        self.approved_start_list.clear();
-        self.approved_close_list.clear();
-        Ok(())
+    /// room_host 为玩家所在房间的房主，私聊时为 None
+    pub fn permission(&self, irc_name: &str, room_host: Option<&str>) -> Permission {
+        Permission::of(irc_name, room_host, &self.operators, &self.admins)
     }
 
-    pub async fn send_queue(&mut self) -> Result<(), Box<dyn Error>> {
-        let queue = self.room_host_list.iter()
-            .map(|name| {
-                name.chars()
-                .map(|c| format!("{c}\u{200B}"))
-                .collect::<String>()
-                .trim_end_matches('\u{200B}')
-                .to_owned()
-            })
-                .collect::<Vec<_>>()
-                .join("->");
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await), &queue).await?;
-        Ok(())
-    }
-
-    pub async fn get_user_mut(&mut self, irc_name: &str) -> Option<&mut User> {
-        if !self.player_info.contains_key(irc_name) {
-            let mut user = User::new(irc_name.to_string(), 0, "".to_string());
-            user.update(&mut self.osu_api).await.unwrap();
-            self.player_info.insert(irc_name.to_string(), user);
+    /// 回复使用的语言：发到频道时用房间语言，私聊时优先用玩家 !lang 设置的语言
+    pub fn reply_lang(&self, target: &str, default: Lang) -> Lang {
+        if target.starts_with('#') {
+            return default;
         }
-        self.player_info.get_mut(irc_name)
-    }
-
-    pub async fn send_beatmap_info(&mut self) -> Result<(), Box<dyn Error>> {
-        self.send_message(&format!("#mp_{}", *self.room_id.lock().await), &self.beatmap_info).await?;
-        self.save_latest_info_to_file().expect("无法写入bot state");
-        Ok(())
-    }
-    
-    pub async fn vote_abort(&mut self, irc_name: &str) -> Result<(), Box<dyn Error>> {
-        // 判断irc_name是否在player_list中
-        if self.player_list.contains(&irc_name.to_string()) {
-            // 如果不在approved_abort_list中，则添加到approved_abort_list中
-            if !self.approved_abort_list.contains(&irc_name.to_string()) {
-                self.approved_abort_list.push(irc_name.to_string());
-            }
-
-            // 判断列表是否满足人数的一半 或者是房主本人
-            if self.approved_abort_list.len() >= (self.player_list.len() / 2) || irc_name == self.room_host.replace(" ", "_") {
-                self.abort_game().await?;
-                self.approved_abort_list.clear();
-            }
-            else {
-                self.send_message(&format!("#mp_{}", *self.room_id.lock().await), &format!("{} / {} in the abort process", self.approved_abort_list.len(), (self.player_list.len() as f64 / 2.0).ceil() as usize)).await?;
+        match self.stats_db.player_lang(target) {
+            Ok(lang) => lang.unwrap_or(default),
+            Err(e) => {
+                println!("Failed to read language of {}: {}", target, e);
+                default
             }
         }
-        Ok(())
-            
-        
     }
 
-    pub async fn vote_skip(&mut self, irc_name: &str) -> Result<(), Box<dyn Error>> {
-        // 判断irc_name是否在player_list中
-        if self.player_list.contains(&irc_name.to_string()) {
-            // 如果不在approved_skip_list中，则添加到approved_skip_list中
-            if !self.approved_skip_list.contains(&irc_name.to_string()) {
-                self.approved_skip_list.push(irc_name.to_string());
-            }
-        // 判断列表是否满足人数的一半 或者是房主本人
-        if self.approved_skip_list.len() >= (self.player_list.len() / 2) || irc_name == self.room_host.replace(" ", "_") {
-            self.rotate_host().await?;
-            self.approved_skip_list.clear();
-        }
-        else {
-            self.send_message(&format!("#mp_{}", *self.room_id.lock().await), &format!("{} / {} in the skip process", self.approved_skip_list.len(), (self.player_list.len() as f64 / 2.0).ceil() as usize)).await?;
-        }
+    pub fn request_shutdown(&mut self) {
+        self.shutdown_requested = true;
     }
-        Ok(())
+
+    #[cfg(test)]
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.shutdown_requested
     }
-    pub async fn vote_close(&mut self, irc_name: &str) -> Result<(), Box<dyn Error>> {
-        // 判断irc_name是否在player_list中
-        if self.player_list.contains(&irc_name.to_string()) {
-            // 如果不在approved_close_list中，则添加到approved_close_list中
-            if !self.approved_close_list.contains(&irc_name.to_string()) {
-                self.approved_close_list.push(irc_name.to_string());
-            }
-        }
-        // 判断列表是否满足人数的一半
-        if self.approved_close_list.len() >= (self.player_list.len() / 2) {
-            self.close_room().await?;
-            self.approved_close_list.clear();
-        }
-        else {
-            self.send_message(&format!("#mp_{}", *self.room_id.lock().await), &format!("{} / {} in the close process", self.approved_close_list.len(), (self.player_list.len() as f64 / 2.0).ceil() as usize)).await?;
+
+    /// 重新读取配置文件并应用到各房间，不会断开连接或重建房间。
+    /// 正在处理消息的房间已从注册表取出，需要通过 current 传入
+    pub async fn reload_config(&mut self, current: Option<&mut Room>) -> Result<(), Box<dyn Error>> {
+        let Some(config_file) = self.config_file.clone() else {
+            return Err("没有使用配置文件启动，无法重新加载".into());
+        };
+        let settings = BotConfig::load(Some(&config_file))?.bot_settings(Some(config_file));
+        if settings.rooms.len() != self.rooms.room_ids().len() {
+            println!("Room count changed in config, restart to create or remove rooms");
         }
-        Ok(())
-    }
-    pub async fn vote_start(&mut self, irc_name: &str) -> Result<(), Box<dyn Error>> {
-        // 判断irc_name是否在player_list中
-        if self.player_list.contains(&irc_name.to_string()) {
-            // 如果不在approved_start_list中，则添加到approved_start_list中
-            if !self.approved_start_list.contains(&irc_name.to_string()) {
-                self.approved_start_list.push(irc_name.to_string());
+        // 某个房间出错时仍然应用其余房间和全局设置，最后一起报告没有生效的房间
+        let mut errors = Vec::new();
+        for room in self.rooms.all_mut().chain(current) {
+            if let Some(room_settings) = settings.rooms.get(room.index)
+                && let Err(e) = room.apply_settings(room_settings.clone()).await {
+                errors.push(format!("{}: {}", room.channel(), e));
             }
         }
-        // 判断列表是否满足人数的一半
-        if self.approved_start_list.len() >= (self.player_list.len() / 2) {
-            self.start_game().await?;
-            self.approved_start_list.clear();
-        }
-        else {
-            self.send_message(&format!("#mp_{}", *self.room_id.lock().await), &format!("{} / {} in the start process", self.approved_start_list.len(), (self.player_list.len() as f64 / 2.0).ceil() as usize)).await?;
+        self.admins = settings.admins;
+        self.operators = settings.operators;
+        self.outbox.set_config(settings.outbox);
+        if !errors.is_empty() {
+            return Err(format!("以下房间的配置没有完全生效: {}", errors.join("; ")).into());
         }
-        Ok(())
-    }
-
-    pub async fn save_room_id_to_file(&self) -> Result<(), Box<dyn Error>> {
-        let room_id = *self.room_id.lock().await;
-        let mut file = File::create("last_room_id.txt")?;
-        write!(file, "{}", room_id)?;
-        println!("Room ID {} saved to last_room_id.txt", room_id);
-        Ok(())
-    }
-
-    pub fn save_latest_info_to_file(&self) -> Result<(), Box<dyn Error>> {
-        let state = BotState{
-            beatmap_name: self.beatmap_title_unicode.clone(),
-            beatmap_artist: self.beatmap_artist_unicode.clone(),
-            beatmap_star: self.beatmap_difficulty_rating,
-            player_list: self.player_list.clone()
-        };
-        let mut file = File::create("bot_state.json")?;
-        serde_json::to_writer_pretty(&file, &state)?;
+        println!("Config reloaded from {}", self.config_file.as_ref().map(|p| p.display().to_string()).unwrap_or_default());
         Ok(())
     }
 }
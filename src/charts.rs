@@ -6,7 +6,7 @@ use serde::{Serialize, Deserialize};
 use std::path::Path;
 
 #[derive(Debug)]
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct ChartQuery {
     pub competition: Option<String>,
     pub season: Option<String>,
@@ -49,21 +49,20 @@ impl ChartQuery {
         });
 
         // 处理只有"NM1"这种形式的情况
-        if competition.is_some() && chart_type.is_none() {
-            if let Some(ref comp) = competition {
-                // 检查是否以mod类型开头
-                for mod_len in (2..=comp.len()).rev() {
-                    let prefix = &comp[..mod_len.min(comp.len())];
-                    if mod_types.contains(prefix.to_uppercase().as_str()) {
-                        // 分离mod类型和序号
-                        chart_type = Some(prefix.to_uppercase());
-                        let remainder = &comp[mod_len..];
-                        if !remainder.is_empty() {
-                            chart_type_index = remainder.parse().ok();
-                        }
-                        competition = None;
-                        break;
+        if competition.is_some() && chart_type.is_none()
+            && let Some(ref comp) = competition {
+            // 检查是否以mod类型开头
+            for mod_len in (2..=comp.len()).rev() {
+                let prefix = &comp[..mod_len.min(comp.len())];
+                if mod_types.contains(prefix.to_uppercase().as_str()) {
+                    // 分离mod类型和序号
+                    chart_type = Some(prefix.to_uppercase());
+                    let remainder = &comp[mod_len..];
+                    if !remainder.is_empty() {
+                        chart_type_index = remainder.parse().ok();
                     }
+                    competition = None;
+                    break;
                 }
             }
         }
@@ -118,59 +117,6 @@ impl ChartQuery {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_parse_queries() {
-        let test_cases = vec![
-            ("!pick HD", (
-                None, None, None, Some("HD"), None
-            )),
-            ("!pick HD1", (
-                None, None, None, Some("HD"), Some(1)
-            )),
-            ("!pick MP5", (
-                Some("MP5"), None, None, None, None
-            )),
-            ("!pick MP5 HD", (
-                Some("MP5"), None, None, Some("HD"), None
-            )),
-            ("!pick MP5 HD1", (
-                Some("MP5"), None, None, Some("HD"), Some(1)
-            )),
-            ("!pick MP5 S22", (
-                Some("MP5"), Some("S22"), None, None, None
-            )),
-            ("!pick MP5 S22 HD", (
-                Some("MP5"), Some("S22"), None, Some("HD"), None
-            )),
-            ("!pick MP5 S22 HD1", (
-                Some("MP5"), Some("S22"), None, Some("HD"), Some(1)
-            )),
-            ("!pick MP5 S22-1", (
-                Some("MP5"), Some("S22"), Some(1), None, None
-            )),
-            ("!pick MP5 S22-1 HD", (
-                Some("MP5"), Some("S22"), Some(1), Some("HD"), None
-            )),
-            ("!pick MP5 S22-1 HD1", (
-                Some("MP5"), Some("S22"), Some(1), Some("HD"), Some(1)
-            )),
-        ];
-
-        for (input, expected) in test_cases {
-            let result = ChartQuery::parse(input).unwrap();
-            assert_eq!(result.competition.as_deref(), expected.0, "Failed on competition for: {}", input);
-            assert_eq!(result.season.as_deref(), expected.1, "Failed on season for: {}", input);
-            assert_eq!(result.pool_index, expected.2, "Failed on pool_index for: {}", input);
-            assert_eq!(result.chart_type.as_deref(), expected.3, "Failed on chart_type for: {}", input);
-            assert_eq!(result.chart_type_index, expected.4, "Failed on chart_type_index for: {}", input);
-        }
-    }
-}
-
 pub struct ChartDatabase {
     conn: Connection,
 }
@@ -254,6 +200,60 @@ impl ChartDatabase {
 }
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_queries() {
+        let test_cases = vec![
+            ("!pick HD", (
+                None, None, None, Some("HD"), None
+            )),
+            ("!pick HD1", (
+                None, None, None, Some("HD"), Some(1)
+            )),
+            ("!pick MP5", (
+                Some("MP5"), None, None, None, None
+            )),
+            ("!pick MP5 HD", (
+                Some("MP5"), None, None, Some("HD"), None
+            )),
+            ("!pick MP5 HD1", (
+                Some("MP5"), None, None, Some("HD"), Some(1)
+            )),
+            ("!pick MP5 S22", (
+                Some("MP5"), Some("S22"), None, None, None
+            )),
+            ("!pick MP5 S22 HD", (
+                Some("MP5"), Some("S22"), None, Some("HD"), None
+            )),
+            ("!pick MP5 S22 HD1", (
+                Some("MP5"), Some("S22"), None, Some("HD"), Some(1)
+            )),
+            ("!pick MP5 S22-1", (
+                Some("MP5"), Some("S22"), Some(1), None, None
+            )),
+            ("!pick MP5 S22-1 HD", (
+                Some("MP5"), Some("S22"), Some(1), Some("HD"), None
+            )),
+            ("!pick MP5 S22-1 HD1", (
+                Some("MP5"), Some("S22"), Some(1), Some("HD"), Some(1)
+            )),
+        ];
+
+        for (input, expected) in test_cases {
+            let result = ChartQuery::parse(input).unwrap();
+            assert_eq!(result.competition.as_deref(), expected.0, "Failed on competition for: {}", input);
+            assert_eq!(result.season.as_deref(), expected.1, "Failed on season for: {}", input);
+            assert_eq!(result.pool_index, expected.2, "Failed on pool_index for: {}", input);
+            assert_eq!(result.chart_type.as_deref(), expected.3, "Failed on chart_type for: {}", input);
+            assert_eq!(result.chart_type_index, expected.4, "Failed on chart_type_index for: {}", input);
+        }
+    }
+}
+
+
 // fn main() -> Result<(), Box<dyn std::error::Error>> {
 //     // 初始化数据库连接
 //     let db = ChartDatabase::open("charts.sqlite")?;
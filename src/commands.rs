@@ -1,71 +1,285 @@
 use crate::{bot::MyBot, osu_api::UserScore, osu_api::RecentScoreResponse};
+use crate::room::Room;
 use std::error::Error;
+use crate::bans;
+use crate::cooldown::{Burst, Limit, Verdict};
+use crate::i18n::Lang;
 use crate::charts::{Chart, ChartQuery};
+use crate::permission::Permission;
+use crate::pp_calculator::{self, PPCalculator, ScorePP};
+use crate::stats;
+use crate::vote::VoteKind;
+use std::path::Path;
 
-pub async fn handle_command(bot: &mut MyBot, sender: &str,target: &str, msg: &str, prefix: Option<String>) -> Result<(), Box<dyn Error>> {
+// 拆分出小写的指令和参数
+fn split_command(msg: &str) -> (String, &str) {
     let mut split = msg.splitn(2, char::is_whitespace); // 只分割一次
-    let mut command = split.next().unwrap_or("").to_lowercase();
+    let command = split.next().unwrap_or("").to_lowercase().replace("！", "!");
     let raw_args = split.next().unwrap_or("").trim();
-    command = command.replace("！", "!");
+    (command, raw_args)
+}
+
+/// 指令的声明，分发、权限检查、菜单和 !help <指令> 都由此生成。说明文案为 commands.<去掉 ! 的名称>
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    /// 参数写法，<> 为必填参数，[] 为可选参数。arg.<词> 有文案的词会被翻译
+    pub args: &'static str,
+    pub permission: Permission,
+    /// 是否可以私聊 bot 使用
+    pub private: bool,
+    /// 冷却和违规时的处理，协管以上不受限制
+    pub limit: Limit,
+}
+
+impl CommandSpec {
+    const fn new(name: &'static str, aliases: &'static [&'static str], args: &'static str, permission: Permission) -> Self {
+        // 协管以上的指令都可以私聊使用
+        let private = matches!(permission, Permission::Operator | Permission::Admin);
+        CommandSpec { name, aliases, args, permission, private, limit: Limit::NONE }
+    }
+
+    const fn private(mut self) -> Self {
+        self.private = true;
+        self
+    }
+
+    const fn limit(mut self, limit: Limit) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    fn matches(&self, command: &str) -> bool {
+        self.name == command || self.aliases.contains(&command)
+    }
+
+    /// 必填参数都有时返回 true
+    fn accepts(&self, args: &str) -> bool {
+        // [] 中的参数都是可选的，例如 !queue 的 move <player> <pos>
+        let mut depth = 0;
+        let mut required = 0;
+        for arg in self.args.split_whitespace() {
+            if depth == 0 && arg.starts_with('<') {
+                required += 1;
+            }
+            depth += arg.matches('[').count();
+            depth -= arg.matches(']').count();
+        }
+        args.split_whitespace().count() >= required
+    }
+
+    /// 例如 `!kick <玩家>`
+    pub fn usage(&self, lang: Lang) -> String {
+        let mut usage = self.name.to_string();
+        if !self.args.is_empty() {
+            usage.push(' ');
+            usage.push_str(&translate_words(self.args, lang));
+        }
+        usage
+    }
+
+    pub fn description(&self, lang: Lang) -> String {
+        lang.t(&format!("commands.{}", &self.name[1..]), &[])
+    }
+
+    /// !help <指令> 和参数错误时的回复
+    pub fn help(&self, lang: Lang) -> String {
+        let mut help = lang.t("help.detail", &[("usage", &self.usage(lang)), ("description", &self.description(lang))]);
+        if !self.aliases.is_empty() {
+            help.push_str(&lang.t("help.aliases", &[("aliases", &self.aliases.join(" "))]));
+        }
+        if self.permission > Permission::Player {
+            let permission = lang.t(&format!("permission.{}", self.permission.name()), &[]);
+            help.push_str(&lang.t("help.permission", &[("permission", &permission)]));
+        }
+        help
+    }
+
+    /// 菜单中的一项，例如 `!queue(!q) 查看房主队列`
+    fn menu_entry(&self, lang: Lang) -> String {
+        let aliases = self.aliases.iter().map(|alias| format!("({})", alias)).collect::<String>();
+        format!("{}{} {}", self.name, aliases, self.description(lang))
+    }
+}
+
+/// 每位玩家 10 秒内最多使用 5 次指令
+pub const USER_BURST: Burst = Burst { count: 5, window_secs: 10 };
+/// 所有玩家 10 秒内最多使用 20 次指令，超出时不回应
+pub const GLOBAL_BURST: Burst = Burst { count: 20, window_secs: 10 };
+
+/// 全部指令，菜单按这里的顺序排列
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("!queue", &["!q"], "[leave|join|position|move <player> <pos>]", Permission::Player).limit(Limit::cooldown(5)),
+    CommandSpec::new("!abort", &[], "", Permission::Player).limit(Limit::cooldown(3)),
+    CommandSpec::new("!start", &[], "", Permission::Player).limit(Limit::cooldown(3)),
+    CommandSpec::new("!skip", &[], "", Permission::Player).limit(Limit::cooldown(3)),
+    CommandSpec::new("!close", &[], "", Permission::Player).limit(Limit::cooldown(3)),
+    CommandSpec::new("!unvote", &[], "[abort|skip|close|start]", Permission::Player).limit(Limit::cooldown(3)),
+    CommandSpec::new("!pr", &["!p"], "", Permission::Player).limit(Limit::api(15)),
+    CommandSpec::new("!re", &["!r"], "", Permission::Player).limit(Limit::api(15)),
+    CommandSpec::new("!s", &[], "", Permission::Player).limit(Limit::api(15)),
+    CommandSpec::new("!info", &["!i"], "", Permission::Player).limit(Limit::cooldown(10)),
+    CommandSpec::new("!pp", &[], "[mods] [acc]", Permission::Player).limit(Limit::cooldown(5)),
+    CommandSpec::new("!pick", &[], "[competition] [season] [mod]", Permission::Host).limit(Limit::cooldown(3)),
+    CommandSpec::new("!ttl", &[], "", Permission::Player).limit(Limit::cooldown(10)),
+    CommandSpec::new("!stats", &[], "[player]", Permission::Player).limit(Limit::cooldown(10)),
+    CommandSpec::new("!top", &[], "", Permission::Player).limit(Limit::cooldown(30)),
+    CommandSpec::new("!lang", &[], "[zh-CN|en|default]", Permission::Player).private().limit(Limit::cooldown(3)),
+    CommandSpec::new("!hello", &[], "", Permission::Player).limit(Limit::cooldown(30)),
+    CommandSpec::new("!help", &["!h"], "[command]", Permission::Player).private().limit(Limit::cooldown(10)),
+    CommandSpec::new("!about", &[], "", Permission::Player).limit(Limit::cooldown(30)),
+    CommandSpec::new("!kick", &[], "<player>", Permission::Operator),
+    CommandSpec::new("!sethost", &[], "<player>", Permission::Operator),
+    CommandSpec::new("!forceskip", &[], "", Permission::Operator),
+    CommandSpec::new("!lock", &[], "", Permission::Operator),
+    CommandSpec::new("!say", &[], "<message>", Permission::Operator),
+    CommandSpec::new("!bans", &[], "", Permission::Operator),
+    CommandSpec::new("!outbox", &[], "", Permission::Operator),
+    CommandSpec::new("!unmute", &[], "<player>", Permission::Operator),
+    CommandSpec::new("!ban", &[], "<player> [duration] [reason]", Permission::Admin),
+    CommandSpec::new("!unban", &[], "<player>", Permission::Admin),
+    CommandSpec::new("!setrules", &[], "[key=value...]", Permission::Admin),
+    CommandSpec::new("!reload", &[], "", Permission::Admin),
+    CommandSpec::new("!shutdown", &[], "", Permission::Admin),
+];
+
+/// 按名称或别名查找指令，可以省略 !
+pub fn find_command(command: &str) -> Option<&'static CommandSpec> {
+    let command = command.to_lowercase().replace("！", "!");
+    let command = if command.starts_with('!') { command } else { format!("!{}", command) };
+    COMMANDS.iter().find(|spec| spec.matches(&command))
+}
+
+/// 翻译参数写法中有 arg.<词> 文案的词
+fn translate_words(args: &str, lang: Lang) -> String {
+    let mut result = String::with_capacity(args.len());
+    let mut word = String::new();
+    for c in args.chars().chain(std::iter::once(' ')) {
+        if c.is_ascii_lowercase() || c == '_' {
+            word.push(c);
+            continue;
+        }
+        if !word.is_empty() {
+            result.push_str(lang.lookup(&format!("arg.{}", word)).unwrap_or(&word));
+            word.clear();
+        }
+        result.push(c);
+    }
+    result.pop();
+    result
+}
+
+pub async fn handle_command(bot: &mut MyBot, room: &mut Room, target: &str, msg: &str, prefix: Option<String>) -> Result<(), Box<dyn Error>> {
+    let (command, raw_args) = split_command(msg);
+    let Some(spec) = find_command(&command) else {
+        return Ok(());
+    };
     let irc_name = prefix.unwrap_or_default();
-    match command.as_str() {
+    let permission = bot.permission(&irc_name, Some(&room.room_host));
+    if bot.limiter.is_muted(&irc_name, tokio::time::Instant::now()) {
+        return Ok(());
+    }
+    if permission < spec.permission {
+        let message = if spec.permission == Permission::Host {
+            room.lang.t("common.host_only", &[("command", &spec.name)])
+        } else {
+            room.lang.t("common.no_permission", &[])
+        };
+        bot.send_message(target, &message).await?;
+        return Ok(());
+    }
+    if !spec.accepts(raw_args) {
+        bot.send_message(target, &spec.help(room.lang)).await?;
+        return Ok(());
+    }
+    // 只有会被执行的指令才计入冷却
+    let reply_lang = bot.reply_lang(&irc_name, room.lang);
+    if !check_limit(bot, &irc_name, permission, spec, reply_lang).await? {
+        return Ok(());
+    }
+    if spec.permission >= Permission::Operator {
+        return handle_admin_command(bot, room, target, &irc_name, spec, raw_args).await;
+    }
+    match spec.name {
         "!hello" => {
-            let response = format!("Hello, {}!", &irc_name);
+            let response = room.lang.t("common.hello", &[("player", &irc_name)]);
             bot.send_message(target, &response).await?;
         }
-        "!info" | "!i" => {
-            bot.send_beatmap_info().await?;
+        "!info" => {
+            room.send_beatmap_info().await?;
         }
-        "!pick"=> {
-            if sender == bot.room_host{
-                handle_pick(bot, target,raw_args).await?;
-            }
-            else { 
-                bot.send_message(target,"只有房主才能选歌哦").await?;
-            }
+        "!pick" => {
+            handle_pick(bot, room, target, spec, raw_args).await?;
         }
         "!abort" => {
-            bot.vote_abort(&irc_name).await?;
+            room.vote(VoteKind::Abort, &irc_name).await?;
         }
-        "!queue" | "!q" => {
-            bot.send_queue().await?;
+        "!queue" => {
+            handle_queue(room, &irc_name, permission, spec, raw_args).await?;
         }
         "!skip" => {
-            bot.vote_skip(&irc_name).await?;
+            room.vote(VoteKind::Skip, &irc_name).await?;
         }
         "!close" => {
-            bot.vote_close(&irc_name).await?;
+            room.vote(VoteKind::Close, &irc_name).await?;
         }
         "!start" => {
-            bot.vote_start(&irc_name).await?;
+            room.vote(VoteKind::Start, &irc_name).await?;
+        }
+        "!unvote" => {
+            if raw_args.is_empty() {
+                room.unvote(&irc_name, None).await?;
+            } else if let Some(kind) = VoteKind::parse(raw_args) {
+                room.unvote(&irc_name, Some(kind)).await?;
+            } else {
+                room.send_message(&spec.help(room.lang)).await?;
+            }
+        }
+        "!pp" => {
+            handle_pp(room, spec, raw_args).await?;
         }
         "!ttl" => {
-            bot.calculate_total_time_left().await?;
+            room.calculate_total_time_left().await?;
         }
-        "!help" | "!h" => {
-            bot.send_menu().await?;
+        "!help" => {
+            let message = help_message(raw_args, room.lang, |spec| spec.permission <= permission.max(Permission::Host));
+            bot.send_message(target, &message).await?;
         }
         "!about" => {
-            bot.send_about().await?;
+            room.send_about().await?;
+        }
+        "!pr" => {
+            handle_recent_score(bot, target, &irc_name, false, room.lang).await?;
         }
-        "!pr" | "!p" => {
-            handle_recent_score(bot, target, &irc_name, false).await?;
+        "!re" => {
+            handle_recent_score(bot, target, &irc_name, true, room.lang).await?;
         }
-        "!re" | "!r" => {
-            handle_recent_score(bot, target, &irc_name, true).await?;
+        "!lang" => {
+            let current = bot.reply_lang(&irc_name, room.lang);
+            handle_lang(bot, target, &irc_name, raw_args, current, room.lang).await?;
+        }
+        "!stats" => {
+            handle_stats(bot, room, &irc_name, raw_args).await?;
+        }
+        "!top" => {
+            let players = bot.stats_db.top_players(5)?;
+            let maps = bot.stats_db.top_maps(3)?;
+            room.send_message(&stats::format_top(&players, &maps, room.lang)).await?;
         }
         "!s" => {
-            let user_id = bot.get_user_mut(&irc_name).await.unwrap().id.clone();
-            let username = bot.get_user_mut(&irc_name).await.unwrap().username.clone();
-            let beatmap_id = bot.beatmap_id;
+            let Some((user_id, username)) = lookup_user(bot, target, &irc_name, room.lang).await? else {
+                return Ok(());
+            };
+            let beatmap_id = room.beatmap_id;
 
             match bot.osu_api.get_user_score(user_id, beatmap_id).await {
                 Ok(userscore) => {
-                    let formatted_score = format_user_score(&username, &userscore, bot);
+                    let formatted_score = format_user_score(&username, &userscore, room);
                     bot.send_message(target, &formatted_score).await?;
                 },
                 Err(e) => {
-                    bot.send_message(target, &format!("Failed to get user score: {}", e)).await?;
+                    bot.send_message(target, &room.lang.t("score.failed", &[("error", &e)])).await?;
                 }
             }
         }
@@ -73,103 +287,528 @@ pub async fn handle_command(bot: &mut MyBot, sender: &str,target: &str, msg: &st
     }
     Ok(())
 }
-async fn handle_pick(bot: &mut MyBot, target: &str,parms:&str) -> Result<(), Box<dyn Error>> {
+/// 私聊 bot 的指令，只接受 private 的指令。管理指令的参数前可以写 #mp_<id> 指定房间，bot 只管理一个房间时可以省略
+pub async fn handle_private_command(bot: &mut MyBot, sender: &str, msg: &str) -> Result<(), Box<dyn Error>> {
+    let (command, raw_args) = split_command(msg);
+    let Some(spec) = find_command(&command).filter(|spec| spec.private) else {
+        return Ok(());
+    };
+    let lang = bot.reply_lang(sender, bot.rooms.default_lang());
+    let permission = bot.permission(sender, None);
+    if bot.limiter.is_muted(sender, tokio::time::Instant::now()) {
+        return Ok(());
+    }
+    if permission < spec.permission {
+        bot.send_message(sender, &lang.t("common.no_permission", &[])).await?;
+        return Ok(());
+    }
+    if !check_limit(bot, sender, permission, spec, lang).await? {
+        return Ok(());
+    }
+    match spec.name {
+        "!lang" => return handle_lang(bot, sender, sender, raw_args, lang, bot.rooms.default_lang()).await,
+        "!help" => {
+            let message = help_message(raw_args, lang, |spec| spec.private && spec.permission <= permission);
+            return bot.send_message(sender, &message).await;
+        }
+        _ => {}
+    }
+
+    let (channel, args) = match raw_args.strip_prefix('#') {
+        Some(_) => {
+            let (channel, args) = raw_args.split_once(char::is_whitespace).unwrap_or((raw_args, ""));
+            (channel.to_string(), args.trim())
+        }
+        None => {
+            let channels = bot.rooms.channels();
+            if channels.len() != 1 {
+                let message = lang.t("admin.choose_room", &[("command", &command), ("rooms", &channels.join(" "))]);
+                bot.send_message(sender, &message).await?;
+                return Ok(());
+            }
+            (channels[0].clone(), raw_args)
+        }
+    };
+    if !spec.accepts(args) {
+        bot.send_message(sender, &spec.help(lang)).await?;
+        return Ok(());
+    }
+    let Some(mut room) = bot.rooms.take(&channel) else {
+        bot.send_message(sender, &lang.t("admin.room_not_found", &[("channel", &channel)])).await?;
+        return Ok(());
+    };
+    let mut result = handle_admin_command(bot, &mut room, sender, sender, spec, args).await;
+    if result.is_ok() && room.bot_pick_requested {
+        result = handle_bot_pick(bot, &mut room).await;
+    }
+    if let Err(e) = room.save_latest_info_to_file() {
+        println!("Failed to save state of {}: {}", room.channel(), e);
+    }
+    bot.rooms.insert(room);
+    result
+}
+
+/// 房间内或私聊中的管理指令，回复发送到 reply_to，调用前需要检查权限和必填参数
+async fn handle_admin_command(bot: &mut MyBot, room: &mut Room, reply_to: &str, irc_name: &str, spec: &CommandSpec, args: &str) -> Result<(), Box<dyn Error>> {
+    let lang = bot.reply_lang(reply_to, room.lang);
+    match spec.name {
+        "!kick" | "!sethost" => {
+            let Some(player) = room.find_player(args) else {
+                bot.send_message(reply_to, &lang.t("common.no_such_player", &[("player", &args)])).await?;
+                return Ok(());
+            };
+            if spec.name == "!kick" {
+                room.send_message(&format!("!mp kick {}", player)).await?;
+            } else {
+                room.force_host(&player).await?;
+            }
+        }
+        "!ban" => {
+            handle_ban(bot, room, reply_to, irc_name, args, lang).await?;
+        }
+        "!unban" => {
+            if bot.ban_list.lift(args)? {
+                bot.send_message(reply_to, &lang.t("ban.unbanned", &[("player", &args)])).await?;
+            } else {
+                bot.send_message(reply_to, &lang.t("ban.not_banned", &[("player", &args)])).await?;
+            }
+        }
+        "!bans" => {
+            let now = bans::now();
+            let bans = bot.ban_list.list(now)?;
+            let message = if bans.is_empty() {
+                lang.t("ban.list_empty", &[])
+            } else {
+                let bans = bans.iter().map(|ban| ban.format(now, lang)).collect::<Vec<_>>().join(" | ");
+                lang.t("ban.list", &[("bans", &bans)])
+            };
+            bot.send_message(reply_to, &message).await?;
+        }
+        "!forceskip" => {
+            bot.send_message(reply_to, &lang.t("host.force_skipped", &[("player", &room.room_host)])).await?;
+            room.rotate_host().await?;
+        }
+        "!lock" => {
+            let locked = room.toggle_lock().await?;
+            let key = if locked { "room.locked" } else { "room.unlocked" };
+            bot.send_message(reply_to, &lang.t(key, &[])).await?;
+        }
+        "!say" => {
+            room.send_message(args).await?;
+        }
+        "!unmute" => {
+            let message = if bot.limiter.unmute(args) {
+                lang.t("cooldown.unmuted", &[("player", &args)])
+            } else {
+                lang.t("cooldown.not_muted", &[("player", &args)])
+            };
+            bot.send_message(reply_to, &message).await?;
+        }
+        "!outbox" => {
+            bot.send_message(reply_to, &bot.outbox.stats().format(lang)).await?;
+        }
+        "!setrules" => {
+            if args.is_empty() {
+                let message = lang.t("rules.current", &[("rules", &room.rules.describe(lang)), ("usage", &lang.t("rules.usage", &[]))]);
+                bot.send_message(reply_to, &message).await?;
+                return Ok(());
+            }
+            let message = match room.rules.update(args, lang) {
+                Ok(_) => lang.t("rules.updated", &[("rules", &room.rules.describe(lang))]),
+                Err(e) => lang.t("rules.not_updated", &[("error", &e)]),
+            };
+            bot.send_message(reply_to, &message).await?;
+        }
+        "!reload" => {
+            handle_reload(bot, room, reply_to, lang).await?;
+        }
+        "!shutdown" => {
+            bot.send_message(reply_to, &lang.t("admin.shutdown", &[])).await?;
+            bot.request_shutdown();
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// !ban <玩家> [时长] [原因]，时长如 30m、2h、7d，不写时永久封禁。玩家在房间中时立即踢出
+async fn handle_ban(bot: &mut MyBot, room: &mut Room, reply_to: &str, irc_name: &str, args: &str, lang: Lang) -> Result<(), Box<dyn Error>> {
+    let mut split = args.splitn(2, char::is_whitespace);
+    let player = split.next().unwrap_or("");
+    let mut rest = split.next().unwrap_or("").trim();
+    let (first, remaining) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let duration = bans::parse_duration(first);
+    if duration.is_some() {
+        rest = remaining.trim();
+    }
+
+    let now = bans::now();
+    let ban = bot.ban_list.ban(player, rest, irc_name, duration, now)?;
+    if let Some(name) = room.find_player(player) {
+        room.send_message(&format!("!mp kick {}", name)).await?;
+    }
+    bot.send_message(reply_to, &lang.t("ban.done", &[("ban", &ban.format(now, lang))])).await?;
+    Ok(())
+}
+
+async fn handle_reload(bot: &mut MyBot, room: &mut Room, reply_to: &str, lang: Lang) -> Result<(), Box<dyn Error>> {
+    match bot.reload_config(Some(room)).await {
+        Ok(_) => bot.send_message(reply_to, &lang.t("admin.reloaded", &[])).await?,
+        Err(e) => {
+            // 配置错误是多行的，irc 消息不能换行
+            let error = e.to_string().lines().map(str::trim).collect::<Vec<_>>().join("; ");
+            bot.send_message(reply_to, &lang.t("admin.reload_failed", &[("error", &error)])).await?
+        }
+    }
+    Ok(())
+}
+
+// !queue [leave|join|position|move <玩家> <位置>]，不写参数时显示队列
+async fn handle_queue(room: &mut Room, irc_name: &str, permission: Permission, spec: &CommandSpec, args: &str) -> Result<(), Box<dyn Error>> {
+    let mut split = args.split_whitespace();
+    let subcommand = split.next().unwrap_or("").to_lowercase();
+    if subcommand.is_empty() {
+        return room.send_queue().await;
+    }
+    if subcommand == "move" {
+        if permission < Permission::Operator {
+            room.send_message(&room.lang.t("common.no_permission", &[])).await?;
+            return Ok(());
+        }
+        let (Some(name), Some(Ok(position))) = (split.next(), split.next().map(str::parse::<usize>)) else {
+            room.send_message(&room.lang.t("queue.move_usage", &[])).await?;
+            return Ok(());
+        };
+        return handle_queue_move(room, name, position).await;
+    }
+
+    let Some(player) = room.find_player(irc_name) else {
+        return Ok(());
+    };
+    match subcommand.as_str() {
+        "leave" => {
+            if !room.host_queue.opt_out(&player) {
+                room.send_message(&room.lang.t("queue.not_in_queue", &[("player", &player)])).await?;
+                return Ok(());
+            }
+            room.send_message(&room.lang.t("queue.left", &[("player", &player)])).await?;
+            if player == room.room_host && !room.is_game_started {
+                room.rotate_host().await?;
+            }
+        }
+        "join" => {
+            if room.host_queue.opt_in(&player) {
+                room.send_message(&room.lang.t("queue.joined", &[("player", &player)])).await?;
+            } else {
+                room.send_message(&room.lang.t("queue.already_in", &[("player", &player)])).await?;
+            }
+        }
+        "position" | "pos" => {
+            let offset = usize::from(room.host_queue.front() == Some(&room.room_host));
+            let message = match room.host_queue.position(&player) {
+                _ if player == room.room_host => room.lang.t("queue.is_host", &[("player", &player)]),
+                None => room.lang.t("queue.not_in_queue_join", &[("player", &player)]),
+                Some(index) => {
+                    // 前面每位房主 (包括当前房主) 大约打一张谱面
+                    let position = index + 1 - offset;
+                    let wait = position as u64 * room.average_map_length();
+                    room.lang.t("queue.position", &[("player", &player), ("position", &position), ("minutes", &wait.div_ceil(60))])
+                }
+            };
+            room.send_message(&message).await?;
+        }
+        _ => {
+            room.send_message(&spec.help(room.lang)).await?;
+        }
+    }
+    Ok(())
+}
+
+// 当前房主在队列最前面，位置从下一位房主开始算
+async fn handle_queue_move(room: &mut Room, name: &str, position: usize) -> Result<(), Box<dyn Error>> {
+    let Some(player) = room.find_player(name) else {
+        room.send_message(&room.lang.t("common.no_such_player", &[("player", &name)])).await?;
+        return Ok(());
+    };
+    if player == room.room_host {
+        room.send_message(&room.lang.t("queue.cannot_move_host", &[])).await?;
+        return Ok(());
+    }
+    let offset = usize::from(room.host_queue.front() == Some(&room.room_host));
+    let message = match room.host_queue.move_to(&player, position.max(1) - 1 + offset) {
+        Some(index) => room.lang.t("queue.moved", &[("player", &player), ("position", &(index + 1 - offset))]),
+        None => room.lang.t("queue.not_in_queue", &[("player", &player)]),
+    };
+    room.send_message(&message).await?;
+    Ok(())
+}
+
+/// 检查冷却和刷屏限制，不能使用时按违规次数忽略、私聊警告或禁言，返回 false
+/// lang 为警告私聊使用的语言
+async fn check_limit(bot: &mut MyBot, irc_name: &str, permission: Permission, spec: &'static CommandSpec, lang: Lang) -> Result<bool, Box<dyn Error>> {
+    if permission >= Permission::Operator {
+        return Ok(true);
+    }
+    let verdict = bot.limiter.check(irc_name, spec.name, spec.limit, tokio::time::Instant::now());
+    if verdict != Verdict::Allow {
+        println!("Limited {} from {}: {:?}", spec.name, irc_name, verdict);
+    }
+    // 警告私聊发送，避免在房间里继续刷屏
+    let message = match verdict {
+        Verdict::Allow => return Ok(true),
+        Verdict::Ignore => return Ok(false),
+        Verdict::Cooldown { seconds } => lang.t("cooldown.wait", &[("command", &spec.name), ("seconds", &seconds)]),
+        Verdict::Burst => lang.t("cooldown.burst", &[]),
+        Verdict::Mute { seconds } => lang.t("cooldown.muted", &[("minutes", &seconds.div_ceil(60))]),
+    };
+    bot.send_message(irc_name, &message).await?;
+    Ok(false)
+}
+
+/// 不写参数时返回 visible 的指令组成的菜单，否则返回该指令的用法
+fn help_message(args: &str, lang: Lang, visible: impl Fn(&CommandSpec) -> bool) -> String {
+    if args.is_empty() {
+        let commands = COMMANDS.iter().filter(|spec| visible(spec)).map(|spec| spec.menu_entry(lang)).collect::<Vec<_>>();
+        return lang.t("help.menu", &[("commands", &commands.join(" | "))]);
+    }
+    match find_command(args) {
+        Some(spec) => spec.help(lang),
+        None => lang.t("help.unknown", &[("command", &args)]),
+    }
+}
+
+// !lang [语言|default]，设置私聊回复使用的语言，房间内的消息始终使用房间语言
+// current 为玩家当前的回复语言，default 为恢复默认后使用的语言
+async fn handle_lang(bot: &mut MyBot, reply_to: &str, irc_name: &str, args: &str, current: Lang, default: Lang) -> Result<(), Box<dyn Error>> {
+    let message = if args.is_empty() {
+        current.t("lang.current", &[("lang", &current.t("lang.name", &[])), ("langs", &Lang::codes())])
+    } else if args.eq_ignore_ascii_case("default") {
+        bot.stats_db.set_player_lang(irc_name, None)?;
+        default.t("lang.reset", &[("lang", &default.t("lang.name", &[]))])
+    } else if let Some(lang) = Lang::parse(args) {
+        bot.stats_db.set_player_lang(irc_name, Some(lang))?;
+        lang.t("lang.set", &[])
+    } else {
+        current.t("lang.unknown", &[("code", &args), ("langs", &Lang::codes())])
+    };
+    bot.send_message(reply_to, &message).await?;
+    Ok(())
+}
+
+// !stats [player]，不写玩家时查询自己
+async fn handle_stats(bot: &mut MyBot, room: &mut Room, irc_name: &str, args: &str) -> Result<(), Box<dyn Error>> {
+    let name = if args.is_empty() { irc_name.to_string() } else { args.to_string() };
+    let message = match bot.stats_db.player_stats(&name)? {
+        Some(stats) => stats.format(room.lang),
+        None => room.lang.t("stats.none", &[("player", &name)]),
+    };
+    room.send_message(&message).await?;
+    Ok(())
+}
+
+async fn handle_pick(bot: &mut MyBot, room: &mut Room, target: &str, spec: &CommandSpec, parms: &str) -> Result<(), Box<dyn Error>> {
 
     let query = match ChartQuery::parse(&parms.to_uppercase()) {
         Ok(q) => q,
-        Err(e) => {
-            bot.send_message(target, "输入的参数有误,请检查").await?;
+        Err(_) => {
+            bot.send_message(target, &spec.help(room.lang)).await?;
             return Ok(());
         }
     };
 
     if let Some(chart) = bot.chart_db.query_with_fallback(&query)? {
         // println!("查询结果: {}", serde_json::to_string_pretty(&chart)?);
-        bot.set_map(chart.chart_id).await?;
-        let formatted_pick = format_pick(chart);
+        room.set_map(chart.chart_id).await?;
+        let formatted_pick = format_pick(chart, room.lang);
         bot.send_message(target, &formatted_pick).await?;
     } else {
-        bot.send_message(target, "没有找到匹配的谱面").await?;
+        bot.send_message(target, &room.lang.t("pick.not_found", &[])).await?;
+    }
+    Ok(())
+}
+
+/// 不设房主的房间由 bot 从图池随机选一张图
+pub async fn handle_bot_pick(bot: &mut MyBot, room: &mut Room) -> Result<(), Box<dyn Error>> {
+    room.bot_pick_requested = false;
+    if room.player_list.is_empty() {
+        return Ok(());
+    }
+    if let Some(chart) = bot.chart_db.query_with_fallback(&ChartQuery::default())? {
+        room.set_map(chart.chart_id).await?;
+        room.send_message(&format_pick(chart, room.lang)).await?;
+    } else {
+        room.send_message(&room.lang.t("pick.empty_pool", &[])).await?;
     }
     Ok(())
 }
 
-async fn handle_recent_score(bot: &mut MyBot, target: &str, irc_name: &str, include_fails: bool) -> Result<(), Box<dyn Error>> {
-    let user_id = bot.get_user_mut(irc_name).await.unwrap().id.clone();
-    let username = bot.get_user_mut(irc_name).await.unwrap().username.clone();
+// !pp <mods> [acc]，不写 mod 时使用房间当前的 mod
+async fn handle_pp(room: &mut Room, spec: &CommandSpec, args: &str) -> Result<(), Box<dyn Error>> {
+    if room.beatmap_path.is_empty() || !Path::new(&room.beatmap_path).exists() {
+        room.send_message(&room.lang.t("room.no_beatmap", &[])).await?;
+        return Ok(());
+    }
+
+    let mut mods = room.room_mods;
+    let mut accuracy = None;
+    for arg in args.split_whitespace() {
+        if let Ok(acc) = arg.trim_end_matches('%').parse::<f64>() {
+            accuracy = Some(acc);
+        } else if let Some(parsed) = pp_calculator::parse_mod_string(arg) {
+            mods = parsed;
+        } else {
+            room.send_message(&spec.help(room.lang)).await?;
+            return Ok(());
+        }
+    }
+
+    let (length, bpm) = room.length_and_bpm_with_mods(mods);
+    let header = format!("+{} | {}s {:.0}bpm", pp_calculator::mods_to_string(mods), length, bpm);
+    let message = match accuracy {
+        Some(acc) => {
+            let (stars, pp) = room.pp_calculator.calculate_accuracy_pp(mods, acc)?;
+            format!("{} | Stars: {:.2} | {:.2}%: {:.2}pp", header, stars, acc, pp)
+        }
+        None => {
+            let details = room.pp_calculator.calculate_beatmap_details(mods)?;
+            format!("{} | {}", header, details.format())
+        }
+    };
+    room.send_message(&message).await?;
+    Ok(())
+}
+
+/// 查询玩家的 osu! id 和用户名，失败时回复错误信息并返回 None
+async fn lookup_user(bot: &mut MyBot, target: &str, irc_name: &str, lang: Lang) -> Result<Option<(u32, String)>, Box<dyn Error>> {
+    match bot.get_user_mut(irc_name).await {
+        Ok(user) => Ok(Some((user.id, user.username.clone()))),
+        Err(e) => {
+            bot.send_message(target, &lang.t("score.user_failed", &[("error", &e)])).await?;
+            Ok(None)
+        }
+    }
+}
+
+async fn handle_recent_score(bot: &mut MyBot, target: &str, irc_name: &str, include_fails: bool, lang: Lang) -> Result<(), Box<dyn Error>> {
+    let Some((user_id, username)) = lookup_user(bot, target, irc_name, lang).await? else {
+        return Ok(());
+    };
 
     match bot.osu_api.get_user_recent_score(user_id, include_fails).await {
         Ok(Some(score)) => {
-            let formatted_score = format_score(&username, &score);
+            // 下载谱面并按成绩的判定统计计算pp，未上架或未通过的成绩api不会给出pp
+            let beatmap_path = bot.beatmap_path(score.beatmap.id);
+            let score_pp = match bot.osu_api.download_beatmap(score.beatmap.id, Path::new(&beatmap_path)).await {
+                Ok(_) => {
+                    let state = score.statistics.to_score_state(score.mode_int, &score.mods, score.max_combo);
+                    PPCalculator::calculate_pp(&beatmap_path, &state)
+                        .map_err(|e| println!("Failed to calculate pp: {}", e))
+                        .ok()
+                }
+                Err(e) => {
+                    println!("Failed to download beatmap {}: {}", score.beatmap.id, e);
+                    None
+                }
+            };
+            let formatted_score = format_score(&username, &score, score_pp.as_ref(), lang);
             bot.send_message(target, &formatted_score).await?;
-            // 这里就要算pp了
         }
         Ok(None) => {
-            let message = if include_fails {
-                format!("没有找到{}最近的成绩", username)
-            } else {
-                format!("没有找到{}最近pass的成绩", username)
-            };
+            let key = if include_fails { "score.recent_none" } else { "score.recent_pass_none" };
+            let message = lang.t(key, &[("player", &username)]);
             bot.send_message(target, &message).await?;
         }
         Err(e) => {
-            bot.send_message(target, &format!("获取最近成绩失败: {}", e)).await?;
+            bot.send_message(target, &lang.t("score.recent_failed", &[("error", &e)])).await?;
         }
     }
     Ok(())
 }
 
-fn format_pick(chart_info:Chart) -> String {
-
-    format!(
-        "当前谱面来自: {} {} {}({}) {}{}",
-        chart_info.competition_name.unwrap_or_default(),
-        chart_info.season.unwrap_or_default(),
-        chart_info.pool_name.unwrap_or_default(),
-        chart_info.pool_index.unwrap_or_default(),
-        chart_info.chart_type.unwrap_or_default(),
-        chart_info.chart_type_index.unwrap_or_default(),
-    )
+fn format_pick(chart_info: Chart, lang: Lang) -> String {
+    lang.t("pick.from", &[
+        ("competition", &chart_info.competition_name.unwrap_or_default()),
+        ("season", &chart_info.season.unwrap_or_default()),
+        ("pool", &chart_info.pool_name.unwrap_or_default()),
+        ("pool_index", &chart_info.pool_index.unwrap_or_default()),
+        ("type", &chart_info.chart_type.unwrap_or_default()),
+        ("type_index", &chart_info.chart_type_index.unwrap_or_default()),
+    ])
 }
 
-fn format_score(username: &str, score: &RecentScoreResponse) -> String {
+fn format_score(username: &str, score: &RecentScoreResponse, score_pp: Option<&ScorePP>, lang: Lang) -> String {
+    let pp = score_pp.map(|p| p.pp).or(score.pp.map(f64::from)).unwrap_or(0.0);
+    // 计算出的星数包含 mod 的影响
+    let stars = score_pp.map(|p| p.stars).unwrap_or(score.beatmap.difficulty_rating as f64);
+    let mut extra = String::new();
+    if let Some(score_pp) = score_pp {
+        if !score.perfect {
+            extra.push_str(&format!(" (FC: {:.2}pp)", score_pp.fc_pp));
+        }
+        if !score.passed {
+            extra.push_str(&lang.t("score.completion", &[("completion", &format!("{:.1}", score_pp.completion))]));
+        }
+    }
     format!(
-        "{}| [{} {} - {}]| {:.2}*| {}| [{}] {:.2}pp Acc: {:.2}% Combo: {}x| {}/{}/{}/{}| {}",
+        "{}| [{} {} - {}]| {:.2}*| {}| [{}] {:.2}pp{} Acc: {:.2}% Combo: {}x| {}| {}",
         username,
         score.format_url(),
         score.beatmapset.title_unicode,
         score.beatmapset.artist_unicode,
-        score.beatmap.difficulty_rating,
+        stars,
         score.mods.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(""),
         score.rank,
-        score.pp.unwrap_or(0.0),
+        pp,
+        extra,
         score.accuracy * 100.0,
         score.max_combo,
-        score.statistics.count_300,
-        score.statistics.count_100,
-        score.statistics.count_50,
-        score.statistics.count_miss,
-        score.format_date()
+        score.statistics.format(score.mode_int),
+        score.format_date(lang)
     )
 }
 
-fn format_user_score(username: &str, score: &UserScore, bot: &MyBot) -> String {
+fn format_user_score(username: &str, score: &UserScore, room: &Room) -> String {
     format!(
-        "{}| [{} {} - {}]| {:.2}*| {}| [{}] {:.2}pp Acc: {:.2}% Combo: {}x| {}/{}/{}/{}| {}",
+        "{}| [{} {} - {}]| {:.2}*| {}| [{}] {:.2}pp Acc: {:.2}% Combo: {}x| {}| {}",
         username,
-        score.score.format_url(bot.beatmap_id),
-        bot.beatmap_title_unicode,
-        bot.beatmap_artist_unicode,
-        bot.beatmap_difficulty_rating,
+        score.score.format_url(room.beatmap_id),
+        room.beatmap_title_unicode,
+        room.beatmap_artist_unicode,
+        room.beatmap_difficulty_rating,
         score.score.mods.iter().map(|m| m.to_string()).collect::<Vec<_>>().join(""),
         score.score.rank,
         score.score.pp.unwrap_or(0.0),
         score.score.accuracy * 100.0,
         score.score.max_combo,
-        score.score.statistics.count_300,
-        score.score.statistics.count_100,
-        score.score.statistics.count_50,
-        score.score.statistics.count_miss,
-        score.score.format_date()
+        score.score.statistics.format(score.score.mode_int),
+        score.score.format_date(room.lang)
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_command_registry() {
+        // 名称和别名不能重复，每个指令都要有说明
+        let mut names = HashSet::new();
+        for spec in COMMANDS {
+            for name in std::iter::once(&spec.name).chain(spec.aliases) {
+                assert!(name.starts_with('!') && names.insert(*name), "{}", name);
+            }
+            assert!(Lang::ZhCn.lookup(&format!("commands.{}", &spec.name[1..])).is_some(), "{}", spec.name);
+        }
+
+        assert_eq!(find_command("！Q").map(|spec| spec.name), Some("!queue"));
+        assert_eq!(find_command("ban").map(|spec| spec.name), Some("!ban"));
+        let ban = find_command("!ban").unwrap();
+        assert_eq!(ban.usage(Lang::ZhCn), "!ban <玩家> [时长] [原因]");
+        assert!(!ban.accepts("") && ban.accepts("Bob"));
+        assert_eq!(find_command("!lang").unwrap().usage(Lang::En), "!lang [zh-CN|en|default]");
+        let queue = find_command("!queue").unwrap();
+        assert_eq!(queue.usage(Lang::ZhCn), "!queue [leave|join|position|move <玩家> <位置>]");
+        assert!(queue.accepts(""));
+    }
+}
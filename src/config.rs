@@ -1,17 +1,620 @@
-use irc::client::prelude::Config;
-use std::env;
-
-pub fn get_config() -> Result<Config, Box<dyn std::error::Error>> {
-    
-    let nickname = env::var("IRC_NICKNAME").expect("NICKNAME must be set");
-    let password = env::var("IRC_PASSWORD").expect("IRC_PASSWORD must be set");
-
-    Ok(Config {
-        nickname: Some(nickname),
-        server: Some("irc.ppy.sh".to_owned()),
-        port: Some(6667),
-        password: Some(password),
-        use_tls: Some(false),
-        ..Config::default()
-    })
-}
\ No newline at end of file
+use irc::client::prelude::Config as IrcConfig;
+use serde::Deserialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::afk::AfkConfig;
+use crate::auto_start::AutoStartConfig;
+use crate::i18n::Lang;
+use crate::osu_api;
+use crate::outbox::OutboxConfig;
+use crate::pp_calculator;
+use crate::rotation::RotationMode;
+use crate::rules::MapRules;
+use crate::vote::{VoteKind, VoteRule, VoteRules};
+use crate::{BotSettings, RoomSettings};
+
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// 完整的 bot 配置，来自 config.toml，没有配置文件时来自环境变量 (.env)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BotConfig {
+    pub connection: ConnectionConfig,
+    #[serde(default)]
+    pub osu_api: OsuApiConfig,
+    /// 配置文件中的 [[room]]，每项一个房间
+    #[serde(default, rename = "room")]
+    pub rooms: Vec<RoomConfig>,
+    /// 房间没有单独设置 rules 时使用的选图规则
+    #[serde(default)]
+    pub rules: MapRules,
+    #[serde(default)]
+    pub votes: VotesConfig,
+    #[serde(default)]
+    pub messages: Messages,
+    #[serde(default)]
+    pub auto_start: AutoStartConfig,
+    #[serde(default)]
+    pub afk_host: AfkConfig,
+    #[serde(default)]
+    pub outbox: OutboxConfig,
+    #[serde(default)]
+    pub paths: PathsConfig,
+    /// 可以使用全部管理指令的玩家 (irc 昵称)
+    #[serde(default)]
+    pub admins: Vec<String>,
+    /// 可以使用 !kick/!sethost/!forceskip/!lock/!say/!bans/!queue move 的玩家 (irc 昵称)
+    #[serde(default)]
+    pub operators: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConnectionConfig {
+    #[serde(default = "default_server")]
+    pub server: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub use_tls: bool,
+    pub nickname: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OsuApiConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    pub base_url: String,
+    /// 后面直接拼接谱面 id
+    pub mirror_url: String,
+    /// 录制模式：把 api 响应保存到该目录
+    pub record_dir: Option<PathBuf>,
+    /// 回放模式：只从该目录读取录制的响应
+    pub fixture_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoomConfig {
+    pub name: String,
+    #[serde(default)]
+    pub password: String,
+    /// osu/taiko/catch/mania
+    #[serde(default = "default_game_mode")]
+    pub game_mode: String,
+    /// 不设置时使用全局的 [rules]
+    pub rules: Option<MapRules>,
+    /// round_robin/random/fewest_picks/join_time/no_host
+    #[serde(default = "default_rotation")]
+    pub rotation: String,
+    /// 房间消息使用的语言，zh-CN/en
+    #[serde(default = "default_lang")]
+    pub lang: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VotesConfig {
+    /// 投票通过需要的玩家比例
+    pub ratio: f64,
+    /// 至少需要的票数
+    pub min_voters: usize,
+    /// 第一票之后多少秒内没有通过则作废，0 表示不过期
+    pub timeout: u64,
+    /// 单独设置某种投票，没有写的项使用上面的值
+    pub abort: VoteRuleConfig,
+    pub skip: VoteRuleConfig,
+    pub close: VoteRuleConfig,
+    pub start: VoteRuleConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VoteRuleConfig {
+    pub ratio: Option<f64>,
+    pub min_voters: Option<usize>,
+    pub timeout: Option<u64>,
+}
+
+/// 发送到房间的文案，{player} 会替换为玩家名
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Messages {
+    /// 不设置时使用房间语言的默认欢迎语
+    pub welcome: Option<String>,
+    pub about: String,
+}
+
+/// 相对路径都相对于 data_dir
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+    pub data_dir: PathBuf,
+    pub charts_db: PathBuf,
+    pub stats_db: PathBuf,
+    pub bans_db: PathBuf,
+    pub room_ids_file: PathBuf,
+    /// 下载的 .osu 谱面文件
+    pub maps_dir: PathBuf,
+}
+
+fn default_server() -> String {
+    "irc.ppy.sh".to_string()
+}
+
+fn default_port() -> u16 {
+    6667
+}
+
+fn default_game_mode() -> String {
+    "osu".to_string()
+}
+
+fn default_lang() -> String {
+    Lang::default().code().to_string()
+}
+
+fn default_rotation() -> String {
+    RotationMode::RoundRobin.name().to_string()
+}
+
+impl Default for OsuApiConfig {
+    fn default() -> Self {
+        Self {
+            client_id: String::new(),
+            client_secret: String::new(),
+            base_url: osu_api::OSU_BASE_URL.to_string(),
+            mirror_url: osu_api::BEATMAP_MIRROR_URL.to_string(),
+            record_dir: None,
+            fixture_dir: None,
+        }
+    }
+}
+
+impl Default for VotesConfig {
+    fn default() -> Self {
+        Self {
+            ratio: 0.5,
+            min_voters: 1,
+            timeout: 0,
+            abort: VoteRuleConfig::default(),
+            skip: VoteRuleConfig::default(),
+            close: VoteRuleConfig::default(),
+            start: VoteRuleConfig::default(),
+        }
+    }
+}
+
+impl VotesConfig {
+    fn rule_config(&self, kind: VoteKind) -> &VoteRuleConfig {
+        match kind {
+            VoteKind::Abort => &self.abort,
+            VoteKind::Skip => &self.skip,
+            VoteKind::Close => &self.close,
+            VoteKind::Start => &self.start,
+        }
+    }
+
+    fn rule(&self, kind: VoteKind) -> VoteRule {
+        let config = self.rule_config(kind);
+        let timeout = config.timeout.unwrap_or(self.timeout);
+        VoteRule {
+            ratio: config.ratio.unwrap_or(self.ratio),
+            min_voters: config.min_voters.unwrap_or(self.min_voters),
+            timeout: (timeout > 0).then(|| Duration::from_secs(timeout)),
+        }
+    }
+
+    pub fn vote_rules(&self) -> VoteRules {
+        VoteRules {
+            abort: self.rule(VoteKind::Abort),
+            skip: self.rule(VoteKind::Skip),
+            close: self.rule(VoteKind::Close),
+            start: self.rule(VoteKind::Start),
+        }
+    }
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self {
+            welcome: None,
+            about: "https://github.com/Ohdmire/osu-ircbot-rust ATRI高性能bot with Rust".to_string(),
+        }
+    }
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("."),
+            charts_db: PathBuf::from("charts.sqlite"),
+            stats_db: PathBuf::from("stats.sqlite"),
+            bans_db: PathBuf::from("bans.sqlite"),
+            room_ids_file: PathBuf::from("last_room_id.txt"),
+            maps_dir: PathBuf::from("maps"),
+        }
+    }
+}
+
+impl PathsConfig {
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        self.data_dir.join(path)
+    }
+}
+
+impl BotConfig {
+    /// 有配置文件时读取配置文件，否则读取环境变量，并检查配置是否有效
+    pub fn load(config_file: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let config = match config_file {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| format!("无法读取配置文件 {}: {}", path.display(), e))?;
+                Self::parse(&text).map_err(|e| format!("配置文件 {} 格式错误: {}", path.display(), e))?
+            }
+            None => Self::from_lookup(|key| std::env::var(key).ok())?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// 兼容旧的 .env 配置，get 为读取环境变量的函数
+    pub fn from_lookup(get: impl Fn(&str) -> Option<String>) -> Result<Self, Box<dyn Error>> {
+        let mut errors = Vec::new();
+        let mut required = |key: &str| {
+            get(key).unwrap_or_else(|| {
+                errors.push(format!("缺少环境变量 {}", key));
+                String::new()
+            })
+        };
+        let nickname = required("IRC_NICKNAME");
+        let password = required("IRC_PASSWORD");
+        let room_name = required("ROOM_NAME");
+        let room_password = required("ROOM_PASSWORD");
+
+        let port = match get("IRC_PORT") {
+            Some(v) => v.parse().unwrap_or_else(|_| {
+                errors.push(format!("IRC_PORT 不是有效的端口: {}", v));
+                0
+            }),
+            None => default_port(),
+        };
+
+        // 第一个房间使用 KEY，其余房间使用 KEY_2、KEY_3 ...
+        let room_var = |key: &str, n: usize| if n == 1 { key.to_string() } else { format!("{}_{}", key, n) };
+        let mut rooms = Vec::new();
+        for n in 1.. {
+            let (name, password) = if n == 1 {
+                (room_name.clone(), room_password.clone())
+            } else {
+                let Some(name) = get(&room_var("ROOM_NAME", n)) else {
+                    break;
+                };
+                (name, get(&room_var("ROOM_PASSWORD", n)).unwrap_or_default())
+            };
+            let var = |key: &str| get(&room_var(key, n));
+            let list = |key: &str| -> Vec<String> {
+                var(key)
+                    .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+                    .unwrap_or_default()
+            };
+            let mut allowed_modes = Vec::new();
+            for mode in list("ALLOWED_MODES") {
+                match pp_calculator::parse_game_mode(&mode) {
+                    Some(mode) => allowed_modes.push(mode),
+                    None => errors.push(format!("{} 中的模式无效: {}", room_var("ALLOWED_MODES", n), mode)),
+                }
+            }
+            let mut number = |key: &str| var(key).and_then(|v| match v.parse() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    errors.push(format!("{} 不是有效的数字: {}", room_var(key, n), v));
+                    None
+                }
+            });
+            let rules = MapRules {
+                min_star: number("MIN_STAR").map(|v: f64| v as f32),
+                max_star: number("MAX_STAR").map(|v: f64| v as f32),
+                max_length: number("MAX_LENGTH").map(|v: f64| v as u64),
+                allowed_status: list("ALLOWED_STATUS"),
+                allowed_modes,
+                max_violations: number("MAX_VIOLATIONS").map(|v: f64| v as u32).unwrap_or(0),
+            };
+            rooms.push(RoomConfig {
+                name,
+                password,
+                game_mode: var("GAME_MODE").unwrap_or_else(default_game_mode),
+                rules: Some(rules),
+                rotation: var("ROTATION").unwrap_or_else(default_rotation),
+                lang: var("BOT_LANG").unwrap_or_else(default_lang),
+            });
+        }
+        if !errors.is_empty() {
+            return Err(format!("配置错误:\n  {}", errors.join("\n  ")).into());
+        }
+
+        let defaults = OsuApiConfig::default();
+        Ok(BotConfig {
+            connection: ConnectionConfig {
+                server: get("IRC_SERVER").unwrap_or_else(default_server),
+                port,
+                use_tls: get("IRC_USE_TLS").is_some_and(|v| v == "true" || v == "1"),
+                nickname,
+                password,
+            },
+            osu_api: OsuApiConfig {
+                client_id: get("OSU_CLIENT_ID").unwrap_or_default(),
+                client_secret: get("OSU_CLIENT_SECRET").unwrap_or_default(),
+                base_url: get("OSU_API_BASE_URL").unwrap_or(defaults.base_url),
+                mirror_url: get("BEATMAP_MIRROR_URL").unwrap_or(defaults.mirror_url),
+                record_dir: get("OSU_API_RECORD").map(PathBuf::from),
+                fixture_dir: get("OSU_API_FIXTURES").map(PathBuf::from),
+            },
+            rooms,
+            rules: MapRules::default(),
+            votes: VotesConfig::default(),
+            messages: Messages::default(),
+            auto_start: AutoStartConfig::default(),
+            afk_host: AfkConfig::default(),
+            outbox: OutboxConfig::default(),
+            paths: PathsConfig {
+                data_dir: get("DATA_DIR").map(PathBuf::from).unwrap_or_else(|| PathBuf::from(".")),
+                ..PathsConfig::default()
+            },
+            admins: get("ADMINS").map(|v| split_names(&v)).unwrap_or_default(),
+            operators: get("OPERATORS").map(|v| split_names(&v)).unwrap_or_default(),
+        })
+    }
+
+    /// 检查所有配置项，一次列出全部错误
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let mut errors = Vec::new();
+        let connection = &self.connection;
+        if connection.server.trim().is_empty() {
+            errors.push("connection.server 不能为空".to_string());
+        }
+        if connection.port == 0 {
+            errors.push("connection.port 不能为 0".to_string());
+        }
+        if connection.nickname.trim().is_empty() {
+            errors.push("connection.nickname 不能为空".to_string());
+        }
+        if connection.password.trim().is_empty() {
+            errors.push("connection.password 不能为空".to_string());
+        }
+        if self.osu_api.fixture_dir.is_none()
+            && (self.osu_api.client_id.is_empty() || self.osu_api.client_secret.is_empty()) {
+            errors.push("osu_api.client_id 和 osu_api.client_secret 不能为空".to_string());
+        }
+
+        if self.rooms.is_empty() {
+            errors.push("至少需要配置一个房间".to_string());
+        }
+        for (i, room) in self.rooms.iter().enumerate() {
+            let name = format!("room[{}]", i + 1);
+            if room.name.trim().is_empty() {
+                errors.push(format!("{}.name 不能为空", name));
+            } else if self.rooms[..i].iter().any(|other| other.name == room.name) {
+                errors.push(format!("{}.name 与前面的房间重名: {}", name, room.name));
+            }
+            if pp_calculator::parse_game_mode(&room.game_mode).is_none() {
+                errors.push(format!("{}.game_mode 无效: {} (可选 osu/taiko/catch/mania)", name, room.game_mode));
+            }
+            if RotationMode::parse(&room.rotation).is_none() {
+                let modes: Vec<&str> = RotationMode::ALL.iter().map(|m| m.name()).collect();
+                errors.push(format!("{}.rotation 无效: {} (可选 {})", name, room.rotation, modes.join("/")));
+            }
+            if Lang::parse(&room.lang).is_none() {
+                errors.push(format!("{}.lang 无效: {} (可选 {})", name, room.lang, Lang::codes()));
+            }
+            if let Some(rules) = &room.rules {
+                validate_rules(rules, &format!("{}.rules", name), &mut errors);
+            }
+        }
+        validate_rules(&self.rules, "rules", &mut errors);
+
+        if !(self.votes.ratio > 0.0 && self.votes.ratio <= 1.0) {
+            errors.push(format!("votes.ratio 必须在 0 到 1 之间: {}", self.votes.ratio));
+        }
+        if self.outbox.rate > 0.0 && self.outbox.burst == 0 {
+            errors.push("outbox.burst 不能为 0".to_string());
+        }
+        if self.afk_host.warn_after > 0 && self.afk_host.skip_after <= self.afk_host.warn_after {
+            errors.push(format!("afk_host.skip_after ({}) 必须大于 warn_after ({})", self.afk_host.skip_after, self.afk_host.warn_after));
+        }
+        for kind in VoteKind::ALL {
+            if let Some(ratio) = self.votes.rule_config(kind).ratio
+                && !(ratio > 0.0 && ratio <= 1.0) {
+                errors.push(format!("votes.{}.ratio 必须在 0 到 1 之间: {}", kind.name(), ratio));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("配置错误:\n  {}", errors.join("\n  ")).into())
+        }
+    }
+
+    pub fn irc_config(&self) -> IrcConfig {
+        IrcConfig {
+            nickname: Some(self.connection.nickname.clone()),
+            server: Some(self.connection.server.clone()),
+            port: Some(self.connection.port),
+            password: Some(self.connection.password.clone()),
+            use_tls: Some(self.connection.use_tls),
+            ..IrcConfig::default()
+        }
+    }
+
+    pub fn bot_settings(&self, config_file: Option<PathBuf>) -> BotSettings {
+        BotSettings {
+            rooms: self.rooms.iter().map(|room| self.room_settings(room)).collect(),
+            paths: self.paths.clone(),
+            admins: self.admins.clone(),
+            operators: self.operators.clone(),
+            outbox: self.outbox.clone(),
+            config_file,
+        }
+    }
+
+    fn room_settings(&self, room: &RoomConfig) -> RoomSettings {
+        RoomSettings {
+            room_name: room.name.clone(),
+            room_password: room.password.clone(),
+            rules: room.rules.clone().unwrap_or_else(|| self.rules.clone()),
+            game_mode: pp_calculator::parse_game_mode(&room.game_mode).unwrap_or(0),
+            votes: self.votes.vote_rules(),
+            messages: self.messages.clone(),
+            auto_start: self.auto_start.clone(),
+            afk_host: self.afk_host.clone(),
+            rotation: RotationMode::parse(&room.rotation).unwrap_or(RotationMode::RoundRobin),
+            lang: Lang::parse(&room.lang).unwrap_or_default(),
+        }
+    }
+}
+
+// 环境变量中以逗号分隔的昵称列表
+fn split_names(value: &str) -> Vec<String> {
+    value.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+}
+
+fn validate_rules(rules: &MapRules, name: &str, errors: &mut Vec<String>) {
+    if let (Some(min), Some(max)) = (rules.min_star, rules.max_star)
+        && min > max {
+        errors.push(format!("{}.min_star ({}) 大于 max_star ({})", name, min, max));
+    }
+    if rules.min_star.is_some_and(|v| v < 0.0) || rules.max_star.is_some_and(|v| v < 0.0) {
+        errors.push(format!("{} 的星数不能为负数", name));
+    }
+    if let Some(mode) = rules.allowed_modes.iter().find(|mode| **mode > 3) {
+        errors.push(format!("{}.allowed_modes 中的模式无效: {}", name, mode));
+    }
+}
+
+/// 等待 SIGHUP 信号，非 unix 平台上不会触发，只能用 !reload 重新加载
+pub struct ReloadSignal {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl ReloadSignal {
+    pub fn new() -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Self { signal: signal(SignalKind::hangup()).ok() }
+        }
+        #[cfg(not(unix))]
+        Self {}
+    }
+
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const EXAMPLE: &str = include_str!("../config.example.toml");
+
+    #[test]
+    fn test_parse_example_config() {
+        let config = BotConfig::parse(EXAMPLE).unwrap();
+        config.validate().unwrap();
+
+        let settings = config.bot_settings(None);
+        assert_eq!(settings.rooms.len(), 2);
+        assert_eq!(settings.rooms[0].rules.max_star, Some(6.5));
+        // 第二个房间单独设置了规则和模式
+        assert_eq!(settings.rooms[1].rules.max_star, None);
+        assert_eq!(settings.rooms[1].game_mode, 3);
+        // [votes.close] 只覆盖写了的项
+        assert_eq!(settings.rooms[0].votes.close.min_voters, 2);
+        assert_eq!(settings.rooms[0].votes.close.timeout, Some(Duration::from_secs(120)));
+        assert_eq!(settings.rooms[0].votes.skip.ratio, 0.5);
+        assert_eq!(config.irc_config().server.as_deref(), Some("irc.ppy.sh"));
+    }
+
+    #[test]
+    fn test_validate_reports_all_errors() {
+        let config = BotConfig::parse(r#"
+            admins = []
+            [connection]
+            nickname = ""
+            password = "x"
+            port = 0
+            [osu_api]
+            fixture_dir = "fixtures"
+            [rules]
+            min_star = 7.0
+            max_star = 5.0
+            [votes]
+            ratio = 1.5
+            [[room]]
+            name = "a"
+            game_mode = "piano"
+            [[room]]
+            name = "a"
+        "#).unwrap();
+        let errors = config.validate().unwrap_err().to_string();
+        for expected in [
+            "connection.port", "connection.nickname", "room[1].game_mode", "room[2].name 与前面的房间重名",
+            "rules.min_star", "votes.ratio",
+        ] {
+            assert!(errors.contains(expected), "missing {:?} in {}", expected, errors);
+        }
+        assert!(!errors.contains("client_id"));
+
+        assert!(BotConfig::parse("[connection]\nnickname = \"a\"\npassword = \"b\"\nunknown = 1").is_err());
+    }
+
+    #[test]
+    fn test_env_config_uses_server_and_port() {
+        let vars: HashMap<&str, &str> = [
+            ("IRC_NICKNAME", "ATRI"),
+            ("IRC_PASSWORD", "secret"),
+            ("IRC_SERVER", "localhost"),
+            ("IRC_PORT", "6668"),
+            ("ROOM_NAME", "room"),
+            ("ROOM_PASSWORD", "123"),
+            ("ROOM_NAME_2", "room 2"),
+            ("MAX_STAR_2", "6.5"),
+            ("ALLOWED_MODES_2", "0, mania"),
+            ("OSU_CLIENT_ID", "1"),
+            ("OSU_CLIENT_SECRET", "s"),
+        ].into_iter().collect();
+        let config = BotConfig::from_lookup(|key| vars.get(key).map(|v| v.to_string())).unwrap();
+        config.validate().unwrap();
+        let irc = config.irc_config();
+        assert_eq!((irc.server.as_deref(), irc.port), (Some("localhost"), Some(6668)));
+        assert_eq!(config.rooms.len(), 2);
+        assert_eq!(config.rooms[1].rules.as_ref().unwrap().max_star, Some(6.5));
+        assert_eq!(config.rooms[1].rules.as_ref().unwrap().allowed_modes, vec![0, 3]);
+
+        let errors = BotConfig::from_lookup(|key| match key {
+            "IRC_PORT" => Some("abc".to_string()),
+            "ALLOWED_MODES" => Some("0,7".to_string()),
+            _ => None,
+        }).unwrap_err().to_string();
+        assert!(errors.contains("缺少环境变量 IRC_NICKNAME"), "{}", errors);
+        assert!(errors.contains("IRC_PORT 不是有效的端口"), "{}", errors);
+        assert!(errors.contains("ALLOWED_MODES 中的模式无效: 7"), "{}", errors);
+    }
+}
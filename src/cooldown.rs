@@ -0,0 +1,223 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::permission::player_key;
+
+/// 违规次数在这段时间内没有增加时清零
+const STRIKE_RESET: Duration = Duration::from_secs(60);
+
+/// 单个指令的限制，在 commands.rs 的指令表中设置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    /// 同一玩家两次使用该指令的最短间隔，0 表示不限制
+    pub cooldown_secs: u64,
+    /// 第几次违规开始私聊警告，之前的违规直接忽略
+    pub warn_at: u32,
+    /// 第几次违规开始禁止使用 bot 指令
+    pub mute_at: u32,
+    pub mute_secs: u64,
+}
+
+impl Limit {
+    pub const NONE: Limit = Limit { cooldown_secs: 0, warn_at: 2, mute_at: 5, mute_secs: 300 };
+
+    pub const fn cooldown(seconds: u64) -> Self {
+        Limit { cooldown_secs: seconds, ..Limit::NONE }
+    }
+
+    /// 会调用 osu! api 的指令，违规时更早禁言
+    pub const fn api(seconds: u64) -> Self {
+        Limit { cooldown_secs: seconds, warn_at: 1, mute_at: 3, mute_secs: 600 }
+    }
+}
+
+/// 一段时间内最多使用几次指令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Burst {
+    pub count: usize,
+    pub window_secs: u64,
+}
+
+/// 检查的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// 不回应
+    Ignore,
+    /// 指令还在冷却，seconds 秒后可以使用
+    Cooldown { seconds: u64 },
+    /// 该玩家短时间内发了太多指令
+    Burst,
+    /// 本次违规导致禁言，seconds 秒内不回应该玩家的指令
+    Mute { seconds: u64 },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Strikes {
+    count: u32,
+    last: Instant,
+}
+
+/// 按玩家和指令记录使用时间，超出限制时逐步升级为忽略、警告、禁言
+#[derive(Debug)]
+pub struct CommandLimiter {
+    user_burst: Burst,
+    global_burst: Burst,
+    last_used: HashMap<(String, &'static str), Instant>,
+    user_recent: HashMap<String, VecDeque<Instant>>,
+    global_recent: VecDeque<Instant>,
+    strikes: HashMap<String, Strikes>,
+    muted_until: HashMap<String, Instant>,
+}
+
+impl CommandLimiter {
+    pub fn new(user_burst: Burst, global_burst: Burst) -> Self {
+        Self {
+            user_burst,
+            global_burst,
+            last_used: HashMap::new(),
+            user_recent: HashMap::new(),
+            global_recent: VecDeque::new(),
+            strikes: HashMap::new(),
+            muted_until: HashMap::new(),
+        }
+    }
+
+    /// 检查 player 现在能否使用 command，允许时记录这次使用
+    pub fn check(&mut self, player: &str, command: &'static str, limit: Limit, now: Instant) -> Verdict {
+        let player = player_key(player);
+        if let Some(until) = self.muted_until.get(&player) {
+            if now < *until {
+                return Verdict::Ignore;
+            }
+            self.muted_until.remove(&player);
+        }
+
+        // 所有人的指令加起来太多时不回应，也不算作个人违规
+        prune(&mut self.global_recent, now, self.global_burst.window_secs);
+        if self.global_recent.len() >= self.global_burst.count {
+            return Verdict::Ignore;
+        }
+
+        let key = (player.clone(), command);
+        let cooldown = Duration::from_secs(limit.cooldown_secs);
+        let waiting = self.last_used.get(&key)
+            .map(|last| cooldown.saturating_sub(now.saturating_duration_since(*last)))
+            .filter(|left| !left.is_zero());
+        let recent = self.user_recent.entry(player.clone()).or_default();
+        prune(recent, now, self.user_burst.window_secs);
+        let violation = match waiting {
+            Some(left) => Some(Verdict::Cooldown { seconds: left.as_secs_f64().ceil() as u64 }),
+            None if recent.len() >= self.user_burst.count => Some(Verdict::Burst),
+            None => None,
+        };
+
+        let Some(warning) = violation else {
+            recent.push_back(now);
+            self.global_recent.push_back(now);
+            self.last_used.insert(key, now);
+            self.prune_idle(now);
+            return Verdict::Allow;
+        };
+        let strikes = self.strikes.entry(player.clone()).or_insert(Strikes { count: 0, last: now });
+        if now.saturating_duration_since(strikes.last) >= STRIKE_RESET {
+            strikes.count = 0;
+        }
+        strikes.count += 1;
+        strikes.last = now;
+        if strikes.count >= limit.mute_at {
+            self.strikes.remove(&player);
+            self.muted_until.insert(player, now + Duration::from_secs(limit.mute_secs));
+            Verdict::Mute { seconds: limit.mute_secs }
+        } else if strikes.count >= limit.warn_at {
+            warning
+        } else {
+            Verdict::Ignore
+        }
+    }
+
+    /// 被禁言的玩家的指令不做任何回应
+    pub fn is_muted(&self, player: &str, now: Instant) -> bool {
+        self.muted_until.get(&player_key(player)).is_some_and(|until| now < *until)
+    }
+
+    /// 管理员解除禁言时使用，返回该玩家是否被禁言
+    pub fn unmute(&mut self, player: &str) -> bool {
+        let player = player_key(player);
+        self.strikes.remove(&player);
+        self.muted_until.remove(&player).is_some()
+    }
+
+    /// 去掉已经不影响结果的记录，避免长时间运行后占用过多内存
+    fn prune_idle(&mut self, now: Instant) {
+        if self.last_used.len() < 1024 {
+            return;
+        }
+        // 冷却不会超过一小时
+        self.last_used.retain(|_, last| now.saturating_duration_since(*last) < Duration::from_secs(3600));
+        let window = Duration::from_secs(self.user_burst.window_secs);
+        self.user_recent.retain(|_, recent| recent.back().is_some_and(|last| now.saturating_duration_since(*last) < window));
+        self.strikes.retain(|_, strikes| now.saturating_duration_since(strikes.last) < STRIKE_RESET);
+        self.muted_until.retain(|_, until| now < *until);
+    }
+}
+
+fn prune(recent: &mut VecDeque<Instant>, now: Instant, window_secs: u64) {
+    while recent.front().is_some_and(|time| now.saturating_duration_since(*time) >= Duration::from_secs(window_secs)) {
+        recent.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cooldown_burst_and_escalation() {
+        let now = Instant::now();
+        let secs = |s: u64| now + Duration::from_secs(s);
+        let mut limiter = CommandLimiter::new(Burst { count: 3, window_secs: 10 }, Burst { count: 5, window_secs: 10 });
+        let limit = Limit { cooldown_secs: 5, warn_at: 2, mute_at: 3, mute_secs: 60 };
+
+        // 冷却中的第一次违规忽略，第二次警告，第三次禁言
+        assert_eq!(limiter.check("Bob", "!re", limit, now), Verdict::Allow);
+        assert_eq!(limiter.check("Bob", "!re", limit, secs(1)), Verdict::Ignore);
+        assert_eq!(limiter.check("bob", "!re", limit, secs(2)), Verdict::Cooldown { seconds: 3 });
+        assert_eq!(limiter.check("Bob", "!info", limit, secs(2)), Verdict::Allow);
+        assert_eq!(limiter.check("Bob", "!re", limit, secs(3)), Verdict::Mute { seconds: 60 });
+        assert_eq!(limiter.check("Bob", "!top", limit, secs(30)), Verdict::Ignore);
+        assert!(limiter.is_muted("bob", secs(30)) && !limiter.is_muted("Bob", secs(63)));
+        assert_eq!(limiter.check("Bob", "!top", limit, secs(63)), Verdict::Allow);
+
+        // 其他玩家不受影响，每人 10 秒内最多 3 条
+        let free = Limit::NONE;
+        for (i, command) in ["!a", "!b", "!c"].into_iter().enumerate() {
+            assert_eq!(limiter.check("Carol", command, free, secs(100 + i as u64)), Verdict::Allow);
+        }
+        assert_eq!(limiter.check("Carol", "!d", free, secs(103)), Verdict::Ignore);
+        assert_eq!(limiter.check("Carol", "!d", free, secs(104)), Verdict::Burst);
+        assert_eq!(limiter.check("Carol", "!d", free, secs(110)), Verdict::Allow);
+
+        // 所有人 10 秒内最多 5 条，超出时不回应也不算违规
+        let mut limiter = CommandLimiter::new(Burst { count: 3, window_secs: 10 }, Burst { count: 5, window_secs: 10 });
+        for name in ["A", "B", "C", "D", "E"] {
+            assert_eq!(limiter.check(name, "!a", free, now), Verdict::Allow);
+        }
+        for _ in 0..3 {
+            assert_eq!(limiter.check("F", "!a", free, secs(1)), Verdict::Ignore);
+        }
+        assert_eq!(limiter.check("F", "!a", free, secs(10)), Verdict::Allow);
+
+        assert!(limiter.check("Dan", "!re", Limit { mute_at: 1, ..limit }, secs(200)) == Verdict::Allow);
+        assert_eq!(limiter.check("Dan", "!re", Limit { mute_at: 1, ..limit }, secs(201)), Verdict::Mute { seconds: 60 });
+        assert!(limiter.unmute("dan") && !limiter.unmute("dan"));
+
+        // 禁言和解除禁言时名字里的空格和下划线视为相同
+        assert_eq!(limiter.check("Some_Player", "!re", Limit { mute_at: 1, ..limit }, secs(300)), Verdict::Allow);
+        assert_eq!(limiter.check("Some_Player", "!re", Limit { mute_at: 1, ..limit }, secs(301)), Verdict::Mute { seconds: 60 });
+        assert!(limiter.is_muted("some player", secs(302)));
+        assert!(limiter.unmute("Some Player"));
+        assert_eq!(limiter.check("Dan", "!info", limit, secs(202)), Verdict::Allow);
+    }
+}
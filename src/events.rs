@@ -1,188 +1,375 @@
 use crate::bot::MyBot;
+use crate::commands::handle_private_command;
+use crate::room::Room;
 use std::error::Error;
-use regex::Regex;
-use crate::pp_calculator::PPCalculator;
+use crate::bancho::{self, BanchoEvent, SlotInfo};
+use crate::bans;
+use crate::osu_api::{Beatmap, OsuApiClient};
+use crate::pp_calculator::{self, PPCalculator};
+use crate::stats::MatchRecord;
 use std::path::Path;
+use std::time::Duration;
 
+/// 对局结束后查询 pp 最多尝试几次，每次间隔 RESULT_PP_RETRY
+const RESULT_PP_ATTEMPTS: u32 = 3;
+const RESULT_PP_RETRY: Duration = Duration::from_secs(5);
 
-pub async fn handle_event(bot: &mut MyBot, sender:&str, msg: &str) -> Result<(), Box<dyn Error>> {
+
+pub async fn handle_event(bot: &mut MyBot, room: &mut Room, sender:&str, msg: &str) -> Result<(), Box<dyn Error>> {
     // 如果不是 BanchoBot 的消息，直接忽略
     if sender != "BanchoBot" {
         return Ok(());
     }
-    
-    match msg {
-        m if m.contains("Created the tournament match") => {
-            handle_create_room(bot, m).await?;
+
+    let Some(event) = bancho::parse(msg) else {
+        return Ok(());
+    };
+
+    match event {
+        BanchoEvent::BeatmapChanged { beatmap_id, .. } => {
+            handle_beatmap_change(bot, room, beatmap_id).await?;
+        }
+        BanchoEvent::CurrentBeatmap { beatmap_id, .. } => {
+            handle_current_beatmap(bot, room, beatmap_id).await?;
         }
-        m if m.contains("Beatmap") || m.contains("beatmap") => {
-            handle_beatmap_change(bot, m).await?;
+        BanchoEvent::AllPlayersReady => {
+            handle_match_ready(room).await?;
         }
-        m if m.contains("All players are ready") => {
-            handle_match_ready(bot).await?;
+        BanchoEvent::MatchStarted => {
+            handle_match_start(room).await?;
         }
-        m if m.contains("The match has started") => {
-            handle_match_start(bot).await?;
+        BanchoEvent::MatchFinished => {
+            handle_match_finish(bot, room).await?;
         }
-        m if m.contains("The match has finished") => {
-            handle_match_finish(bot).await?;
+        BanchoEvent::PlayerFinished { name, score, passed } => {
+            room.record_result(name, score, passed);
         }
-        m if m.contains("Aborted the match") => {
-            handle_match_abort(bot).await?;
+        BanchoEvent::MatchAborted => {
+            handle_match_abort(room).await?;
         }
-        m if m.contains("joined in slot") => {
-            handle_player_join(bot, m).await?;
+        BanchoEvent::PlayerJoined { name, .. } => {
+            handle_player_join(bot, room, name).await?;
         }
-        m if m.contains("left the game") => {
-            handle_player_leave(bot, m).await?;
+        BanchoEvent::PlayerLeft { name } => {
+            handle_player_leave(room, &name).await?;
         }
-        m if m.starts_with("Slot") => {
-            handle_slot(bot, m).await?;
+        // 被踢出时 BanchoBot 可能还会发 left the game，只处理一次
+        BanchoEvent::PlayerKicked { name } if room.player_list.contains(&name) => {
+            handle_player_leave(room, &name).await?;
+        }
+        BanchoEvent::Slot(slot) => {
+            handle_slot(room, slot).await?;
+        }
+        BanchoEvent::ActiveMods { mods } => {
+            handle_active_mods(room, &mods).await?;
+        }
+        BanchoEvent::ModsChanged { enabled, disabled } => {
+            handle_mods_change(room, &enabled, &disabled).await?;
         }
         _ => {}
     }
     Ok(())
 }
 
-async fn handle_create_room(bot: &mut MyBot, msg: &str) -> Result<(), Box<dyn Error>> {
-    parse_room_id(bot, msg).await?;
-    bot.join_channel(&format!("#mp_{}", *bot.room_id.lock().await)).await?;
-    bot.set_room_password(bot.room_password.clone()).await?;
-    bot.save_room_id_to_file().await?;
+/// 处理房间频道以外的消息：BanchoBot 私聊发来的建房回复，以及管理员私聊的指令
+pub async fn handle_private_event(bot: &mut MyBot, sender: &str, msg: &str) -> Result<(), Box<dyn Error>> {
+    if sender != "BanchoBot" {
+        if msg.starts_with('!') || msg.starts_with('！') {
+            handle_private_command(bot, sender, msg).await?;
+        }
+        return Ok(());
+    }
+
+    if let Some(BanchoEvent::RoomCreated { room_id, name }) = bancho::parse(msg) {
+        handle_create_room(bot, room_id, &name).await?;
+    }
     Ok(())
 }
 
+async fn handle_create_room(bot: &mut MyBot, new_room_id: u32, name: &str) -> Result<(), Box<dyn Error>> {
+    let Some(mut room) = bot.rooms.take_pending_by_name(name) else {
+        println!("Created room {} is not managed by this bot: {}", new_room_id, name);
+        return Ok(());
+    };
+    room.room_id = new_room_id;
+    println!("Room ID set to: {}", new_room_id);
+    room.join().await?;
+    room.set_room_password(room.room_password.clone()).await?;
+    bot.rooms.insert(room);
+    bot.save_room_ids_to_file()?;
+    Ok(())
+}
 
-async fn parse_room_id(bot: &mut MyBot, msg: &str) -> Result<(), Box<dyn Error>> {
-    let re = Regex::new(r"https://osu\.ppy\.sh/mp/(\d+)")?;
-    if let Some(captures) = re.captures(msg) {
-        if let Some(id) = captures.get(1) {
-            let new_room_id = id.as_str().parse::<u32>()?;
-            {
-                let mut room_id = bot.room_id.lock().await;
-                *room_id = new_room_id;
-            }
-            println!("Room ID set to: {}", new_room_id);
-        }
-    }
-    Ok(())
-}
-
-async fn handle_beatmap_change(bot: &mut MyBot, msg: &str) -> Result<(), Box<dyn Error>> {
-    let re = Regex::new(r"https://osu\.ppy\.sh/b/(\d+)")?;
-    if let Some(captures) = re.captures(msg) {
-        if let Some(id) = captures.get(1) {
-            bot.beatmap_id = id.as_str().parse::<u32>()?;
-            println!("Beatmap ID changed to: {}", bot.beatmap_id);
-            
-            // 获取谱面信息
-            let beatmap = bot.osu_api.get_beatmap_info(bot.beatmap_id).await?;
-
-            // 写入一些数据
-            bot.beatmap_length = beatmap.total_length;
-            bot.beatmap_difficulty_rating = beatmap.difficulty_rating;
-            bot.beatmap_title_unicode = beatmap.beatmapset.title_unicode.clone();
-            bot.beatmap_artist_unicode = beatmap.beatmapset.artist_unicode.clone();
-
-            bot.beatmap_info = beatmap.get_formatted_info();
-            
-            bot.send_beatmap_info().await?;
-            
-            // 下载谱面
-            bot.osu_api.download_beatmap(bot.beatmap_id).await?;
-
-            // 更新 beatmap_path
-            bot.beatmap_path = format!("./maps/{}.osu", bot.beatmap_id);
-            
-            // 检查文件是否存在
-            if !Path::new(&bot.beatmap_path).exists() {
-                println!("Beatmap file not found: {}", bot.beatmap_path);
-                // 这里可以添加下载谱面的逻辑，或者发送一条消息说明谱面文件不存在
-                bot.send_message(&format!("#mp_{}", *bot.room_id.lock().await), "Beatmap file not found. Unable to calculate PP.").await?;
-                return Ok(());
-            }
+async fn handle_beatmap_change(bot: &mut MyBot, room: &mut Room, beatmap_id: u32) -> Result<(), Box<dyn Error>> {
+    // 违规后 bot 换回的谱面，只恢复状态
+    if room.pending_revert == Some(beatmap_id) {
+        room.pending_revert = None;
+        return handle_current_beatmap(bot, room, beatmap_id).await;
+    }
 
-            // 如果文件存在，继续处理
-            bot.pp_calculator = PPCalculator::new(bot.beatmap_path.clone());
+    // 获取谱面信息
+    let beatmap = bot.osu_api.get_beatmap_info(beatmap_id).await?;
 
-            let mods = 0;
-            let (stars, max_pp, pp_95_fc, pp_96_fc, pp_97_fc, pp_98_fc, pp_99_fc) = bot.pp_calculator.calculate_beatmap_details(mods)?;
+    // 检查房间的选图规则
+    if let Some(reason) = room.rules.check(&beatmap, room.lang) {
+        handle_rule_violation(room, beatmap_id, &reason).await?;
+        return Ok(());
+    }
+    room.last_valid_beatmap_id = Some(beatmap_id);
+    room.beatmap_rejected = false;
+    room.host_watch.reset(tokio::time::Instant::now());
 
-            let beatmap_pp_info = format!("Stars: {:.2} | 95%: {:.2}pp | 96%: {:.2}pp | 97%: {:.2}pp | 98%: {:.2}pp | 99%: {:.2}pp | Max: {:.2}pp", 
-                                  stars, pp_95_fc, pp_96_fc, pp_97_fc, pp_98_fc, pp_99_fc, max_pp);
+    apply_beatmap(room, &beatmap);
+    println!("Beatmap ID changed to: {}", room.beatmap_id);
 
-            bot.beatmap_pp_info = beatmap_pp_info;
+    room.send_beatmap_info().await?;
+    room.schedule_auto_start().await?;
 
-            bot.send_message(&format!("#mp_{}", *bot.room_id.lock().await), &bot.beatmap_pp_info).await?;
-        }
+    if !load_beatmap_file(bot, room).await? {
+        // 这里可以添加下载谱面的逻辑，或者发送一条消息说明谱面文件不存在
+        room.send_message(&room.lang.t("room.beatmap_file_missing", &[])).await?;
+        return Ok(());
+    }
+    send_pp_info(room).await?;
+    Ok(())
+}
+
+/// !mp settings 返回的当前谱面，重连或重新加入房间时也会收到。
+/// 只恢复谱面状态，不检查规则、不发消息，也不重新开始挂机和自动开始的计时
+async fn handle_current_beatmap(bot: &mut MyBot, room: &mut Room, beatmap_id: u32) -> Result<(), Box<dyn Error>> {
+    if room.beatmap_id == beatmap_id && Path::new(&room.beatmap_path).exists() {
+        return Ok(());
     }
+    let beatmap = bot.osu_api.get_beatmap_info(beatmap_id).await?;
+    room.last_valid_beatmap_id.get_or_insert(beatmap_id);
+    apply_beatmap(room, &beatmap);
+    println!("Restored beatmap: {}", room.beatmap_id);
+    load_beatmap_file(bot, room).await?;
     Ok(())
 }
 
-async fn handle_slot(bot: &mut MyBot, msg: &str) -> Result<(), Box<dyn Error>> {
-    let re = Regex::new(r"Slot \d+\s+(?:Not Ready|Ready)\s+https://osu\.ppy\.sh/u/\d+\s+(.+?)(?:\s+\[.+?\])?$")?;
+fn apply_beatmap(room: &mut Room, beatmap: &Beatmap) {
+    room.beatmap_id = beatmap.id;
+    room.beatmap_length = beatmap.total_length;
+    room.beatmap_difficulty_rating = beatmap.difficulty_rating;
+    room.beatmap_bpm = beatmap.bpm;
+    // std 谱面按房间的模式转换计算，其他模式的谱面只能按原模式计算
+    room.beatmap_mode = if beatmap.mode_int == 0 { room.game_mode } else { beatmap.mode_int };
+    room.beatmap_title_unicode = beatmap.beatmapset.title_unicode.clone();
+    room.beatmap_artist_unicode = beatmap.beatmapset.artist_unicode.clone();
+    room.beatmap_info = beatmap.get_formatted_info(room.lang);
+}
+
+/// 下载当前谱面并准备 pp 计算，谱面文件不存在时返回 false
+async fn load_beatmap_file(bot: &mut MyBot, room: &mut Room) -> Result<bool, Box<dyn Error>> {
+    room.beatmap_path = bot.beatmap_path(room.beatmap_id);
+    // 下载失败时和谱面不存在一样处理，让玩家知道无法计算 pp
+    if let Err(e) = bot.osu_api.download_beatmap(room.beatmap_id, Path::new(&room.beatmap_path)).await {
+        println!("Failed to download beatmap {}: {}", room.beatmap_id, e);
+    }
+    if !Path::new(&room.beatmap_path).exists() {
+        println!("Beatmap file not found: {}", room.beatmap_path);
+        return Ok(false);
+    }
+    room.pp_calculator = PPCalculator::new(room.beatmap_path.clone(), room.beatmap_mode);
+    Ok(true)
+}
+
+// 按房间当前的 mod 计算并发送 pp 信息
+async fn send_pp_info(room: &mut Room) -> Result<(), Box<dyn Error>> {
+    if !Path::new(&room.beatmap_path).exists() {
+        return Ok(());
+    }
+    let details = room.pp_calculator.calculate_beatmap_details(room.room_mods)?;
+
+    room.beatmap_pp_info = if room.room_mods == 0 {
+        details.format()
+    } else {
+        let (length, bpm) = room.length_and_bpm_with_mods(room.room_mods);
+        format!("+{} | {}s {:.0}bpm | {}", pp_calculator::mods_to_string(room.room_mods), length, bpm, details.format())
+    };
+    if room.beatmap_mode != 0 {
+        room.beatmap_pp_info = format!("[{}] {}", pp_calculator::mode_name(room.beatmap_mode), room.beatmap_pp_info);
+    }
+
+    room.send_message(&room.beatmap_pp_info).await?;
+    Ok(())
+}
 
-    if let Some(captures) = re.captures(msg) {
-        if let Some(player_name) = captures.get(1) {
-            let player_name = player_name.as_str().trim().to_string();
-            bot.add_player(player_name.clone());
-            println!("Added player from slot: {}", player_name);
+async fn handle_rule_violation(room: &mut Room, beatmap_id: u32, reason: &str) -> Result<(), Box<dyn Error>> {
+    println!("Beatmap {} rejected: {}", beatmap_id, reason);
+    let rules = room.rules.describe(room.lang);
+    room.send_message(&room.lang.t("host.rule_violation", &[("reason", &reason), ("rules", &rules)])).await?;
+    match room.last_valid_beatmap_id {
+        Some(previous) => {
+            room.pending_revert = Some(previous);
+            room.set_map(previous as i32).await?;
         }
+        // 没有可以换回的谱面时违规的谱面留在房间里，换图前不允许开始
+        None => room.beatmap_rejected = true,
+    }
+
+    room.host_violations += 1;
+    if room.rules.max_violations > 0 && room.host_violations >= room.rules.max_violations {
+        room.send_message(&room.lang.t("host.violations_skip", &[("player", &room.room_host)])).await?;
+        room.rotate_host().await?;
     }
-    
     Ok(())
 }
 
-async fn handle_match_ready(bot: &mut MyBot) -> Result<(), Box<dyn Error>> {
-    bot.start_game().await?;
+async fn handle_active_mods(room: &mut Room, mods: &[String]) -> Result<(), Box<dyn Error>> {
+    if room.set_active_mods(mods) {
+        println!("Room mods: {}", pp_calculator::mods_to_string(room.room_mods));
+        send_pp_info(room).await?;
+    }
     Ok(())
 }
 
-async fn handle_match_start(bot: &mut MyBot) -> Result<(), Box<dyn Error>> {
-    bot.beatmap_start_time = Some(std::time::Instant::now());
-    bot.is_game_started = true;
+async fn handle_mods_change(room: &mut Room, enabled: &[String], disabled: &[String]) -> Result<(), Box<dyn Error>> {
+    if room.apply_mods_change(enabled, disabled) {
+        println!("Room mods: {}", pp_calculator::mods_to_string(room.room_mods));
+        send_pp_info(room).await?;
+    }
+    Ok(())
+}
+
+async fn handle_slot(room: &mut Room, slot: SlotInfo) -> Result<(), Box<dyn Error>> {
+    room.add_player(slot.name.clone());
+    println!("Added player from slot: {}", slot.name);
+    Ok(())
+}
+
+async fn handle_match_ready(room: &mut Room) -> Result<(), Box<dyn Error>> {
+    room.start_game().await?;
+    Ok(())
+}
+
+async fn handle_match_start(room: &mut Room) -> Result<(), Box<dyn Error>> {
+    if room.beatmap_rejected {
+        room.send_message(&room.lang.t("host.map_rejected", &[])).await?;
+        room.abort_game().await?;
+        return Ok(());
+    }
+    room.beatmap_start_time = Some(std::time::Instant::now());
+    room.is_game_started = true;
+    room.auto_start = None;
+    room.host_watch.pause();
+    room.record_pick();
+    room.match_results.clear();
     println!("Match started");
     Ok(())
 }
 
-async fn handle_match_finish(bot: &mut MyBot) -> Result<(), Box<dyn Error>> {
-    bot.beatmap_end_time = Some(std::time::Instant::now());
-    bot.is_game_started = false;
+async fn handle_match_finish(bot: &mut MyBot, room: &mut Room) -> Result<(), Box<dyn Error>> {
+    room.beatmap_end_time = Some(std::time::Instant::now());
+    room.is_game_started = false;
     // 清理投票列表
-    bot.cleanup_after_match().await?;
+    room.cleanup_after_match().await?;
     println!("Match finished");
-    if is_fully_played(bot) {
-        bot.rotate_host().await?;
+    // 查询 pp 需要访问 api，在后台完成后再记录并发出结果
+    let results = std::mem::take(&mut room.match_results);
+    if !results.is_empty() {
+        let record = MatchRecord {
+            room_id: room.room_id,
+            beatmap_id: room.beatmap_id,
+            beatmap_name: format!("{} - {}", room.beatmap_title_unicode, room.beatmap_artist_unicode),
+            mods: pp_calculator::mods_to_string(room.room_mods),
+            host: room.room_host.clone(),
+            results,
+        };
+        bot.fetch_result_pp(room.channel(), record, room.beatmap_path.clone());
+    }
+    room.host_watch.reset(tokio::time::Instant::now());
+    room.record_map_length(room.beatmap_length);
+    if is_fully_played(room) {
+        let host = room.room_host.clone();
+        room.host_watch.clear_strikes(&host);
+        room.rotate_host().await?;
     }
      // 这里实现参考下文的房主退出逻辑的补足
-    if !bot.player_list.contains(&bot.room_host) {
-        bot.rotate_host().await?
+    if !room.player_list.contains(&room.room_host) {
+        room.rotate_host().await?
     }
-    bot.send_queue().await?;
+    room.send_queue().await?;
     Ok(())
 }
 
-async fn handle_match_abort(bot: &mut MyBot) -> Result<(), Box<dyn Error>> {
-    bot.beatmap_end_time = Some(std::time::Instant::now());
-    bot.is_game_started = false;
+async fn handle_match_abort(room: &mut Room) -> Result<(), Box<dyn Error>> {
+    room.beatmap_end_time = Some(std::time::Instant::now());
+    room.is_game_started = false;
     // 清理投票列表
-    bot.cleanup_after_match().await?;
+    room.cleanup_after_match().await?;
+    room.match_results.clear();
     println!("Match aborted");
-    if is_fully_played(bot) {
-        bot.rotate_host().await?;
+    room.host_watch.reset(tokio::time::Instant::now());
+    // 违规谱面被 bot 中止的对局不算打完
+    if !room.beatmap_rejected && is_fully_played(room) {
+        room.rotate_host().await?;
     }
     // 这里实现参考下文的房主退出逻辑的补足
-    if !bot.player_list.contains(&bot.room_host) {
-        bot.rotate_host().await?
+    if !room.player_list.contains(&room.room_host) {
+        room.rotate_host().await?
     }
-    bot.send_queue().await?;
+    room.send_queue().await?;
     Ok(())
 }
 
-fn is_fully_played(bot: &MyBot) -> bool {
-    let played_len = bot.beatmap_end_time.unwrap_or_else(|| std::time::Instant::now()).duration_since(bot.beatmap_start_time.unwrap_or_else(|| std::time::Instant::now())).as_secs();
+// 通过 api 取回每位 pass 玩家在该谱面的最近成绩，用 PPCalculator 计算 pp。
+// 刚结束的成绩可能还没出现在 api 中，没找到的玩家隔一段时间重试
+pub async fn fetch_result_pp(mut osu_api: Box<dyn OsuApiClient>, beatmap_path: String, mut record: MatchRecord) -> MatchRecord {
+    let mut pending: Vec<usize> = (0..record.results.len()).filter(|i| record.results[*i].passed).collect();
+    for attempt in 1..=RESULT_PP_ATTEMPTS {
+        if attempt > 1 {
+            tokio::time::sleep(RESULT_PP_RETRY).await;
+        }
+        let mut missing = Vec::new();
+        for i in pending {
+            let result = &mut record.results[i];
+            let score = match osu_api.get_user_info(result.name.clone()).await {
+                Ok(user) => osu_api.get_user_recent_score(user.id, false).await,
+                Err(e) => Err(e),
+            };
+            match score {
+                Ok(Some(score)) if score.beatmap.id == record.beatmap_id => {
+                    result.accuracy = Some(score.accuracy);
+                    let state = score.statistics.to_score_state(score.mode_int, &score.mods, score.max_combo);
+                    match PPCalculator::calculate_pp(&beatmap_path, &state) {
+                        Ok(score_pp) => result.pp = Some(score_pp.pp),
+                        Err(e) => println!("Failed to calculate pp for {}: {}", result.name, e),
+                    }
+                }
+                Ok(_) => {
+                    println!("No recent score on current beatmap for {} (attempt {})", result.name, attempt);
+                    missing.push(i);
+                }
+                Err(e) => println!("Failed to get recent score for {}: {}", result.name, e),
+            }
+        }
+        pending = missing;
+        if pending.is_empty() {
+            break;
+        }
+    }
+    record
+}
+
+/// 后台查询完成后记录对局并把结果发到房间，房间已关闭时只记录
+pub async fn handle_results_ready(bot: &mut MyBot, channel: &str, mut record: MatchRecord) -> Result<(), Box<dyn Error>> {
+    if let Err(e) = bot.stats_db.record_match(&record) {
+        println!("Failed to record match: {}", e);
+    }
+    let Some(mut room) = bot.rooms.take(channel) else {
+        return Ok(());
+    };
+    let result = room.send_match_results(&mut record.results).await;
+    bot.rooms.insert(room);
+    result
+}
+
+fn is_fully_played(room: &Room) -> bool {
+    let played_len = room.beatmap_end_time.unwrap_or_else(std::time::Instant::now).duration_since(room.beatmap_start_time.unwrap_or_else(std::time::Instant::now)).as_secs();
     
-    let half_length = bot.beatmap_length / 2;
+    let half_length = room.beatmap_length / 2;
 
     match played_len.cmp(&half_length) {
         std::cmp::Ordering::Greater => println!("Played length: {}s > {}s (1/2 beatmap_length)", played_len, half_length),
@@ -192,50 +379,59 @@ fn is_fully_played(bot: &MyBot) -> bool {
     played_len >= half_length
 }
 
-async fn handle_player_join(bot: &mut MyBot, msg: &str) -> Result<(), Box<dyn Error>> {
-    let re = Regex::new(r"(.+) joined in slot \d+")?;
-    if let Some(captures) = re.captures(msg) {
-        if let Some(name) = captures.get(1) {
-            let player_name = name.as_str().to_string();
-            bot.add_player(player_name.clone());
-            bot.send_welcome(player_name.clone()).await?;
-            bot.save_latest_info_to_file().expect("无法写入bot state");
-            println!("Player joined: {}", player_name);
-            // 检查玩家是不是房间里面的第一个加入的
-            if bot.player_list.len() == 1 {
-                // 如果之前为空，将当前玩家设为主机
-                bot.set_host(&player_name).await?;
-                println!("Set {} as host (first player)", player_name);
-                bot.set_free_mod().await?;
-                println!("Set FreeMod");
-            }
-            println!("Player list: {:?}", bot.player_list);
-            println!("Host list {:?}", bot.room_host_list);
+async fn handle_player_join(bot: &mut MyBot, room: &mut Room, player_name: String) -> Result<(), Box<dyn Error>> {
+    match bot.ban_list.active_ban(&player_name, bans::now()) {
+        Ok(Some(ban)) => {
+            println!("Banned player joined: {}", player_name);
+            room.send_message(&format!("!mp kick {}", player_name)).await?;
+            let ban = ban.format(bans::now(), room.lang);
+            room.send_message(&room.lang.t("ban.kicked", &[("player", &player_name), ("ban", &ban)])).await?;
+            return Ok(());
         }
+        Ok(None) => {}
+        Err(e) => println!("Failed to check ban of {}: {}", player_name, e),
     }
+    room.add_player(player_name.clone());
+    room.send_welcome(player_name.clone()).await?;
+    if let Err(e) = room.save_latest_info_to_file() {
+        println!("Failed to save state of {}: {}", room.channel(), e);
+    }
+    println!("Player joined: {}", player_name);
+    // 检查玩家是不是房间里面的第一个加入的
+    if room.player_list.len() == 1 {
+        // 如果之前为空，将当前玩家设为主机，不设房主的房间由 bot 选图
+        if room.rotation.bot_picks() {
+            room.bot_pick_requested = true;
+        } else {
+            room.set_host(&player_name).await?;
+            println!("Set {} as host (first player)", player_name);
+        }
+        room.set_free_mod().await?;
+        println!("Set FreeMod");
+    }
+    println!("Player list: {:?}", room.player_list);
+    println!("Host list {:?}", room.host_queue.players());
     Ok(())
 }
 
-async fn handle_player_leave(bot: &mut MyBot, msg: &str) -> Result<(), Box<dyn Error>> {
-    let re = Regex::new(r"(.+) left the game")?;
-    if let Some(captures) = re.captures(msg) {
-        if let Some(name) = captures.get(1) {
-            bot.remove_player(name.as_str());
-            // 判断是否是房主离开 是的话要rotate
-            // 还需充分考虑match状态 前文的finish和abort状态如果触发了 可能会导致两次rotate
-            // ok还需要考虑如果start以后没玩完abort了然后再保持原房主 但是此时无法进一步rotate所以这一部分如果考虑会导致超级冲突
-            // 转而考虑abort部分
-            if name.as_str() == bot.room_host && bot.is_game_started == false{
-                bot.rotate_host().await?;
-            }
-            bot.save_latest_info_to_file().expect("无法写入bot state");
-            println!("Player left: {}", name.as_str());
-            println!("Player list: {:?}", bot.player_list);
-            println!("Host list {:?}", bot.room_host_list);
-        }
+async fn handle_player_leave(room: &mut Room, name: &str) -> Result<(), Box<dyn Error>> {
+    room.remove_player(name);
+    room.remove_voter(name).await?;
+    if name == room.room_host {
+        room.host_watch.add_strike(name);
     }
+    // 判断是否是房主离开 是的话要rotate
+    // 还需充分考虑match状态 前文的finish和abort状态如果触发了 可能会导致两次rotate
+    // ok还需要考虑如果start以后没玩完abort了然后再保持原房主 但是此时无法进一步rotate所以这一部分如果考虑会导致超级冲突
+    // 转而考虑abort部分
+    if name == room.room_host && !room.is_game_started {
+        room.rotate_host().await?;
+    }
+    if let Err(e) = room.save_latest_info_to_file() {
+        println!("Failed to save state of {}: {}", room.channel(), e);
+    }
+    println!("Player left: {}", name);
+    println!("Player list: {:?}", room.player_list);
+    println!("Host list {:?}", room.host_queue.players());
     Ok(())
 }
-
-
-
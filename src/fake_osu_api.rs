@@ -0,0 +1,106 @@
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::osu_api::{self, Beatmap, OsuApiClient, RecentScoreResponse, UserData, UserScore};
+
+/// 不访问网络的 osu! api，响应来自预先设置的 json 或录制的 fixture 目录
+#[derive(Default, Clone)]
+pub struct FakeOsuApi {
+    fixture_dir: Option<PathBuf>,
+    responses: HashMap<String, String>,
+    /// 按顺序记录收到的请求路径
+    pub requests: Vec<String>,
+}
+
+impl FakeOsuApi {
+    #[cfg(test)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 回放 OsuApi 录制模式保存的 fixture
+    pub fn from_dir(dir: PathBuf) -> Self {
+        Self { fixture_dir: Some(dir), ..Self::default() }
+    }
+
+    /// 设置某个 api 路径的响应，优先于 fixture 目录
+    #[cfg(test)]
+    pub fn insert_response(&mut self, path: String, json: String) {
+        self.responses.insert(path, json);
+    }
+
+    fn get_json<T: DeserializeOwned>(&mut self, path: &str) -> Result<T, Box<dyn Error>> {
+        self.requests.push(path.to_string());
+        let body = match self.responses.get(path) {
+            Some(body) => body.clone(),
+            None => {
+                let file = self.fixture_dir.as_deref()
+                    .map(|dir| osu_api::fixture_file(dir, path))
+                    .filter(|file| file.exists())
+                    .ok_or_else(|| format!("没有 {} 的 fixture", path))?;
+                std::fs::read_to_string(file)?
+            }
+        };
+        Ok(serde_json::from_str(&body)?)
+    }
+}
+
+#[async_trait(?Send)]
+impl OsuApiClient for FakeOsuApi {
+    async fn get_user_info(&mut self, irc_name: String) -> Result<UserData, Box<dyn Error>> {
+        self.get_json(&osu_api::user_info_path(&irc_name))
+    }
+
+    async fn get_beatmap_info(&mut self, beatmap_id: u32) -> Result<Beatmap, Box<dyn Error>> {
+        self.get_json(&osu_api::beatmap_info_path(beatmap_id))
+    }
+
+    async fn download_beatmap(&mut self, beatmap_id: u32, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.requests.push(format!("mirror/{}", beatmap_id));
+        if path.exists() {
+            return Ok(());
+        }
+        let file = self.fixture_dir.as_deref()
+            .map(|dir| osu_api::fixture_beatmap_file(dir, beatmap_id))
+            .filter(|file| file.exists())
+            .ok_or_else(|| format!("没有谱面 {} 的 fixture", beatmap_id))?;
+        osu_api::save_beatmap(path, &std::fs::read(file)?)
+    }
+
+    async fn get_user_score(&mut self, user_id: u32, beatmap_id: u32) -> Result<UserScore, Box<dyn Error>> {
+        self.get_json(&osu_api::user_score_path(user_id, beatmap_id))
+    }
+
+    async fn get_user_recent_score(&mut self, user_id: u32, include_fails: bool) -> Result<Option<RecentScoreResponse>, Box<dyn Error>> {
+        let scores: Vec<RecentScoreResponse> = self.get_json(&osu_api::user_recent_score_path(user_id, include_fails))?;
+        Ok(scores.into_iter().next())
+    }
+
+    fn background_client(&self) -> Box<dyn OsuApiClient> {
+        Box::new(Self { requests: Vec::new(), ..self.clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fake_responses() {
+        let mut api = FakeOsuApi::new();
+        api.insert_response(osu_api::user_info_path("peppy"), r#"{"id":2,"username":"peppy"}"#.to_string());
+        api.insert_response(osu_api::user_recent_score_path(2, true), "[]".to_string());
+
+        assert_eq!(api.get_user_info("peppy".to_string()).await.unwrap().id, 2);
+        assert!(api.get_user_recent_score(2, true).await.unwrap().is_none());
+        assert!(api.get_user_info("nobody".to_string()).await.is_err());
+        assert_eq!(api.requests, vec![
+            "/api/v2/users/peppy",
+            "/api/v2/users/2/scores/recent?include_fails=1&limit=1",
+            "/api/v2/users/nobody",
+        ]);
+    }
+}
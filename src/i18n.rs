@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::LazyLock;
+
+const ZH_CN: &str = include_str!("../locales/zh-CN.toml");
+const EN: &str = include_str!("../locales/en.toml");
+
+static CATALOG_ZH_CN: LazyLock<HashMap<String, String>> = LazyLock::new(|| load(ZH_CN));
+static CATALOG_EN: LazyLock<HashMap<String, String>> = LazyLock::new(|| load(EN));
+
+/// bot 发出的消息使用的语言，文案在 locales/ 目录下
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    ZhCn,
+    En,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 2] = [Lang::ZhCn, Lang::En];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::ZhCn => "zh-CN",
+            Lang::En => "en",
+        }
+    }
+
+    /// 接受 zh-CN、zh、en 等写法，不区分大小写
+    pub fn parse(code: &str) -> Option<Self> {
+        match code.trim().to_lowercase().replace('_', "-").as_str() {
+            "zh-cn" | "zh" | "cn" => Some(Lang::ZhCn),
+            "en" | "en-us" | "en-gb" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    /// 以空格分隔的全部语言代码，用于提示
+    pub fn codes() -> String {
+        Self::ALL.iter().map(|lang| lang.code()).collect::<Vec<_>>().join(" ")
+    }
+
+    /// 取出 key 对应的文案并替换 {name} 占位符。当前语言缺少该文案时使用中文，都没有时返回 key
+    pub fn t(self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        fill(self.lookup(key).unwrap_or(key), args)
+    }
+
+    /// 不替换占位符，两种语言都没有该文案时返回 None
+    pub fn lookup(self, key: &str) -> Option<&'static str> {
+        self.catalog().get(key)
+            .or_else(|| CATALOG_ZH_CN.get(key))
+            .map(String::as_str)
+    }
+
+    fn catalog(self) -> &'static HashMap<String, String> {
+        match self {
+            Lang::ZhCn => &CATALOG_ZH_CN,
+            Lang::En => &CATALOG_EN,
+        }
+    }
+}
+
+/// 把 toml 的表展开成 `表名.键` 形式的 key
+fn load(source: &str) -> HashMap<String, String> {
+    fn flatten(prefix: &str, table: &toml::Table, catalog: &mut HashMap<String, String>) {
+        for (key, value) in table {
+            let key = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+            match value {
+                toml::Value::String(text) => {
+                    catalog.insert(key, text.clone());
+                }
+                toml::Value::Table(table) => flatten(&key, table, catalog),
+                _ => panic!("内置的语言文件中 {} 不是字符串", key),
+            }
+        }
+    }
+
+    let table: toml::Table = source.parse().expect("内置的语言文件格式错误");
+    let mut catalog = HashMap::new();
+    flatten("", &table, &mut catalog);
+    catalog
+}
+
+/// 替换 {name}，没有对应参数的占位符原样保留
+fn fill(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}')
+            .and_then(|end| args.iter().find(|(name, _)| *name == &after[..end]).map(|(_, value)| (end, value)));
+        match value {
+            Some((end, value)) => {
+                result.push_str(&value.to_string());
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn placeholders(template: &str) -> BTreeSet<&str> {
+        template.split('{').skip(1).filter_map(|part| part.split_once('}')).map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn test_catalogs_and_placeholders() {
+        assert_eq!(Lang::ZhCn.t("queue.moved", &[("player", &"Bob"), ("position", &2)]), "Bob 移动到了第 2 位");
+        assert_eq!(Lang::En.t("queue.moved", &[("player", &"Bob"), ("position", &2)]), "Bob moved to position 2");
+        // 缺少参数时保留占位符，缺少文案时返回 key
+        assert_eq!(fill("{a} {b} {", &[("a", &1)]), "1 {b} {");
+        assert_eq!(Lang::En.t("no.such.key", &[]), "no.such.key");
+        assert_eq!(Lang::parse("ZH_cn"), Some(Lang::ZhCn));
+        assert_eq!(Lang::parse("fr"), None);
+
+        // 两种语言的 key 和占位符必须一致
+        for (key, zh) in CATALOG_ZH_CN.iter() {
+            let en = CATALOG_EN.get(key).unwrap_or_else(|| panic!("en 缺少 {}", key));
+            assert_eq!(placeholders(zh), placeholders(en), "{} 的占位符不一致", key);
+        }
+        assert_eq!(CATALOG_ZH_CN.len(), CATALOG_EN.len());
+    }
+}
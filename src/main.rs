@@ -1,22 +1,103 @@
+mod afk;
+mod auto_start;
+mod bans;
 mod bot;
 mod commands;
 mod config;
+mod cooldown;
 mod pp_calculator;
 mod osu_api;
+mod outbox;
+mod fake_osu_api;
+mod transport;
+#[cfg(test)]
+mod simulator;
 mod events;
+mod i18n;
+mod bancho;
+mod results;
+mod permission;
+mod queue;
+mod room;
+mod rotation;
+mod rules;
+mod split;
+mod vote;
 
 mod charts;
+mod stats;
 
+use afk::AfkConfig;
+use auto_start::AutoStartConfig;
 use bot::MyBot;
-use config::get_config;
+use config::{BotConfig, Messages, OsuApiConfig, PathsConfig};
 use std::env;
 use dotenv::dotenv;
-use crate::charts::ChartDatabase;
+use rotation::RotationMode;
+use rules::MapRules;
+use vote::VoteRules;
+use fake_osu_api::FakeOsuApi;
+use i18n::Lang;
+use osu_api::{OsuApi, OsuApiClient};
+use outbox::OutboxConfig;
+use std::path::{Path, PathBuf};
+use transport::IrcTransport;
 
-// bot设置
-pub struct BotSettings {
+// 单个房间的设置
+#[derive(Clone)]
+pub struct RoomSettings {
     pub room_name:String,
     pub room_password:String,
+    pub rules: MapRules,
+    /// 0 osu / 1 taiko / 2 catch / 3 mania
+    pub game_mode: u32,
+    /// 各种投票的通过条件
+    pub votes: VoteRules,
+    pub messages: Messages,
+    pub auto_start: AutoStartConfig,
+    pub afk_host: AfkConfig,
+    pub rotation: RotationMode,
+    /// 房间消息的语言
+    pub lang: Lang,
+}
+
+// bot设置
+pub struct BotSettings {
+    pub rooms: Vec<RoomSettings>,
+    /// 状态文件、房间ID文件和数据库的位置
+    pub paths: PathsConfig,
+    pub admins: Vec<String>,
+    pub operators: Vec<String>,
+    /// 发送限速
+    pub outbox: OutboxConfig,
+    /// 启动时使用的配置文件，为空时配置来自环境变量，不能重新加载
+    pub config_file: Option<PathBuf>,
+}
+
+// fixture_dir 指定时离线回放 fixture，不访问 osu! api；record_dir 指定时把响应录制到该目录
+fn build_osu_api(config: &OsuApiConfig) -> Box<dyn OsuApiClient> {
+    if let Some(dir) = &config.fixture_dir {
+        println!("Using osu! api fixtures from {}", dir.display());
+        return Box::new(FakeOsuApi::from_dir(dir.clone()));
+    }
+    let api = OsuApi::new(
+        config.client_id.clone(),
+        config.client_secret.clone(),
+        config.base_url.clone(),
+        config.mirror_url.clone(),
+    );
+    match &config.record_dir {
+        Some(dir) => Box::new(api.with_recording(dir.clone())),
+        None => Box::new(api),
+    }
+}
+
+// CONFIG_FILE 指定的文件，或运行目录下的 config.toml，都不存在时使用 .env
+fn config_file() -> Option<PathBuf> {
+    match env::var("CONFIG_FILE") {
+        Ok(path) => Some(PathBuf::from(path)),
+        Err(_) => Path::new(config::DEFAULT_CONFIG_FILE).exists().then(|| PathBuf::from(config::DEFAULT_CONFIG_FILE)),
+    }
 }
 
 #[tokio::main]
@@ -24,16 +105,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // 加载 .env 文件
     dotenv().ok();
 
-    let config = get_config()?;
-    
-    let botsettings = BotSettings{
-        room_name: env::var("ROOM_NAME").expect("ROOM_NAME must be set in .env file"),
-        room_password: env::var("ROOM_PASSWORD").expect("ROOM_PASSWORD must be set in .env file"),
-    };    
-    let client_id = env::var("OSU_CLIENT_ID").expect("OSU_CLIENT_ID must be set in .env file");
-    let client_secret = env::var("OSU_CLIENT_SECRET").expect("OSU_CLIENT_SECRET must be set in .env file");
-    
-    let mut bot = MyBot::new(config, client_id, client_secret,botsettings).await?;
+    let config_file = config_file();
+    let config = match BotConfig::load(config_file.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let transport = Box::new(IrcTransport::new(config.irc_config()));
+    let osu_api = build_osu_api(&config.osu_api);
+    let bot_name = config.connection.nickname.clone();
+    let mut bot = MyBot::new(bot_name, transport, osu_api, config.bot_settings(config_file)).await?;
     bot.run().await?;
     
     Ok(())
@@ -1,16 +1,44 @@
 use reqwest::Client;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use chrono::{DateTime, Utc};
 use std::fs::File;
 use std::io::Write;
-use std::path::Path;
-
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::i18n::Lang;
+use crate::pp_calculator::{self, ScoreState};
+
+pub const OSU_BASE_URL: &str = "https://osu.ppy.sh";
+pub const BEATMAP_MIRROR_URL: &str = "https://osu.direct/api/osu/";
+// token 剩余有效期不足这个时间时提前刷新
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Clone)]
 pub struct OsuApi {
     client: Client,
+    base_url: String,
+    mirror_url: String,
     client_id: String,
     client_secret: String,
-    access_token: Option<String>,
+    /// 与 background_client 共用，任何一方刷新后双方都使用新的 token
+    access_token: Arc<Mutex<Option<AccessToken>>>,
+    record_dir: Option<PathBuf>,
+}
+
+#[derive(Clone)]
+struct AccessToken {
+    token: String,
+    expires_at: Instant,
+}
+
+impl AccessToken {
+    fn is_fresh(&self) -> bool {
+        Instant::now() + TOKEN_REFRESH_MARGIN < self.expires_at
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -55,18 +83,7 @@ pub struct Beatmap {
 #[derive(Deserialize, Debug)]
 pub struct BeatmapForRecentScore {
     pub id: u32,
-    pub beatmapset_id: u32,
-    pub status: String,
-    pub total_length: u64,
-    pub version: String,
     pub difficulty_rating: f32,
-    pub accuracy: f32,
-    pub ar: f32,
-    pub bpm: f32,
-    pub cs: f32,
-    pub drain: f32,
-    pub mode_int: u32,
-    pub url: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -81,8 +98,6 @@ pub struct Beatmapset {
 
 #[derive(Deserialize, Debug)]
 pub struct BeatmapsetForRecentScore {
-    pub artist: String,
-    pub title: String,
     pub title_unicode: String,
     pub artist_unicode: String,
 }
@@ -95,19 +110,12 @@ pub struct UserScore {
 #[derive(Deserialize, Debug)]
 pub struct Score {
     pub accuracy: f64,
-    pub best_id: Option<u64>,
     pub created_at: String,
-    pub id: u64,
     pub max_combo: u32,
-    pub mode: String,
     pub mode_int: u8,
     pub mods: Vec<String>,
-    pub passed: bool,
-    pub perfect: bool,
     pub pp: Option<f32>,
     pub rank: String,
-    pub replay: bool,
-    pub score: u64,
     pub statistics: ScoreStatistics,
 }
 
@@ -124,31 +132,54 @@ pub struct ScoreStatistics {
 #[derive(Deserialize, Debug)]
 pub struct RecentScoreResponse {
     pub accuracy: f64,
-    pub best_id: Option<u64>,
     pub created_at: String,
-    pub id: u64,
     pub max_combo: u32,
-    pub mode: String,
     pub mode_int: u8,
     pub mods: Vec<String>,
     pub passed: bool,
     pub perfect: bool,
     pub pp: Option<f32>,
     pub rank: String,
-    pub replay: bool,
-    pub score: u64,
     pub statistics: ScoreStatistics,
     pub beatmap: BeatmapForRecentScore,
     pub beatmapset: BeatmapsetForRecentScore,
 }
 
+impl ScoreStatistics {
+    pub fn to_score_state(&self, mode: u8, mods: &[String], max_combo: u32) -> ScoreState {
+        ScoreState {
+            mode: mode as u32,
+            mods: pp_calculator::mods_from_acronyms(mods),
+            max_combo,
+            n_geki: self.count_geki.unwrap_or(0),
+            n300: self.count_300,
+            n_katu: self.count_katu.unwrap_or(0),
+            n100: self.count_100,
+            n50: self.count_50,
+            misses: self.count_miss,
+        }
+    }
+
+    /// 按游戏模式显示判定，例如 mania 为 MAX/300/200/100/50/miss
+    pub fn format(&self, mode: u8) -> String {
+        let geki = self.count_geki.unwrap_or(0);
+        let katu = self.count_katu.unwrap_or(0);
+        match mode {
+            1 => format!("{}/{}/{}", self.count_300, self.count_100, self.count_miss),
+            2 => format!("fruits:{} drops:{} droplets:{} miss:{}", self.count_300, self.count_100, self.count_50, self.count_miss),
+            3 => format!("{}/{}/{}/{}/{}/{}", geki, self.count_300, katu, self.count_100, self.count_50, self.count_miss),
+            _ => format!("{}/{}/{}/{}", self.count_300, self.count_100, self.count_50, self.count_miss),
+        }
+    }
+}
+
 impl User {
     pub fn new(irc_name: String, id: u32, username: String) -> Self {
         Self { irc_name, id, username }
     }
 
-    pub async fn update(&mut self, osu_api: &mut OsuApi) -> Result<(), Box<dyn Error>> {
-        let userdata = osu_api.get_user_info(self.irc_name.clone()).await.unwrap();
+    pub async fn update(&mut self, osu_api: &mut dyn OsuApiClient) -> Result<(), Box<dyn Error>> {
+        let userdata = osu_api.get_user_info(self.irc_name.clone()).await?;
         self.id = userdata.id;
         self.username = userdata.username;
         Ok(())
@@ -156,7 +187,7 @@ impl User {
 }
 
 impl Beatmap {
-    pub fn get_formatted_info(&self) -> String {
+    pub fn get_formatted_info(&self, lang: Lang) -> String {
         let date = self.beatmapset.ranked_date
             .as_deref()
             .or(Some(&self.beatmapset.submitted_date))
@@ -165,18 +196,26 @@ impl Beatmap {
                     .ok()
                     .map(|dt| dt.with_timezone(&Utc).format("%Y-%m-%d").to_string())
             })
-            .unwrap_or_else(|| "未知日期".to_string());
+            .unwrap_or_else(|| lang.t("common.unknown_date", &[]));
 
         let length_seconds = self.total_length;
         let osudirect_url = self.url.clone();
         let sayo_url = format!("https://osu.sayobot.cn/home?search={}", self.beatmapset_id);
         let inso_url = format!("http://inso.link/yukiho/?b={}", self.beatmapset_id);
 
+        // 各模式只显示有意义的难度参数，mania 的 cs 即键数
+        let attributes = match self.mode_int {
+            1 => format!("od:{} hp:{}", self.accuracy, self.drain),
+            2 => format!("ar:{} cs:{} hp:{}", self.ar, self.cs, self.drain),
+            3 => format!("{}K od:{} hp:{}", self.cs, self.accuracy, self.drain),
+            _ => format!("ar:{} cs:{} od:{} hp:{}", self.ar, self.cs, self.accuracy, self.drain),
+        };
+
         format!(
-            "{} {}| {}*| [{} {} - {}]| bpm:{} length:{}s| ar:{} cs:{} od:{} hp:{}| [{} Sayobot] OR [{} inso]",
+            "{} {}| {}*| [{} {} - {}]| bpm:{} length:{}s| {}| [{} Sayobot] OR [{} inso]",
             date, self.status, self.difficulty_rating, osudirect_url,
             self.beatmapset.title_unicode, self.beatmapset.artist_unicode, self.bpm, length_seconds,
-            self.ar, self.cs, self.accuracy, self.drain, sayo_url, inso_url
+            attributes, sayo_url, inso_url
         )
     }
 }
@@ -186,20 +225,20 @@ impl Score {
         format!("https://osu.ppy.sh/b/{}", beatmap_id)
     }
 
-    pub fn format_date(&self) -> String {
+    pub fn format_date(&self, lang: Lang) -> String {
         DateTime::parse_from_rfc3339(&self.created_at)
             .ok()
             .map(|dt| dt.with_timezone(&Utc).format("%Y-%m-%d").to_string())
-            .unwrap_or_else(|| "未知日期".to_string())
+            .unwrap_or_else(|| lang.t("common.unknown_date", &[]))
     }
 }
 
 impl RecentScoreResponse {
-    pub fn format_date(&self) -> String {
+    pub fn format_date(&self, lang: Lang) -> String {
         DateTime::parse_from_rfc3339(&self.created_at)
             .ok()
             .map(|dt| dt.with_timezone(&Utc).format("%Y-%m-%d").to_string())
-            .unwrap_or_else(|| "未知日期".to_string())
+            .unwrap_or_else(|| lang.t("common.unknown_date", &[]))
     }
 
     pub fn format_url(&self) -> String {
@@ -207,17 +246,97 @@ impl RecentScoreResponse {
     }
 }
 
+/// osu! api 的抽象，bot 只通过它访问 api，测试时可以换成 [`FakeOsuApi`](crate::fake_osu_api::FakeOsuApi)
+#[async_trait(?Send)]
+pub trait OsuApiClient {
+    async fn get_user_info(&mut self, irc_name: String) -> Result<UserData, Box<dyn Error>>;
+    async fn get_beatmap_info(&mut self, beatmap_id: u32) -> Result<Beatmap, Box<dyn Error>>;
+    /// 下载 .osu 文件到 path，一般由 [`pp_calculator::beatmap_path`] 得到，已存在时跳过
+    async fn download_beatmap(&mut self, beatmap_id: u32, path: &Path) -> Result<(), Box<dyn Error>>;
+    async fn get_user_score(&mut self, user_id: u32, beatmap_id: u32) -> Result<UserScore, Box<dyn Error>>;
+    async fn get_user_recent_score(&mut self, user_id: u32, include_fails: bool) -> Result<Option<RecentScoreResponse>, Box<dyn Error>>;
+    /// 设置相同、共用 token 的另一个客户端，给不阻塞消息处理的后台任务使用
+    fn background_client(&self) -> Box<dyn OsuApiClient>;
+}
+
+// 各接口相对 base url 的路径，录制的 fixture 也按路径命名
+pub fn user_info_path(irc_name: &str) -> String {
+    format!("/api/v2/users/{}", irc_name)
+}
+
+pub fn beatmap_info_path(beatmap_id: u32) -> String {
+    format!("/api/v2/beatmaps/{}", beatmap_id)
+}
+
+pub fn user_score_path(user_id: u32, beatmap_id: u32) -> String {
+    format!("/api/v2/beatmaps/{}/scores/users/{}", beatmap_id, user_id)
+}
+
+pub fn user_recent_score_path(user_id: u32, include_fails: bool) -> String {
+    format!(
+        "/api/v2/users/{}/scores/recent?include_fails={}&limit=1",
+        user_id,
+        if include_fails { "1" } else { "0" }
+    )
+}
+
+/// fixture 目录下对应 api 路径的文件，例如 /api/v2/beatmaps/1 -> api_v2_beatmaps_1.json
+pub fn fixture_file(dir: &Path, path: &str) -> PathBuf {
+    let name: String = path.trim_start_matches('/')
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+    dir.join(format!("{}.json", name))
+}
+
+/// fixture 目录下谱面 .osu 文件
+pub fn fixture_beatmap_file(dir: &Path, beatmap_id: u32) -> PathBuf {
+    dir.join(format!("mirror_{}.osu", beatmap_id))
+}
+
+/// 把下载的谱面写到 path
+pub fn save_beatmap(path: &Path, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+    // 确保 maps 目录存在
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut file = File::create(path)?;
+    file.write_all(bytes)?;
+    println!("谱面下载并保存到: {}", path.display());
+    Ok(())
+}
+
 impl OsuApi {
-    pub fn new(client_id: String, client_secret: String) -> Self {
+    /// base_url 例如 https://osu.ppy.sh，mirror_url 后面直接拼接谱面 id，例如 https://osu.direct/api/osu/
+    pub fn new(client_id: String, client_secret: String, base_url: String, mirror_url: String) -> Self {
         Self {
             client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            mirror_url,
             client_id,
             client_secret,
-            access_token: None,
+            access_token: Arc::new(Mutex::new(None)),
+            record_dir: None,
         }
     }
 
-    async fn get_token(&mut self) -> Result<(), Box<dyn Error>> {
+    /// 录制模式：把每个成功的响应保存到 dir，之后可以用 FakeOsuApi::from_dir 离线回放
+    pub fn with_recording(mut self, dir: PathBuf) -> Self {
+        self.record_dir = Some(dir);
+        self
+    }
+
+    /// 只在没有 token 或 token 即将过期时才重新认证
+    async fn ensure_token(&mut self) -> Result<String, Box<dyn Error>> {
+        let cached = self.access_token.lock().unwrap().clone().filter(AccessToken::is_fresh);
+        match cached {
+            Some(token) => Ok(token.token),
+            None => self.refresh_token().await,
+        }
+    }
+
+    async fn refresh_token(&mut self) -> Result<String, Box<dyn Error>> {
         let params = [
             ("client_id", &self.client_id),
             ("client_secret", &self.client_secret),
@@ -225,64 +344,72 @@ impl OsuApi {
             ("scope", &"public".to_string()),
         ];
 
-        let res: TokenResponse = self.client
-            .post("https://osu.ppy.sh/oauth/token")
+        let res = self.client
+            .post(format!("{}/oauth/token", self.base_url))
             .form(&params)
             .send()
-            .await?
-            .json()
             .await?;
-
-        self.access_token = Some(res.access_token);
-        Ok(())
+        if !res.status().is_success() {
+            return Err(format!("获取 token 时错误: {:?}", res.status()).into());
+        }
+        let res: TokenResponse = res.json().await?;
+
+        println!("osu! api token refreshed, expires in {}s", res.expires_in);
+        *self.access_token.lock().unwrap() = Some(AccessToken {
+            token: res.access_token.clone(),
+            expires_at: Instant::now() + Duration::from_secs(res.expires_in as u64),
+        });
+        Ok(res.access_token)
     }
 
-    pub async fn get_user_info(&mut self, irc_name: String) -> Result<UserData, Box<dyn Error>> {
-        self.get_token().await?;
-
-        let url = format!("https://osu.ppy.sh/api/v2/users/{}", irc_name);
-        let res = self.client
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", self.access_token.as_ref().unwrap()))
-            .send()
-            .await?;
-        if res.status().is_success() {
-            let userdata: UserData = res.json().await?;
-            Ok(userdata)
-        } else {
-            Err(format!("获取用户信息时错误: {:?}", res.status()).into())
+    /// 带 token 发送 GET 请求，token 失效 (401) 时刷新 token 并重试一次
+    async fn get_authorized(&mut self, path: &str) -> Result<reqwest::Response, Box<dyn Error>> {
+        let url = format!("{}{}", self.base_url, path);
+        let token = self.ensure_token().await?;
+        let res = self.client.get(&url).bearer_auth(token).send().await?;
+        if res.status() != reqwest::StatusCode::UNAUTHORIZED {
+            return Ok(res);
         }
+
+        println!("osu! api token rejected, refreshing");
+        *self.access_token.lock().unwrap() = None;
+        let token = self.refresh_token().await?;
+        Ok(self.client.get(&url).bearer_auth(token).send().await?)
     }
 
-    pub async fn get_beatmap_info(&mut self, beatmap_id: u32) -> Result<Beatmap, Box<dyn Error>> {
-        self.get_token().await?;
+    async fn get_json<T: DeserializeOwned>(&mut self, path: &str, action: &str) -> Result<T, Box<dyn Error>> {
+        let res = self.get_authorized(path).await?;
+        if !res.status().is_success() {
+            return Err(format!("{}时错误: {:?}", action, res.status()).into());
+        }
+        let body = res.text().await?;
+        if let Some(dir) = &self.record_dir {
+            std::fs::create_dir_all(dir)?;
+            std::fs::write(fixture_file(dir, path), &body)?;
+        }
+        Ok(serde_json::from_str(&body)?)
+    }
+}
 
-        let url = format!("https://osu.ppy.sh/api/v2/beatmaps/{}", beatmap_id);
-        let res = self.client
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", self.access_token.as_ref().unwrap()))
-            .send()
-            .await?;
+#[async_trait(?Send)]
+impl OsuApiClient for OsuApi {
+    async fn get_user_info(&mut self, irc_name: String) -> Result<UserData, Box<dyn Error>> {
+        self.get_json(&user_info_path(&irc_name), "获取用户信息").await
+    }
 
-        if res.status().is_success() {
-            let beatmap: Beatmap = res.json().await?;
-            Ok(beatmap)
-        } else {
-            Err(format!("获取谱面时错误: {:?}", res.status()).into())
-        }
+    async fn get_beatmap_info(&mut self, beatmap_id: u32) -> Result<Beatmap, Box<dyn Error>> {
+        self.get_json(&beatmap_info_path(beatmap_id), "获取谱面").await
     }
 
-    pub async fn download_beatmap(&mut self, beatmap_id: u32) -> Result<(), Box<dyn Error>> {
+    async fn download_beatmap(&mut self, beatmap_id: u32, path: &Path) -> Result<(), Box<dyn Error>> {
 
         // 检查文件是否已存在
-        let file_path = format!("./maps/{}.osu", beatmap_id);
-        let path = Path::new(&file_path);
         if path.exists() {
-            println!("谱面已存在: {}", file_path);
+            println!("谱面已存在: {}", path.display());
             return Ok(());
         }
 
-        let url = format!("https://osu.direct/api/osu/{}", beatmap_id);
+        let url = format!("{}{}", self.mirror_url, beatmap_id);
         let res = self.client
             .get(&url)
             .send()
@@ -290,62 +417,215 @@ impl OsuApi {
 
         if res.status().is_success() {
             let bytes = res.bytes().await?;
-            let file_path = format!("./maps/{}.osu", beatmap_id);
-            let path = Path::new(&file_path);
-
-            // 确保 maps 目录存在
-            if let Some(parent) = path.parent() {
-                std::fs::create_dir_all(parent)?;
+            if let Some(dir) = &self.record_dir {
+                std::fs::create_dir_all(dir)?;
+                std::fs::write(fixture_beatmap_file(dir, beatmap_id), &bytes)?;
             }
-
-            let mut file = File::create(path)?;
-            file.write_all(&bytes)?;
-            println!("谱面下载并保存到: {}", file_path);
-            Ok(())
+            save_beatmap(path, &bytes)
         } else {
             Err(format!("下载谱面时错误: {:?}", res.status()).into())
         }
     }
 
-    pub async fn get_user_score(&mut self, user_id: u32, beatmap_id: u32) -> Result<UserScore, Box<dyn Error>> {
-        self.get_token().await?;
-        
-        let url = format!("https://osu.ppy.sh/api/v2/beatmaps/{}/scores/users/{}", beatmap_id, user_id);
-        let res = self.client
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", self.access_token.as_ref().unwrap()))
-            .send()
-            .await?;
+    async fn get_user_score(&mut self, user_id: u32, beatmap_id: u32) -> Result<UserScore, Box<dyn Error>> {
+        self.get_json(&user_score_path(user_id, beatmap_id), "获取成绩").await
+    }
 
-        if res.status().is_success() {
-            let user_score: UserScore = res.json().await?;
-            Ok(user_score)
-        } else {
-            Err(format!("获取成绩时错误: {:?}", res.status()).into())
-        }
+    async fn get_user_recent_score(&mut self, user_id: u32, include_fails: bool) -> Result<Option<RecentScoreResponse>, Box<dyn Error>> {
+        let scores: Vec<RecentScoreResponse> = self.get_json(&user_recent_score_path(user_id, include_fails), "获取成绩").await?;
+        Ok(scores.into_iter().next())
     }
 
-    pub async fn get_user_recent_score(&mut self, user_id: u32, include_fails: bool) -> Result<Option<RecentScoreResponse>, Box<dyn Error>> {
-        self.get_token().await?;
+    fn background_client(&self) -> Box<dyn OsuApiClient> {
+        Box::new(self.clone())
+    }
+}
 
-        let url = format!(
-            "https://osu.ppy.sh/api/v2/users/{}/scores/recent?include_fails={}&limit=1",
-            user_id,
-            if include_fails { "1" } else { "0" }
-        );
-        let res = self.client
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", self.access_token.as_ref().unwrap()))
-            .send()
-            .await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
 
-        if res.status().is_success() {
-            let scores: Vec<RecentScoreResponse> = res.json().await?;
-            Ok(scores.into_iter().next())
-        } else {
-            Err(format!("获取成绩时错误: {:?}", res.status()).into())
-        }
+    #[derive(Debug, Clone)]
+    struct MockRequest {
+        path: String,
+        authorization: Option<String>,
     }
 
-    // Add more API methods as needed
+    type Handler = dyn Fn(&MockRequest, &[MockRequest]) -> (u16, String) + Send + Sync;
+
+    /// 本地的 http 服务器，按 handler 返回状态码和 json，并记录收到的请求
+    async fn mock_server(handler: Arc<Handler>) -> (String, Arc<Mutex<Vec<MockRequest>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).await.unwrap();
+                let mut authorization = None;
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).await.unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let (name, value) = line.split_once(": ").unwrap_or((line, ""));
+                    match name.to_ascii_lowercase().as_str() {
+                        "authorization" => authorization = Some(value.to_string()),
+                        "content-length" => content_length = value.parse().unwrap(),
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).await.unwrap();
+
+                let request = MockRequest {
+                    path: request_line.split_whitespace().nth(1).unwrap_or("").to_string(),
+                    authorization,
+                };
+                let (status, body) = {
+                    let mut log = log.lock().unwrap();
+                    let response = handler(&request, &log);
+                    log.push(request);
+                    response
+                };
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status, body.len(), body
+                );
+                reader.get_mut().write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (base_url, requests)
+    }
+
+    fn token_json(token: &str, expires_in: u32) -> String {
+        format!(r#"{{"access_token":"{}","token_type":"Bearer","expires_in":{}}}"#, token, expires_in)
+    }
+
+    fn token_requests(requests: &Mutex<Vec<MockRequest>>) -> usize {
+        requests.lock().unwrap().iter().filter(|r| r.path == "/oauth/token").count()
+    }
+
+    async fn api_with(handler: Arc<Handler>) -> (OsuApi, Arc<Mutex<Vec<MockRequest>>>) {
+        let (base_url, requests) = mock_server(handler).await;
+        (OsuApi::new("id".to_string(), "secret".to_string(), base_url.clone(), format!("{}/mirror/", base_url)), requests)
+    }
+
+    const USER_JSON: &str = r#"{"id":2,"username":"peppy"}"#;
+
+    #[tokio::test]
+    async fn test_token_is_cached() {
+        let (mut api, requests) = api_with(Arc::new(|req: &MockRequest, _: &[MockRequest]| {
+            if req.path == "/oauth/token" {
+                (200, token_json("t1", 86400))
+            } else {
+                (200, USER_JSON.to_string())
+            }
+        })).await;
+
+        assert_eq!(api.get_user_info("peppy".to_string()).await.unwrap().id, 2);
+        assert_eq!(api.get_user_info("peppy".to_string()).await.unwrap().id, 2);
+        assert_eq!(token_requests(&requests), 1);
+        let requests = requests.lock().unwrap();
+        assert!(requests.iter()
+            .filter(|r| r.path.starts_with("/api/v2"))
+            .all(|r| r.authorization.as_deref() == Some("Bearer t1")));
+    }
+
+    #[tokio::test]
+    async fn test_token_refreshed_near_expiry() {
+        // 有效期短于提前刷新的时间，每次请求前都会重新获取
+        let (mut api, requests) = api_with(Arc::new(|req: &MockRequest, _: &[MockRequest]| {
+            if req.path == "/oauth/token" {
+                (200, token_json("short", 30))
+            } else {
+                (200, USER_JSON.to_string())
+            }
+        })).await;
+
+        api.get_user_info("peppy".to_string()).await.unwrap();
+        api.get_user_info("peppy".to_string()).await.unwrap();
+        assert_eq!(token_requests(&requests), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_refreshes_and_retries_once() {
+        let (mut api, requests) = api_with(Arc::new(|req: &MockRequest, log: &[MockRequest]| {
+            if req.path == "/oauth/token" {
+                let issued = log.iter().filter(|r| r.path == "/oauth/token").count();
+                (200, token_json(&format!("t{}", issued + 1), 86400))
+            } else if req.authorization.as_deref() == Some("Bearer t2") {
+                (200, USER_JSON.to_string())
+            } else {
+                (401, "{}".to_string())
+            }
+        })).await;
+
+        assert_eq!(api.get_user_info("peppy".to_string()).await.unwrap().username, "peppy");
+        assert_eq!(token_requests(&requests), 2);
+        let paths: Vec<_> = requests.lock().unwrap().iter().map(|r| r.path.clone()).collect();
+        assert_eq!(paths, vec!["/oauth/token", "/api/v2/users/peppy", "/oauth/token", "/api/v2/users/peppy"]);
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_retry_is_not_repeated() {
+        let (mut api, requests) = api_with(Arc::new(|req: &MockRequest, _: &[MockRequest]| {
+            if req.path == "/oauth/token" {
+                (200, token_json("bad", 86400))
+            } else {
+                (401, "{}".to_string())
+            }
+        })).await;
+
+        assert!(api.get_user_info("peppy".to_string()).await.is_err());
+        assert_eq!(requests.lock().unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_background_client_shares_token() {
+        let (mut api, requests) = api_with(Arc::new(|req: &MockRequest, log: &[MockRequest]| {
+            if req.path == "/oauth/token" {
+                let issued = log.iter().filter(|r| r.path == "/oauth/token").count();
+                (200, token_json(&format!("t{}", issued + 1), 86400))
+            } else if req.authorization.as_deref() == Some("Bearer t2") {
+                (200, USER_JSON.to_string())
+            } else {
+                (401, "{}".to_string())
+            }
+        })).await;
+
+        // 主客户端因 401 刷新的 token，之前创建的后台客户端也直接使用
+        let mut background = api.background_client();
+        api.get_user_info("peppy".to_string()).await.unwrap();
+        background.get_user_info("peppy".to_string()).await.unwrap();
+        assert_eq!(token_requests(&requests), 2);
+    }
+
+    #[tokio::test]
+    async fn test_recorded_fixtures_replay_offline() {
+        let dir = std::env::temp_dir().join(format!("osu_api_fixtures_{}", std::process::id()));
+        let (api, _) = api_with(Arc::new(|req: &MockRequest, _: &[MockRequest]| {
+            if req.path == "/oauth/token" {
+                (200, token_json("t1", 86400))
+            } else {
+                (200, USER_JSON.to_string())
+            }
+        })).await;
+        let mut api = api.with_recording(dir.clone());
+        api.get_user_info("peppy".to_string()).await.unwrap();
+        assert!(fixture_file(&dir, &user_info_path("peppy")).exists());
+
+        let mut fake = crate::fake_osu_api::FakeOsuApi::from_dir(dir.clone());
+        assert_eq!(fake.get_user_info("peppy".to_string()).await.unwrap().username, "peppy");
+        assert!(fake.get_beatmap_info(1).await.is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
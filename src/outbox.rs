@@ -0,0 +1,237 @@
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::i18n::Lang;
+
+/// 发送限速设置，来自配置文件的 [outbox]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutboxConfig {
+    /// 每秒最多发送几条消息，0 表示不限速
+    pub rate: f64,
+    /// 空闲一段时间后最多可以连续发送几条
+    pub burst: u32,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self { rate: 1.0, burst: 10 }
+    }
+}
+
+/// 等待发送的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outgoing {
+    Privmsg { target: String, text: String },
+    Join(String),
+}
+
+impl Outgoing {
+    /// 加入频道、!mp 指令和发给 BanchoBot 的消息优先于聊天消息
+    pub fn is_command(&self) -> bool {
+        match self {
+            Outgoing::Privmsg { target, text } => text.starts_with("!mp ") || target == "BanchoBot",
+            Outgoing::Join(_) => true,
+        }
+    }
+}
+
+/// 发送队列的统计，管理员可以用 !outbox 查看
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutboxStats {
+    pub commands: usize,
+    pub chat: usize,
+    /// 运行以来队列最长的时候
+    pub max_depth: usize,
+    pub sent: u64,
+    /// 与队列中的消息重复而被合并的条数
+    pub coalesced: u64,
+}
+
+impl OutboxStats {
+    pub fn depth(&self) -> usize {
+        self.commands + self.chat
+    }
+
+    pub fn format(&self, lang: Lang) -> String {
+        lang.t("admin.outbox", &[
+            ("depth", &self.depth()),
+            ("commands", &self.commands),
+            ("chat", &self.chat),
+            ("max_depth", &self.max_depth),
+            ("sent", &self.sent),
+            ("coalesced", &self.coalesced),
+        ])
+    }
+}
+
+/// 队列中的一条消息，过长的消息分成几部分，合并重复消息时按整条比较
+#[derive(Debug)]
+struct Queued {
+    parts: VecDeque<Outgoing>,
+    /// 已经发出一部分的消息不再参与合并
+    started: bool,
+}
+
+/// 按令牌桶限速的发送队列，指令先于聊天发送，同一队列中重复的消息只发一次
+#[derive(Debug)]
+pub struct Outbox {
+    config: OutboxConfig,
+    commands: VecDeque<Queued>,
+    chat: VecDeque<Queued>,
+    tokens: f64,
+    refilled_at: Instant,
+    stats: OutboxStats,
+}
+
+impl Outbox {
+    pub fn new(config: OutboxConfig, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(config.burst),
+            config,
+            commands: VecDeque::new(),
+            chat: VecDeque::new(),
+            refilled_at: now,
+            stats: OutboxStats::default(),
+        }
+    }
+
+    pub fn set_config(&mut self, config: OutboxConfig) {
+        self.tokens = self.tokens.min(f64::from(config.burst));
+        self.config = config;
+    }
+
+    /// 加入队列，过长的消息分成的几部分按顺序一起加入。
+    /// 只有整条消息与还没开始发送的消息相同时才合并并返回 false
+    pub fn push(&mut self, parts: Vec<Outgoing>) -> bool {
+        let Some(first) = parts.first() else {
+            return true;
+        };
+        let queue = if first.is_command() { &mut self.commands } else { &mut self.chat };
+        let parts = VecDeque::from(parts);
+        if queue.iter().any(|queued| !queued.started && queued.parts == parts) {
+            self.stats.coalesced += 1;
+            return false;
+        }
+        queue.push_back(Queued { parts, started: false });
+        self.stats.max_depth = self.stats.max_depth.max(self.depth());
+        true
+    }
+
+    /// 取出下一条消息，没有可用的令牌时返回 None
+    pub fn pop(&mut self, now: Instant) -> Option<Outgoing> {
+        if self.commands.is_empty() && self.chat.is_empty() {
+            return None;
+        }
+        if !self.unlimited() {
+            self.tokens = self.tokens_at(now);
+            self.refilled_at = now;
+            if self.tokens < 1.0 {
+                return None;
+            }
+            self.tokens -= 1.0;
+        }
+        self.stats.sent += 1;
+        let queue = if self.commands.is_empty() { &mut self.chat } else { &mut self.commands };
+        let queued = queue.front_mut()?;
+        let message = queued.parts.pop_front();
+        queued.started = true;
+        if queued.parts.is_empty() {
+            queue.pop_front();
+        }
+        message
+    }
+
+    /// 下一条消息可以发送的时间，队列为空时返回 None
+    pub fn next_send_at(&self, now: Instant) -> Option<Instant> {
+        if self.commands.is_empty() && self.chat.is_empty() {
+            return None;
+        }
+        let tokens = self.tokens_at(now);
+        if self.unlimited() || tokens >= 1.0 {
+            return Some(now);
+        }
+        Some(now + Duration::from_secs_f64((1.0 - tokens) / self.config.rate))
+    }
+
+    pub fn stats(&self) -> OutboxStats {
+        OutboxStats { commands: count_parts(&self.commands), chat: count_parts(&self.chat), ..self.stats }
+    }
+
+    fn depth(&self) -> usize {
+        count_parts(&self.commands) + count_parts(&self.chat)
+    }
+
+    fn unlimited(&self) -> bool {
+        self.config.rate <= 0.0
+    }
+
+    fn tokens_at(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        (self.tokens + elapsed * self.config.rate).min(f64::from(self.config.burst.max(1)))
+    }
+}
+
+fn count_parts(queue: &VecDeque<Queued>) -> usize {
+    queue.iter().map(|queued| queued.parts.len()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(text: &str) -> Outgoing {
+        Outgoing::Privmsg { target: "#mp_1".to_string(), text: text.to_string() }
+    }
+
+    #[test]
+    fn test_rate_limit_priority_and_coalescing() {
+        let now = Instant::now();
+        let secs = |s: f64| now + Duration::from_secs_f64(s);
+        let mut outbox = Outbox::new(OutboxConfig { rate: 2.0, burst: 2 }, now);
+        assert_eq!(outbox.next_send_at(now), None);
+
+        assert!(outbox.push(vec![chat("hello")]));
+        assert!(outbox.push(vec![chat("world")]));
+        assert!(!outbox.push(vec![chat("hello")]));
+        assert!(outbox.push(vec![chat("!mp start")]));
+        assert!(outbox.push(vec![Outgoing::Join("#mp_2".to_string())]));
+        assert_eq!(outbox.stats().depth(), 4);
+
+        // 指令插到聊天前面，令牌用完后需要等待
+        assert_eq!(outbox.pop(now), Some(chat("!mp start")));
+        assert_eq!(outbox.pop(now), Some(Outgoing::Join("#mp_2".to_string())));
+        assert_eq!(outbox.pop(now), None);
+        assert_eq!(outbox.next_send_at(now), Some(secs(0.5)));
+        assert_eq!(outbox.pop(secs(0.5)), Some(chat("hello")));
+        // 已经发出的消息可以再次加入
+        assert!(outbox.push(vec![chat("hello")]));
+        assert_eq!(outbox.pop(secs(0.6)), None);
+        assert_eq!(outbox.pop(secs(10.0)), Some(chat("world")));
+        assert_eq!(outbox.pop(secs(10.0)), Some(chat("hello")));
+
+        let stats = outbox.stats();
+        assert_eq!((stats.depth(), stats.max_depth, stats.sent, stats.coalesced), (0, 4, 5, 1));
+
+        // 不限速时一次全部发出
+        let mut outbox = Outbox::new(OutboxConfig { rate: 0.0, burst: 0 }, now);
+        for i in 0..20 {
+            outbox.push(vec![chat(&i.to_string())]);
+        }
+        assert_eq!(std::iter::from_fn(|| outbox.pop(now)).count(), 20);
+
+        // 长消息中相同的部分不会被合并，整条相同的消息才合并
+        let parts = vec![chat("---"), chat("a"), chat("---")];
+        assert!(outbox.push(parts.clone()));
+        assert!(!outbox.push(parts.clone()));
+        assert!(outbox.push(vec![chat("---")]));
+        assert_eq!(outbox.stats().depth(), 4);
+        assert_eq!(outbox.pop(now), Some(chat("---")));
+        // 已经开始发送的消息不再合并
+        assert!(outbox.push(vec![chat("a"), chat("---")]));
+        let sent: Vec<_> = std::iter::from_fn(|| outbox.pop(now)).collect();
+        assert_eq!(sent, vec![chat("a"), chat("---"), chat("---"), chat("a"), chat("---")]);
+    }
+}
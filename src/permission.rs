@@ -0,0 +1,77 @@
+/// BanchoBot 消息里的用户名带空格，irc 昵称里是下划线，统一写成 irc 昵称。
+/// 封禁、统计和权限都按它匹配玩家，比较时不区分大小写
+pub fn normalize_name(name: &str) -> String {
+    name.trim().replace(' ', "_")
+}
+
+/// 按玩家记录状态时使用的键，同一玩家的不同写法得到相同的键
+pub fn player_key(name: &str) -> String {
+    normalize_name(name).to_ascii_lowercase()
+}
+
+pub fn same_player(a: &str, b: &str) -> bool {
+    player_key(a) == player_key(b)
+}
+
+/// 指令的权限等级，高等级包含低等级的全部权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    Player,
+    /// 当前房间的房主
+    Host,
+    /// 配置中的 operators，可以踢人、换房主、跳过、锁房、代发消息和查看封禁
+    Operator,
+    /// 配置中的 admins，另外可以封禁、修改规则、重新加载配置和关闭 bot
+    Admin,
+}
+
+impl Permission {
+    /// 文案中的名称，对应 permission.<name>
+    pub fn name(self) -> &'static str {
+        match self {
+            Permission::Player => "player",
+            Permission::Host => "host",
+            Permission::Operator => "operator",
+            Permission::Admin => "admin",
+        }
+    }
+
+    /// irc_name 为 irc 昵称，房主名来自 BanchoBot 消息，空格按下划线比较
+    pub fn of(irc_name: &str, room_host: Option<&str>, operators: &[String], admins: &[String]) -> Self {
+        let matches = |name: &str| same_player(name, irc_name);
+        if admins.iter().any(|name| matches(name)) {
+            Permission::Admin
+        } else if operators.iter().any(|name| matches(name)) {
+            Permission::Operator
+        } else if room_host.is_some_and(matches) {
+            Permission::Host
+        } else {
+            Permission::Player
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permission_levels() {
+        let admins = vec!["Owner".to_string()];
+        let operators = vec!["Mod Name".to_string()];
+        let cases = vec![
+            ("owner", Some("Someone"), Permission::Admin),
+            ("Mod_Name", None, Permission::Operator),
+            ("Host_Player", Some("Host Player"), Permission::Host),
+            ("Someone", Some("Host Player"), Permission::Player),
+            ("Someone", None, Permission::Player),
+        ];
+        for (name, host, expected) in cases {
+            assert_eq!(Permission::of(name, host, &operators, &admins), expected, "{}", name);
+        }
+        assert!(Permission::Admin > Permission::Operator && Permission::Operator > Permission::Host);
+        assert_eq!(normalize_name(" Mod Name "), "Mod_Name");
+        assert!(same_player("mod_name", "Mod Name") && !same_player("Mod", "Mod Name"));
+        assert_eq!(player_key("Mod Name"), "mod_name");
+    }
+}
@@ -1,43 +1,168 @@
-use rosu_pp::Beatmap;
+use rosu_pp::model::mode::GameMode;
+use rosu_pp::any::DifficultyAttributes;
+use rosu_pp::{Beatmap, GameMods};
 use std::error::Error;
+use std::path::Path;
 
 pub struct PPCalculator {
     beatmap_path: String,
+    /// 计算所用的游戏模式 (mode_int)，std 谱面会被转换到该模式
+    mode: u32,
+}
+
+pub struct BeatmapDetails {
+    pub stars: f64,
+    pub max_pp: f64,
+    pub pp_95: f64,
+    pub pp_96: f64,
+    pub pp_97: f64,
+    pub pp_98: f64,
+    pub pp_99: f64,
+}
+
+impl BeatmapDetails {
+    pub fn format(&self) -> String {
+        format!("Stars: {:.2} | 95%: {:.2}pp | 96%: {:.2}pp | 97%: {:.2}pp | 98%: {:.2}pp | 99%: {:.2}pp | Max: {:.2}pp",
+                self.stars, self.pp_95, self.pp_96, self.pp_97, self.pp_98, self.pp_99, self.max_pp)
+    }
+}
+
+/// 一次成绩的 mod 与判定统计
+///
+/// 各模式下判定的含义与 osu! api 一致：mania 的 geki/katu 为 MAX/200，
+/// catch 的 300/100/50 为水果/中果/小果，katu 为漏掉的小果
+pub struct ScoreState {
+    pub mode: u32,
+    pub mods: u32,
+    pub max_combo: u32,
+    pub n_geki: u32,
+    pub n300: u32,
+    pub n_katu: u32,
+    pub n100: u32,
+    pub n50: u32,
+    pub misses: u32,
+}
+
+impl ScoreState {
+    /// 已经打过的物件数，与 total_objects 的计数方式相同：
+    /// taiko 不含连打和转盘，catch 只算水果和中果
+    pub fn passed_objects(&self) -> u32 {
+        match self.mode {
+            1 => self.n300 + self.n100 + self.misses,
+            2 => self.n300 + self.n100 + self.misses,
+            3 => self.n_geki + self.n300 + self.n_katu + self.n100 + self.n50 + self.misses,
+            _ => self.n300 + self.n100 + self.n50 + self.misses,
+        }
+    }
+}
+
+pub struct ScorePP {
+    pub stars: f64,
+    pub pp: f64,
+    /// 同样准确率下全连的 pp
+    pub fc_pp: f64,
+    /// 打过的物件占全部物件的百分比，未通过的成绩小于 100
+    pub completion: f64,
+}
+
+/// 谱面文件在 maps_dir 中的保存位置
+pub fn beatmap_path(maps_dir: &Path, beatmap_id: u32) -> String {
+    maps_dir.join(format!("{}.osu", beatmap_id)).to_string_lossy().into_owned()
+}
+
+/// 读取谱面，std 谱面按需转换到目标模式
+fn load_map(path: &str, mode: u32, mods: u32) -> Result<Beatmap, Box<dyn Error>> {
+    let mut map = Beatmap::from_path(path)?;
+    map.convert_mut(game_mode(mode), &GameMods::from(mods))?;
+    Ok(map)
+}
+
+pub fn game_mode(mode: u32) -> GameMode {
+    match mode {
+        1 => GameMode::Taiko,
+        2 => GameMode::Catch,
+        3 => GameMode::Mania,
+        _ => GameMode::Osu,
+    }
+}
+
+/// 解析 osu/taiko/fruits/catch/mania 或 0-3
+pub fn parse_game_mode(input: &str) -> Option<u32> {
+    match input.trim().to_lowercase().as_str() {
+        "0" | "osu" | "std" | "standard" => Some(0),
+        "1" | "taiko" => Some(1),
+        "2" | "fruits" | "catch" | "ctb" => Some(2),
+        "3" | "mania" => Some(3),
+        _ => None,
+    }
+}
+
+pub fn mode_name(mode: u32) -> &'static str {
+    match mode {
+        1 => "taiko",
+        2 => "catch",
+        3 => "mania",
+        _ => "osu",
+    }
 }
 
 impl PPCalculator {
-    pub fn new(beatmap_path: String) -> Self {
-        Self { beatmap_path }
+    pub fn new(beatmap_path: String, mode: u32) -> Self {
+        Self { beatmap_path, mode }
     }
 
-    pub fn calculate_pp(&self, beatmap_id: u32, mods: u32, combo: u32, accuracy: f64, misses: u32) -> Result<(f64, f64, f64), Box<dyn Error>> {
-        let map = Beatmap::from_path(&self.beatmap_path)?;
+    /// 按一次成绩的判定统计计算 pp，谱面文件需要事先下载到 beatmap_path
+    pub fn calculate_pp(beatmap_path: &str, score: &ScoreState) -> Result<ScorePP, Box<dyn Error>> {
+        let map = load_map(beatmap_path, score.mode, score.mods)?;
+        let hit_objects = score.passed_objects();
 
         let diff_attrs = rosu_pp::Difficulty::new()
-            .mods(mods)
+            .mods(score.mods)
             .calculate(&map);
+        let total_objects = total_objects(&diff_attrs);
 
         let stars = diff_attrs.stars();
 
+        // 未通过的成绩只计算已经打过的物件
         let perf_attrs = rosu_pp::Performance::new(diff_attrs)
-            .mods(mods)
-            .combo(combo)
-            .accuracy(accuracy)
-            .misses(misses)
+            .mods(score.mods)
+            .combo(score.max_combo)
+            .n_geki(score.n_geki)
+            .n300(score.n300)
+            .n_katu(score.n_katu)
+            .n100(score.n100)
+            .n50(score.n50)
+            .misses(score.misses)
+            .passed_objects(hit_objects.min(total_objects))
             .calculate();
 
         let pp = perf_attrs.pp();
 
-        let max_pp = perf_attrs.performance()
-            .mods(mods)
+        // 全连时不指定最高判定，miss 和没打到的物件由 rosu_pp 补为最高判定
+        let fc_perf = perf_attrs.performance()
+            .mods(score.mods)
+            .n100(score.n100)
+            .n50(score.n50)
+            .misses(0);
+        let fc_pp = match score.mode {
+            2 => fc_perf.n_katu(score.n_katu),
+            3 => fc_perf.n300(score.n300).n_katu(score.n_katu),
+            _ => fc_perf,
+        }
             .calculate()
             .pp();
 
-        Ok((stars, pp, max_pp))
+        let completion = if total_objects == 0 {
+            100.0
+        } else {
+            (hit_objects as f64 / total_objects as f64 * 100.0).min(100.0)
+        };
+
+        Ok(ScorePP { stars, pp, fc_pp, completion })
     }
 
-    pub fn calculate_beatmap_details(&self, mods: u32) -> Result<(f64, f64, f64, f64, f64, f64, f64), Box<dyn Error>> {
-        let map = Beatmap::from_path(&self.beatmap_path)?;
+    pub fn calculate_beatmap_details(&self, mods: u32) -> Result<BeatmapDetails, Box<dyn Error>> {
+        let map = load_map(&self.beatmap_path, self.mode, mods)?;
 
         let diff_attrs = rosu_pp::Difficulty::new()
             .mods(mods)
@@ -50,16 +175,163 @@ impl PPCalculator {
 
         let max_pp = perf_attrs.clone().accuracy(100.0).calculate().pp();
 
-        let pp_95_fc = perf_attrs.clone().accuracy(95.0).calculate().pp();
+        let pp_95 = perf_attrs.clone().accuracy(95.0).calculate().pp();
 
-        let pp_96_fc = perf_attrs.clone().accuracy(96.0).calculate().pp();
+        let pp_96 = perf_attrs.clone().accuracy(96.0).calculate().pp();
 
-        let pp_97_fc = perf_attrs.clone().accuracy(97.0).calculate().pp();
+        let pp_97 = perf_attrs.clone().accuracy(97.0).calculate().pp();
+
+        let pp_98 = perf_attrs.clone().accuracy(98.0).calculate().pp();
+
+        let pp_99 = perf_attrs.clone().accuracy(99.0).calculate().pp();
+
+        Ok(BeatmapDetails { stars, max_pp, pp_95, pp_96, pp_97, pp_98, pp_99 })
+    }
 
-        let pp_98_fc = perf_attrs.clone().accuracy(98.0).calculate().pp();
+    /// 指定 mod 和准确率下全连的 pp，返回 (stars, pp)
+    pub fn calculate_accuracy_pp(&self, mods: u32, accuracy: f64) -> Result<(f64, f64), Box<dyn Error>> {
+        let map = load_map(&self.beatmap_path, self.mode, mods)?;
+
+        let diff_attrs = rosu_pp::Difficulty::new()
+            .mods(mods)
+            .calculate(&map);
+
+        let stars = diff_attrs.stars();
+
+        let pp = rosu_pp::Performance::new(diff_attrs)
+            .mods(mods)
+            .accuracy(accuracy)
+            .calculate()
+            .pp();
+
+        Ok((stars, pp))
+    }
+}
+
+/// 全连时 passed_objects 的值，按 rosu_pp 各模式计算未通过成绩时的方式计数
+fn total_objects(attrs: &DifficultyAttributes) -> u32 {
+    match attrs {
+        DifficultyAttributes::Osu(attrs) => attrs.n_objects(),
+        // taiko 的 combo 只来自音符，catch 的 combo 来自水果和中果
+        DifficultyAttributes::Taiko(attrs) => attrs.max_combo(),
+        DifficultyAttributes::Catch(attrs) => attrs.max_combo(),
+        DifficultyAttributes::Mania(attrs) => attrs.n_objects(),
+    }
+}
+
+// (位标记, 缩写, BanchoBot 使用的全称)
+const MODS: &[(u32, &str, &str)] = &[
+    (1, "NF", "NoFail"),
+    (2, "EZ", "Easy"),
+    (4, "TD", "TouchDevice"),
+    (8, "HD", "Hidden"),
+    (16, "HR", "HardRock"),
+    (32, "SD", "SuddenDeath"),
+    (64, "DT", "DoubleTime"),
+    (128, "RX", "Relax"),
+    (256, "HT", "HalfTime"),
+    (512 | 64, "NC", "Nightcore"),
+    (1024, "FL", "Flashlight"),
+    (4096, "SO", "SpunOut"),
+    (8192, "AP", "Relax2"),
+    (16384 | 32, "PF", "Perfect"),
+    (32768, "4K", "Key4"),
+    (65536, "5K", "Key5"),
+    (131072, "6K", "Key6"),
+    (262144, "7K", "Key7"),
+    (524288, "8K", "Key8"),
+    (16777216, "9K", "Key9"),
+    (1048576, "FI", "FadeIn"),
+    (2097152, "RD", "Random"),
+];
+
+/// 将 osu! api 返回的 mod 缩写（如 HD、DT）转换为 rosu_pp 使用的位标记
+pub fn mods_from_acronyms<S: AsRef<str>>(acronyms: &[S]) -> u32 {
+    acronyms.iter()
+        .filter_map(|acronym| MODS.iter().find(|(_, a, _)| a.eq_ignore_ascii_case(acronym.as_ref())))
+        .fold(0, |bits, (bit, _, _)| bits | bit)
+}
+
+/// 将 BanchoBot 消息中的 mod 全称（如 Hidden、DoubleTime）转换为位标记，FreeMod 等无关项会被忽略
+pub fn mods_from_names<S: AsRef<str>>(names: &[S]) -> u32 {
+    names.iter()
+        .filter_map(|name| MODS.iter().find(|(_, _, n)| n.eq_ignore_ascii_case(name.as_ref())))
+        .fold(0, |bits, (bit, _, _)| bits | bit)
+}
+
+/// 解析玩家输入的 mod 组合，例如 HDDT、HD,HR、NM，存在无法识别的 mod 时返回 None
+pub fn parse_mod_string(input: &str) -> Option<u32> {
+    let letters: Vec<char> = input.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    if letters.is_empty() || !letters.len().is_multiple_of(2) {
+        return None;
+    }
+    letters.chunks(2).try_fold(0, |bits, chunk| {
+        let acronym: String = chunk.iter().collect();
+        if acronym.eq_ignore_ascii_case("NM") {
+            return Some(bits);
+        }
+        MODS.iter()
+            .find(|(_, a, _)| a.eq_ignore_ascii_case(&acronym))
+            .map(|(bit, _, _)| bits | bit)
+    })
+}
+
+/// 位标记转换为缩写串，例如 HDDT，没有 mod 时为 NM
+pub fn mods_to_string(mods: u32) -> String {
+    let text: String = MODS.iter()
+        .filter(|(bit, _, _)| mods & bit == *bit)
+        // NC 包含 DT，PF 包含 SD，只显示前者
+        .filter(|(bit, _, _)| !(*bit == 64 && mods & 512 != 0 || *bit == 32 && mods & 16384 != 0))
+        .map(|(_, acronym, _)| *acronym)
+        .collect();
+    if text.is_empty() { "NM".to_string() } else { text }
+}
+
+/// DT/NC 为 1.5 倍速，HT 为 0.75 倍速
+pub fn clock_rate(mods: u32) -> f64 {
+    if mods & 64 != 0 {
+        1.5
+    } else if mods & 256 != 0 {
+        0.75
+    } else {
+        1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mod_conversions() {
+        assert_eq!(mods_from_acronyms(&["HD", "dt"]), 8 | 64);
+        assert_eq!(mods_from_names(&["Hidden", "DoubleTime", "Freemod"]), 8 | 64);
+        assert_eq!(parse_mod_string("HDHR"), Some(8 | 16));
+        assert_eq!(parse_mod_string("hd,dt"), Some(8 | 64));
+        assert_eq!(parse_mod_string("NM"), Some(0));
+        assert_eq!(parse_mod_string("HDX"), None);
+        assert_eq!(parse_mod_string("XX"), None);
+        assert_eq!(mods_to_string(8 | 64), "HDDT");
+        assert_eq!(mods_to_string(512 | 64), "NC");
+        assert_eq!(mods_to_string(0), "NM");
+        assert_eq!(clock_rate(512 | 64), 1.5);
+        assert_eq!(clock_rate(256), 0.75);
+    }
 
-        let pp_99_fc = perf_attrs.clone().accuracy(99.0).calculate().pp();
-            
-        Ok((stars,max_pp, pp_95_fc, pp_96_fc, pp_97_fc, pp_98_fc, pp_99_fc))
+    #[test]
+    fn test_total_objects_by_mode() {
+        // 一个圆圈和一条滑条
+        let osu = "osu file format v14\n\n[General]\nMode: 0\n\n[Difficulty]\nHPDrainRate:5\nCircleSize:4\nOverallDifficulty:8\nApproachRate:9\nSliderMultiplier:1.4\nSliderTickRate:1\n\n[TimingPoints]\n0,500,4,2,0,100,1,0\n\n[HitObjects]\n256,192,1000,1,0\n100,100,2000,2,0,L|400:100,1,280\n";
+        let totals: Vec<u32> = (0..3).map(|mode| {
+            let mut map = Beatmap::from_bytes(osu.as_bytes()).unwrap();
+            map.convert_mut(game_mode(mode), &GameMods::from(0)).unwrap();
+            assert_eq!(map.hit_objects.len(), 2);
+            total_objects(&rosu_pp::Difficulty::new().calculate(&map))
+        }).collect();
+        assert_eq!(totals[0], 2);
+        // taiko 中滑条变成连打，不计入
+        assert_eq!(totals[1], 1);
+        // catch 中滑条带来中果
+        assert!(totals[2] > 2, "{:?}", totals);
     }
 }
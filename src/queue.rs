@@ -0,0 +1,138 @@
+/// 房主队列，排在最前面的是当前 (或下一位) 房主。
+/// 用 !queue leave 退出队列的玩家仍在房间里，但不会再轮到房主，直到 !queue join
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HostQueue {
+    players: Vec<String>,
+    opted_out: Vec<String>,
+}
+
+impl HostQueue {
+    pub fn from_parts(players: Vec<String>, opted_out: Vec<String>) -> Self {
+        Self { players, opted_out }
+    }
+
+    pub fn players(&self) -> &[String] {
+        &self.players
+    }
+
+    pub fn opted_out(&self) -> &[String] {
+        &self.opted_out
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    pub fn front(&self) -> Option<&String> {
+        self.players.first()
+    }
+
+    /// 新玩家排到队尾，已在队列中或退出了队列的玩家不变
+    pub fn add(&mut self, name: &str) {
+        if !self.players.iter().any(|p| p == name) && !self.opted_out.iter().any(|p| p == name) {
+            self.players.push(name.to_string());
+        }
+    }
+
+    /// 玩家离开房间，之后重新加入时排到队尾
+    pub fn remove(&mut self, name: &str) {
+        self.players.retain(|p| p != name);
+        self.opted_out.retain(|p| p != name);
+    }
+
+    /// 删除不在房间里的玩家
+    pub fn retain_players(&mut self, players: &[String]) {
+        self.players.retain(|p| players.contains(p));
+        self.opted_out.retain(|p| players.contains(p));
+    }
+
+    /// 最前面的玩家排到队尾
+    pub fn rotate(&mut self) {
+        if !self.players.is_empty() {
+            let front = self.players.remove(0);
+            self.players.push(front);
+        }
+    }
+
+    /// 移动到最前面，不在队列中时加入
+    pub fn promote(&mut self, name: &str) {
+        self.remove(name);
+        self.players.insert(0, name.to_string());
+    }
+
+    /// 退出队列，返回之前是否在队列中
+    pub fn opt_out(&mut self, name: &str) -> bool {
+        let Some(index) = self.position(name) else {
+            return false;
+        };
+        let name = self.players.remove(index);
+        self.opted_out.push(name);
+        true
+    }
+
+    /// 重新加入队列并排到队尾，返回之前是否退出了队列
+    pub fn opt_in(&mut self, name: &str) -> bool {
+        let Some(index) = self.opted_out.iter().position(|p| p == name) else {
+            return false;
+        };
+        let name = self.opted_out.remove(index);
+        self.players.push(name);
+        true
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.players.iter().position(|p| p == name)
+    }
+
+    /// 移动到 index，超出队列长度时排到队尾，返回实际位置
+    pub fn move_to(&mut self, name: &str, index: usize) -> Option<usize> {
+        let from = self.position(name)?;
+        let name = self.players.remove(from);
+        let index = index.min(self.players.len());
+        self.players.insert(index, name);
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(queue: &HostQueue) -> Vec<&str> {
+        queue.players().iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn test_queue_operations() {
+        let mut queue = HostQueue::default();
+        for name in ["a", "b", "c", "d", "a"] {
+            queue.add(name);
+        }
+        assert_eq!(names(&queue), vec!["a", "b", "c", "d"]);
+
+        queue.rotate();
+        assert_eq!(names(&queue), vec!["b", "c", "d", "a"]);
+        queue.promote("d");
+        assert_eq!(names(&queue), vec!["d", "b", "c", "a"]);
+        assert_eq!(queue.move_to("a", 1), Some(1));
+        assert_eq!(queue.move_to("d", 99), Some(3));
+        assert_eq!(names(&queue), vec!["a", "b", "c", "d"]);
+
+        // 退出队列后不会被重新加入，直到 opt_in
+        assert!(queue.opt_out("b"));
+        assert!(!queue.opt_out("b"));
+        queue.add("b");
+        assert_eq!(names(&queue), vec!["a", "c", "d"]);
+        assert!(queue.opt_in("b"));
+        assert_eq!(queue.position("b"), Some(3));
+
+        queue.opt_out("c");
+        queue.retain_players(&["a".to_string(), "b".to_string()]);
+        assert_eq!(names(&queue), vec!["a", "b"]);
+        assert!(queue.opted_out().is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::i18n::Lang;
+
+/// 一名玩家在一局中的成绩，来自 BanchoBot 的 "finished playing" 消息
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerResult {
+    pub name: String,
+    pub score: u64,
+    pub passed: bool,
+    pub pp: Option<f64>,
+    /// 0-1，通过 api 取回的 pass 成绩才有
+    pub accuracy: Option<f64>,
+}
+
+impl PlayerResult {
+    pub fn new(name: String, score: u64, passed: bool) -> Self {
+        Self { name, score, passed, pp: None, accuracy: None }
+    }
+}
+
+/// 按分数从高到低排序，同分时 pass 的玩家在前
+pub fn rank_results(results: &mut [PlayerResult]) {
+    results.sort_by(|a, b| b.score.cmp(&a.score).then(b.passed.cmp(&a.passed)));
+}
+
+pub fn format_results(results: &[PlayerResult], lang: Lang) -> String {
+    let lines = results
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let pp = r.pp.map(|pp| format!(" {:.2}pp", pp)).unwrap_or_default();
+            format!(
+                "#{} {} {} {}{}",
+                i + 1,
+                r.name,
+                r.score,
+                if r.passed { "PASS" } else { "FAIL" },
+                pp
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" | ");
+    lang.t("room.results", &[("results", &lines)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_and_format_results() {
+        let mut results = vec![
+            PlayerResult::new("Alice".to_string(), 500000, false),
+            PlayerResult { pp: Some(123.456), ..PlayerResult::new("Bob".to_string(), 900000, true) },
+            PlayerResult::new("Carol".to_string(), 500000, true),
+        ];
+        rank_results(&mut results);
+
+        let names: Vec<_> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["Bob", "Carol", "Alice"]);
+        assert_eq!(
+            format_results(&results, Lang::ZhCn),
+            "本局结果: #1 Bob 900000 PASS 123.46pp | #2 Carol 500000 PASS | #3 Alice 500000 FAIL"
+        );
+    }
+}
@@ -0,0 +1,754 @@
+use crate::afk::{AfkConfig, AfkEvent, HostWatch};
+use crate::auto_start::{AutoStart, AutoStartConfig, AutoStartEvent};
+use crate::pp_calculator::{self, PPCalculator};
+use crate::queue::HostQueue;
+use crate::results::{self, PlayerResult};
+use crate::rotation::{RotationContext, RotationStrategy};
+use crate::rules::MapRules;
+use crate::RoomSettings;
+use crate::config::Messages;
+use crate::i18n::Lang;
+use crate::permission;
+use crate::transport::MessageSender;
+use crate::vote::{VoteKind, VoteResult, VoteRules, Votes};
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::time;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct BotState {
+    beatmap_name: String,
+    beatmap_artist: String,
+    beatmap_star: f32,
+    player_list: Vec<String>,
+    // 以下字段用于断线或重启后恢复房间状态，旧版本的状态文件中没有
+    #[serde(default)]
+    beatmap_id: u32,
+    #[serde(default)]
+    room_host: String,
+    #[serde(default)]
+    room_host_list: Vec<String>,
+    #[serde(default)]
+    queue_opted_out: Vec<String>,
+    #[serde(default)]
+    approved_abort_list: Vec<String>,
+    #[serde(default)]
+    approved_start_list: Vec<String>,
+    #[serde(default)]
+    approved_skip_list: Vec<String>,
+    #[serde(default)]
+    approved_close_list: Vec<String>,
+}
+
+/// 一个 #mp 房间的全部状态
+pub struct Room {
+    sender: MessageSender,
+    /// 状态文件所在目录
+    data_dir: PathBuf,
+    /// 房间在配置中的序号，用于区分各房间的状态文件
+    pub index: usize,
+    pub room_id: u32,
+    pub room_name: String,
+    pub room_password: String,
+    pub player_list: Vec<String>,
+    pub host_queue: HostQueue,
+    /// 房主轮换策略
+    pub rotation: Box<dyn RotationStrategy>,
+    /// 本次运行中各玩家当房主打过的谱面数
+    pub pick_counts: HashMap<String, u32>,
+    /// 各玩家进入房间的顺序，离开后重新进入时重新计
+    pub join_order: HashMap<String, u64>,
+    next_join: u64,
+    /// 房主离开时的进房顺序，保留到下一次轮换
+    last_host_join: Option<u64>,
+    /// 不设房主的房间需要 bot 选下一张图，由 bot 处理完消息后完成
+    pub bot_pick_requested: bool,
+    /// 最近打完的谱面长度，用于估计排队时间
+    pub recent_map_lengths: VecDeque<u64>,
+    pub beatmap_start_time: Option<Instant>,
+    pub beatmap_end_time: Option<Instant>,
+    /// 进行中的 !abort/!skip/!close/!start 投票
+    pub votes: Votes,
+    pub room_host: String,
+    pub beatmap_id: u32,
+    pub beatmap_length: u64,
+    pub beatmap_path: String,
+    pub pp_calculator: PPCalculator,
+    pub beatmap_title_unicode: String,
+    pub beatmap_artist_unicode: String,
+    pub beatmap_difficulty_rating: f32,
+    pub beatmap_bpm: f32,
+    /// 当前谱面实际计算 pp 所用的模式
+    pub beatmap_mode: u32,
+    pub beatmap_info: String,
+    pub beatmap_pp_info: String,
+    pub is_channel_exist: bool,
+    pub is_game_started: bool,
+    pub match_results: Vec<PlayerResult>,
+    /// 房间当前的全局 mod（位标记）
+    pub room_mods: u32,
+    pub free_mod: bool,
+    /// 房间的游戏模式 (mode_int)，std 谱面会按该模式转换
+    pub game_mode: u32,
+    pub rules: MapRules,
+    /// 最近一张符合规则的谱面，违规时换回这张
+    pub last_valid_beatmap_id: Option<u32>,
+    /// bot 为了换回谱面发出的 !mp map，收到对应的换图消息时不当作房主选图
+    pub pending_revert: Option<u32>,
+    /// 房主选了违规的谱面且没有可以换回的谱面，换成符合规则的谱面前不能开始
+    pub beatmap_rejected: bool,
+    /// 当前房主本轮选了几次违规谱面
+    pub host_violations: u32,
+    /// 管理员是否用 !lock 锁定了位置
+    pub slots_locked: bool,
+    /// 投票通过需要的玩家比例
+    pub vote_rules: VoteRules,
+    pub messages: Messages,
+    /// 房间消息的语言
+    pub lang: Lang,
+    pub auto_start_config: AutoStartConfig,
+    /// 换图后的自动开始倒计时，开始游戏或再次换图时取消
+    pub auto_start: Option<AutoStart>,
+    pub afk_config: AfkConfig,
+    /// 房主挂机检测
+    pub host_watch: HostWatch,
+}
+
+impl Room {
+    pub fn new(sender: MessageSender, index: usize, room_id: u32, settings: RoomSettings, data_dir: &Path) -> Self {
+        Room {
+            sender,
+            data_dir: data_dir.to_path_buf(),
+            index,
+            room_id,
+            room_name: settings.room_name,
+            room_password: settings.room_password,
+            player_list: Vec::new(),
+            host_queue: HostQueue::default(),
+            rotation: settings.rotation.strategy(),
+            pick_counts: HashMap::new(),
+            join_order: HashMap::new(),
+            next_join: 0,
+            last_host_join: None,
+            bot_pick_requested: false,
+            recent_map_lengths: VecDeque::new(),
+            beatmap_start_time: None,
+            beatmap_end_time: None,
+            votes: Votes::default(),
+            room_host: String::new(),
+            beatmap_id: 0,
+            beatmap_length: 0,
+            beatmap_path: String::new(),
+            pp_calculator: PPCalculator::new(String::new(), settings.game_mode),
+            beatmap_title_unicode: String::new(),
+            beatmap_artist_unicode: String::new(),
+            beatmap_difficulty_rating: 0.0,
+            beatmap_bpm: 0.0,
+            beatmap_mode: 0,
+            beatmap_info: String::new(),
+            beatmap_pp_info: String::new(),
+            is_channel_exist: false,
+            is_game_started: false,
+            match_results: Vec::new(),
+            room_mods: 0,
+            free_mod: false,
+            game_mode: settings.game_mode,
+            rules: settings.rules,
+            last_valid_beatmap_id: None,
+            pending_revert: None,
+            beatmap_rejected: false,
+            host_violations: 0,
+            slots_locked: false,
+            vote_rules: settings.votes,
+            messages: settings.messages,
+            lang: settings.lang,
+            auto_start_config: settings.auto_start,
+            auto_start: None,
+            afk_config: settings.afk_host,
+            host_watch: HostWatch::default(),
+        }
+    }
+
+    pub fn channel(&self) -> String {
+        format!("#mp_{}", self.room_id)
+    }
+
+    /// 第一个房间沿用 bot_state.json，其余房间为 bot_state_2.json、bot_state_3.json ...
+    pub fn state_file(&self) -> PathBuf {
+        match self.index {
+            0 => self.data_dir.join("bot_state.json"),
+            n => self.data_dir.join(format!("bot_state_{}.json", n + 1)),
+        }
+    }
+
+    /// 重新加载配置后更新房间设置，房间名只在建房时使用，修改后需要重建房间才生效
+    pub async fn apply_settings(&mut self, settings: RoomSettings) -> Result<(), Box<dyn Error>> {
+        let password_changed = settings.room_password != self.room_password;
+        self.room_password = settings.room_password;
+        self.rules = settings.rules;
+        self.game_mode = settings.game_mode;
+        self.vote_rules = settings.votes;
+        self.messages = settings.messages;
+        self.lang = settings.lang;
+        self.auto_start_config = settings.auto_start;
+        self.afk_config = settings.afk_host;
+        if settings.rotation != self.rotation.mode() {
+            println!("Rotation of {} changed to {}", self.channel(), settings.rotation.name());
+            self.rotation = settings.rotation.strategy();
+        }
+        if password_changed && self.room_id != 0 {
+            self.set_room_password(self.room_password.clone()).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn send_message(&self, message: &str) -> Result<(), Box<dyn Error>> {
+        let channel = self.channel();
+        self.sender.send_privmsg(&channel, message);
+        println!("发送消息: {} -> {}", channel, message);
+        Ok(())
+    }
+
+    pub async fn create_room(&mut self) -> Result<(), Box<dyn Error>> {
+        self.sender.send_privmsg("BanchoBot", &format!("!mp make {}", self.room_name));
+        println!("Sent room creation request to BanchoBot: {}", self.room_name);
+        Ok(())
+    }
+
+    pub async fn join(&self) -> Result<(), Box<dyn Error>> {
+        self.sender.send_join(&self.channel());
+        println!("Joined room: {}", self.channel());
+        Ok(())
+    }
+
+    /// 重新加入房间，玩家列表由 !mp settings 返回的 Slot 行重建
+    pub async fn rejoin(&mut self) -> Result<(), Box<dyn Error>> {
+        self.join().await?;
+        self.player_list.clear();
+        self.get_mp_settings().await?;
+        Ok(())
+    }
+
+    pub async fn rotate_host(&mut self) -> Result<(), Box<dyn Error>> {
+        //轮换房主前，删除不在player_list中的玩家
+        self.remove_player_not_in_list();
+        let last_host_join = self.last_host_join.take();
+        if self.rotation.bot_picks() {
+            if !self.room_host.is_empty() {
+                self.send_message("!mp clearhost").await?;
+                self.room_host.clear();
+            }
+            self.bot_pick_requested = !self.player_list.is_empty();
+            return Ok(());
+        }
+        let mut current = self.room_host.clone();
+        // 多次挂机或当房主时离开的玩家失去一次机会，至少留下一个人
+        for attempt in 1..=self.host_queue.len() {
+            let ctx = RotationContext {
+                current_host: &current,
+                pick_counts: &self.pick_counts,
+                join_order: &self.join_order,
+                last_host_join,
+            };
+            let Some(candidate) = self.rotation.next_host(&mut self.host_queue, &ctx) else {
+                break;
+            };
+            if attempt == self.host_queue.len() || !self.host_watch.take_penalty(&candidate, &self.afk_config) {
+                self.set_host(&candidate).await?;
+                println!("Rotated host to: {}", candidate);
+                break;
+            }
+            self.send_message(&self.lang.t("host.penalty_skip", &[("player", &candidate)])).await?;
+            current = candidate;
+        }
+        Ok(())
+    }
+
+    pub fn add_player(&mut self, name: String) {
+        if !self.player_list.contains(&name) {
+            self.player_list.push(name.clone());
+        }
+        self.host_queue.add(&name);
+        if !self.join_order.contains_key(&name) {
+            self.join_order.insert(name, self.next_join);
+            self.next_join += 1;
+        }
+    }
+
+    pub fn remove_player(&mut self, name: &str) {
+        self.player_list.retain(|n| n != name);
+        self.host_queue.remove(name);
+        if name == self.room_host {
+            self.last_host_join = self.join_order.get(name).copied();
+        }
+        self.join_order.remove(name);
+    }
+
+    /// 开始一局时记录房主选图的次数
+    pub fn record_pick(&mut self) {
+        if !self.room_host.is_empty() {
+            *self.pick_counts.entry(self.room_host.clone()).or_default() += 1;
+        }
+    }
+
+    pub fn remove_player_not_in_list(&mut self) {
+        self.host_queue.retain_players(&self.player_list);
+    }
+
+    /// 记录打完的谱面长度，只保留最近 10 张
+    pub fn record_map_length(&mut self, length: u64) {
+        self.recent_map_lengths.push_back(length);
+        if self.recent_map_lengths.len() > 10 {
+            self.recent_map_lengths.pop_front();
+        }
+    }
+
+    /// 每位房主大约占用的时间，还没有打过谱面时按当前谱面或 3 分钟估计
+    pub fn average_map_length(&self) -> u64 {
+        if self.recent_map_lengths.is_empty() {
+            return if self.beatmap_length > 0 { self.beatmap_length } else { 180 };
+        }
+        self.recent_map_lengths.iter().sum::<u64>() / self.recent_map_lengths.len() as u64
+    }
+
+    pub async fn get_mp_settings(&mut self) -> Result<(), Box<dyn Error>> {
+        self.send_message("!mp settings").await?;
+        Ok(())
+    }
+
+    pub async fn set_room_password(&mut self, password: String) -> Result<(), Box<dyn Error>> {
+        self.send_message(&format!("!mp password {}", password)).await?;
+        Ok(())
+    }
+
+    pub async fn calculate_total_time_left(&self) -> Result<String, Box<dyn Error>> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.beatmap_start_time.unwrap_or(now));
+        if elapsed == Duration::from_secs(0) {
+            let msg_not_started = self.lang.t("room.not_started", &[]);
+            self.send_message(&msg_not_started).await?;
+            Ok(msg_not_started)
+        }
+        else {
+            let total_time_left = self.beatmap_length.saturating_sub(elapsed.as_secs());
+            let msg_started = self.lang.t("room.time_left", &[("seconds", &total_time_left)]);
+            self.send_message(&msg_started).await?;
+            Ok(msg_started)
+        }
+    }
+
+    pub async fn send_welcome(&mut self, player_name: String) -> Result<(), Box<dyn Error>> {
+        let welcome = match &self.messages.welcome {
+            Some(welcome) => welcome.replace("{player}", &player_name),
+            None => self.lang.t("room.welcome", &[("player", &player_name)]),
+        };
+        self.send_message(&welcome).await?;
+
+        if self.is_game_started{
+            let remain_time_text = self.calculate_total_time_left().await?;
+            self.send_message(&remain_time_text).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn send_about(&mut self) -> Result<(), Box<dyn Error>> {
+        self.send_message(&self.messages.about).await?;
+        Ok(())
+    }
+
+    pub async fn set_host(&mut self, player_name: &str) -> Result<(), Box<dyn Error>> {
+        self.send_message(&format!("!mp host {}", player_name)).await?;
+        self.room_host = player_name.to_string();
+        self.host_violations = 0;
+        // 跳过投票针对的是上一任房主
+        self.votes.clear(VoteKind::Skip);
+        // 自动开始是给上一任房主选的图计时的
+        self.auto_start = None;
+        self.host_watch.reset(time::Instant::now());
+        Ok(())
+    }
+
+    /// 按 irc 昵称 (空格写作下划线) 查找房间内的玩家，返回 BanchoBot 使用的名字
+    pub fn find_player(&self, name: &str) -> Option<String> {
+        self.player_list.iter()
+            .find(|player| permission::same_player(player, name))
+            .cloned()
+    }
+
+    /// 管理员指定房主，该玩家移动到队列最前面，之后从他开始继续轮换
+    pub async fn force_host(&mut self, player_name: &str) -> Result<(), Box<dyn Error>> {
+        self.host_queue.promote(player_name);
+        self.set_host(player_name).await
+    }
+
+    /// 切换 !mp lock / !mp unlock，返回切换后是否锁定
+    pub async fn toggle_lock(&mut self) -> Result<bool, Box<dyn Error>> {
+        self.slots_locked = !self.slots_locked;
+        self.send_message(if self.slots_locked { "!mp lock" } else { "!mp unlock" }).await?;
+        Ok(self.slots_locked)
+    }
+
+    pub async fn set_free_mod(&mut self) -> Result<(), Box<dyn Error>> {
+        self.send_message("!mp mods FreeMod").await?;
+        Ok(())
+    }
+
+    pub async fn set_map(&mut self,map_id: i32) -> Result<(), Box<dyn Error>> {
+        self.send_message(&format!("!mp map {}", map_id)).await?;
+        Ok(())
+    }
+
+    pub async fn start_game(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.beatmap_rejected {
+            self.send_message(&self.lang.t("host.map_rejected", &[])).await?;
+            return Ok(());
+        }
+        self.send_message("!mp start").await?;
+        Ok(())
+    }
+
+    pub async fn abort_game(&mut self) -> Result<(), Box<dyn Error>> {
+        self.send_message("!mp abort").await?;
+        Ok(())
+    }
+
+    pub async fn close_room(&mut self) -> Result<(), Box<dyn Error>> {
+        self.send_message("!mp close").await?;
+        Ok(())
+    }
+
+    pub async fn cleanup_after_match(&mut self) -> Result<(), Box<dyn Error>> {
+        self.votes.clear_all();
+        Ok(())
+    }
+
+    /// !mp settings 中的 Active mods 行，返回 mod 是否有变化
+    pub fn set_active_mods(&mut self, names: &[String]) -> bool {
+        let old = (self.room_mods, self.free_mod);
+        self.room_mods = pp_calculator::mods_from_names(names);
+        self.free_mod = names.iter().any(|n| n.eq_ignore_ascii_case("freemod"));
+        old != (self.room_mods, self.free_mod)
+    }
+
+    /// !mp mods 的回复，返回 mod 是否有变化
+    pub fn apply_mods_change(&mut self, enabled: &[String], disabled: &[String]) -> bool {
+        let old = (self.room_mods, self.free_mod);
+        if disabled.iter().any(|n| n.eq_ignore_ascii_case("all mods")) {
+            self.room_mods = 0;
+        }
+        self.room_mods &= !pp_calculator::mods_from_names(disabled);
+        self.room_mods |= pp_calculator::mods_from_names(enabled);
+        if disabled.iter().any(|n| n.eq_ignore_ascii_case("freemod")) {
+            self.free_mod = false;
+        }
+        if enabled.iter().any(|n| n.eq_ignore_ascii_case("freemod")) {
+            self.free_mod = true;
+        }
+        old != (self.room_mods, self.free_mod)
+    }
+
+    /// 根据 mod 的变速换算谱面长度(秒)和 bpm
+    pub fn length_and_bpm_with_mods(&self, mods: u32) -> (u64, f32) {
+        let rate = pp_calculator::clock_rate(mods);
+        ((self.beatmap_length as f64 / rate).round() as u64, (self.beatmap_bpm as f64 * rate) as f32)
+    }
+
+    pub fn record_result(&mut self, name: String, score: u64, passed: bool) {
+        // 同一局中同一玩家只保留最后一条
+        self.match_results.retain(|r| r.name != name);
+        self.match_results.push(PlayerResult::new(name, score, passed));
+    }
+
+    pub async fn send_match_results(&mut self, results: &mut [PlayerResult]) -> Result<(), Box<dyn Error>> {
+        if results.is_empty() {
+            return Ok(());
+        }
+        results::rank_results(results);
+        let summary = results::format_results(results, self.lang);
+        self.send_message(&summary).await?;
+        Ok(())
+    }
+
+    pub async fn send_queue(&mut self) -> Result<(), Box<dyn Error>> {
+        let queue = self.host_queue.players().iter()
+            .map(|name| {
+                name.chars()
+                .map(|c| format!("{c}\u{200B}"))
+                .collect::<String>()
+                .trim_end_matches('\u{200B}')
+                .to_owned()
+            })
+                .collect::<Vec<_>>()
+                .join("->");
+        self.send_message(&queue).await?;
+        Ok(())
+    }
+
+    pub async fn send_beatmap_info(&mut self) -> Result<(), Box<dyn Error>> {
+        self.send_message(&self.beatmap_info).await?;
+        if let Err(e) = self.save_latest_info_to_file() {
+            println!("Failed to save state of {}: {}", self.channel(), e);
+        }
+        Ok(())
+    }
+
+    /// 房间内的玩家投票，房主投 abort/skip 时直接通过
+    pub async fn vote(&mut self, kind: VoteKind, irc_name: &str) -> Result<(), Box<dyn Error>> {
+        let Some(voter) = self.find_player(irc_name) else {
+            return Ok(());
+        };
+        let result = if kind.host_decides() && voter == self.room_host {
+            self.votes.clear(kind);
+            VoteResult::Passed
+        } else {
+            let rule = *self.vote_rules.get(kind);
+            self.votes.cast(kind, &voter, &rule, self.player_list.len(), time::Instant::now())
+        };
+        match result {
+            VoteResult::Passed => self.execute_vote(kind).await?,
+            VoteResult::Pending { votes, needed } => {
+                let message = self.lang.t("vote.progress", &[("votes", &votes), ("needed", &needed), ("kind", &kind.name())]);
+                self.send_message(&message).await?;
+                if kind == VoteKind::Start {
+                    self.shorten_auto_start().await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// !unvote [abort|skip|close|start]，不写时撤回全部投票
+    pub async fn unvote(&mut self, irc_name: &str, kind: Option<VoteKind>) -> Result<(), Box<dyn Error>> {
+        let Some(voter) = self.find_player(irc_name) else {
+            return Ok(());
+        };
+        let cancelled = self.votes.cancel(&voter, kind);
+        if cancelled.is_empty() {
+            self.send_message(&self.lang.t("vote.unvote_none", &[("player", &voter)])).await?;
+        } else {
+            let names = cancelled.iter().map(|k| k.name()).collect::<Vec<_>>().join("/");
+            self.send_message(&self.lang.t("vote.unvote_done", &[("player", &voter), ("kinds", &names)])).await?;
+        }
+        Ok(())
+    }
+
+    /// 玩家离开后撤回他的票，剩下的人数可能已经足够让投票通过
+    pub async fn remove_voter(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        let rules = self.vote_rules.clone();
+        for kind in self.votes.remove_voter(name, &rules, self.player_list.len(), time::Instant::now()) {
+            self.execute_vote(kind).await?;
+        }
+        Ok(())
+    }
+
+    async fn execute_vote(&mut self, kind: VoteKind) -> Result<(), Box<dyn Error>> {
+        match kind {
+            VoteKind::Abort => self.abort_game().await,
+            VoteKind::Skip => self.rotate_host().await,
+            VoteKind::Close => self.close_room().await,
+            VoteKind::Start => self.start_game().await,
+        }
+    }
+
+    /// 换图后重新开始倒计时，之前的倒计时作废
+    pub async fn schedule_auto_start(&mut self) -> Result<(), Box<dyn Error>> {
+        self.auto_start = None;
+        if self.player_list.is_empty() || self.is_game_started {
+            return Ok(());
+        }
+        if let Some(auto_start) = AutoStart::new(&self.auto_start_config, time::Instant::now()) {
+            self.auto_start = Some(auto_start);
+            self.send_message(&self.lang.t("auto_start.scheduled", &[("seconds", &self.auto_start_config.delay)])).await?;
+        }
+        Ok(())
+    }
+
+    // 每张 !start 票缩短倒计时，至少留 5 秒
+    async fn shorten_auto_start(&mut self) -> Result<(), Box<dyn Error>> {
+        let by = Duration::from_secs(self.auto_start_config.vote_shorten);
+        let Some(auto_start) = self.auto_start.as_mut().filter(|_| !by.is_zero()) else {
+            return Ok(());
+        };
+        let remaining = auto_start.shorten(by, Duration::from_secs(5), time::Instant::now());
+        self.send_message(&self.lang.t("auto_start.shortened", &[("seconds", &remaining)])).await?;
+        Ok(())
+    }
+
+    /// 每秒调用一次，处理自动开始倒计时和房主挂机检测
+    pub async fn tick(&mut self, now: time::Instant) -> Result<(), Box<dyn Error>> {
+        self.tick_auto_start(now).await?;
+        self.tick_afk_host(now).await
+    }
+
+    async fn tick_auto_start(&mut self, now: time::Instant) -> Result<(), Box<dyn Error>> {
+        if self.is_game_started {
+            self.auto_start = None;
+        }
+        let Some(event) = self.auto_start.as_mut().and_then(|auto_start| auto_start.poll(now)) else {
+            return Ok(());
+        };
+        match event {
+            AutoStartEvent::Announce(seconds) => {
+                self.send_message(&self.lang.t("auto_start.remaining", &[("seconds", &seconds)])).await?;
+            }
+            AutoStartEvent::Start => {
+                self.auto_start = None;
+                if !self.player_list.is_empty() {
+                    self.start_game().await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 房主已经选图等待自动开始，或房间里只有房主一个人时不检测
+    async fn tick_afk_host(&mut self, now: time::Instant) -> Result<(), Box<dyn Error>> {
+        // no_host 轮换由 bot 选图，没有可以跳过的房主
+        if self.is_game_started || self.auto_start.is_some() || self.host_queue.len() < 2
+            || self.room_host.is_empty() || self.rotation.bot_picks() {
+            return Ok(());
+        }
+        match self.host_watch.poll(&self.afk_config, now) {
+            Some(AfkEvent::Warn) => {
+                let remaining = self.afk_config.skip_after.saturating_sub(self.afk_config.warn_after);
+                self.send_message(&self.lang.t("host.afk_warn", &[("player", &self.room_host), ("seconds", &remaining)])).await?;
+            }
+            Some(AfkEvent::Skip) => {
+                let host = self.room_host.clone();
+                self.host_watch.add_strike(&host);
+                self.send_message(&self.lang.t("host.afk_skip", &[("player", &host)])).await?;
+                self.rotate_host().await?;
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    pub fn save_latest_info_to_file(&self) -> Result<(), Box<dyn Error>> {
+        let state = BotState{
+            beatmap_name: self.beatmap_title_unicode.clone(),
+            beatmap_artist: self.beatmap_artist_unicode.clone(),
+            beatmap_star: self.beatmap_difficulty_rating,
+            player_list: self.player_list.clone(),
+            beatmap_id: self.beatmap_id,
+            room_host: self.room_host.clone(),
+            room_host_list: self.host_queue.players().to_vec(),
+            queue_opted_out: self.host_queue.opted_out().to_vec(),
+            approved_abort_list: self.votes.voters(VoteKind::Abort),
+            approved_start_list: self.votes.voters(VoteKind::Start),
+            approved_skip_list: self.votes.voters(VoteKind::Skip),
+            approved_close_list: self.votes.voters(VoteKind::Close),
+        };
+        let file = File::create(self.state_file())?;
+        serde_json::to_writer_pretty(&file, &state)?;
+        Ok(())
+    }
+
+    /// 从状态文件恢复房主队列和投票，玩家列表会在 !mp settings 后重建
+    pub fn load_state_from_file(&mut self) -> Result<(), Box<dyn Error>> {
+        let file = File::open(self.state_file())?;
+        let state: BotState = serde_json::from_reader(file)?;
+        self.beatmap_title_unicode = state.beatmap_name;
+        self.beatmap_artist_unicode = state.beatmap_artist;
+        self.beatmap_difficulty_rating = state.beatmap_star;
+        self.beatmap_id = state.beatmap_id;
+        self.room_host = state.room_host;
+        self.host_queue = HostQueue::from_parts(state.room_host_list, state.queue_opted_out);
+        self.votes.clear_all();
+        self.votes.restore(VoteKind::Abort, state.approved_abort_list);
+        self.votes.restore(VoteKind::Start, state.approved_start_list);
+        self.votes.restore(VoteKind::Skip, state.approved_skip_list);
+        self.votes.restore(VoteKind::Close, state.approved_close_list);
+        Ok(())
+    }
+}
+
+/// 所有房间的注册表，已创建的房间以频道名 (#mp_<id>) 为键
+pub struct RoomRegistry {
+    rooms: HashMap<String, Room>,
+    /// 尚未创建、等待 BanchoBot 回复的房间
+    pending: Vec<Room>,
+    room_count: usize,
+}
+
+impl RoomRegistry {
+    pub fn new(rooms: Vec<Room>) -> Self {
+        let room_count = rooms.len();
+        let mut registry = RoomRegistry { rooms: HashMap::new(), pending: Vec::new(), room_count };
+        for room in rooms {
+            if room.room_id == 0 {
+                registry.pending.push(room);
+            } else {
+                registry.insert(room);
+            }
+        }
+        registry
+    }
+
+    pub fn insert(&mut self, room: Room) {
+        self.rooms.insert(room.channel(), room);
+    }
+
+    /// 取出房间进行处理，处理完毕后需要用 [`RoomRegistry::insert`] 放回
+    pub fn take(&mut self, channel: &str) -> Option<Room> {
+        self.rooms.remove(channel)
+    }
+
+    pub fn add_pending(&mut self, room: Room) {
+        self.pending.push(room);
+    }
+
+    pub fn take_pending_by_name(&mut self, name: &str) -> Option<Room> {
+        let pos = self.pending.iter().position(|room| room.room_name == name)?;
+        Some(self.pending.remove(pos))
+    }
+
+    /// 已创建房间的频道名，按配置顺序排列
+    pub fn channels(&self) -> Vec<String> {
+        let mut rooms: Vec<&Room> = self.rooms.values().collect();
+        rooms.sort_by_key(|room| room.index);
+        rooms.into_iter().map(Room::channel).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty() && self.pending.is_empty()
+    }
+
+    /// 配置中第一个房间的语言，用于不属于任何房间的私聊回复
+    pub fn default_lang(&self) -> Lang {
+        self.rooms.values().chain(self.pending.iter())
+            .min_by_key(|room| room.index)
+            .map(|room| room.lang)
+            .unwrap_or_default()
+    }
+
+    pub fn rooms_mut(&mut self) -> impl Iterator<Item = &mut Room> {
+        self.rooms.values_mut()
+    }
+
+    pub fn pending_mut(&mut self) -> impl Iterator<Item = &mut Room> {
+        self.pending.iter_mut()
+    }
+
+    pub fn all_mut(&mut self) -> impl Iterator<Item = &mut Room> {
+        self.rooms.values_mut().chain(self.pending.iter_mut())
+    }
+
+    /// 按配置顺序返回各房间的 ID，未创建或已关闭的房间为 0
+    pub fn room_ids(&self) -> Vec<u32> {
+        let mut ids = vec![0; self.room_count];
+        for room in self.rooms.values() {
+            if let Some(id) = ids.get_mut(room.index) {
+                *id = room.room_id;
+            }
+        }
+        ids
+    }
+}
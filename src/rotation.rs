@@ -0,0 +1,271 @@
+use crate::queue::HostQueue;
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 房主轮换方式，来自 [[room]] 的 rotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationMode {
+    /// 按队列顺序轮流当房主
+    RoundRobin,
+    /// 随机选一位
+    Random,
+    /// 选图次数最少的玩家优先
+    FewestPicks,
+    /// 按进入房间的先后顺序轮流
+    JoinTime,
+    /// 不设房主，由 bot 从图池中选图
+    NoHost,
+}
+
+impl RotationMode {
+    pub const ALL: [RotationMode; 5] = [
+        RotationMode::RoundRobin,
+        RotationMode::Random,
+        RotationMode::FewestPicks,
+        RotationMode::JoinTime,
+        RotationMode::NoHost,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RotationMode::RoundRobin => "round_robin",
+            RotationMode::Random => "random",
+            RotationMode::FewestPicks => "fewest_picks",
+            RotationMode::JoinTime => "join_time",
+            RotationMode::NoHost => "no_host",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn strategy(self) -> Box<dyn RotationStrategy> {
+        match self {
+            RotationMode::RoundRobin => Box::new(RoundRobin),
+            RotationMode::Random => Box::new(Random::new()),
+            RotationMode::FewestPicks => Box::new(FewestPicks),
+            RotationMode::JoinTime => Box::new(JoinTime),
+            RotationMode::NoHost => Box::new(NoHost),
+        }
+    }
+}
+
+/// 选下一位房主时可以参考的房间信息
+pub struct RotationContext<'a> {
+    /// 当前 (或刚被跳过的) 房主，没有房主时为空
+    pub current_host: &'a str,
+    /// 本次运行中各玩家当房主打过的谱面数
+    pub pick_counts: &'a HashMap<String, u32>,
+    /// 各玩家进入房间的顺序，数字越小越早
+    pub join_order: &'a HashMap<String, u64>,
+    /// 房主离开房间后 join_order 中已经没有他，用这个值继续按进房顺序轮换
+    pub last_host_join: Option<u64>,
+}
+
+impl RotationContext<'_> {
+    fn picks(&self, name: &str) -> u32 {
+        self.pick_counts.get(name).copied().unwrap_or(0)
+    }
+
+    fn joined(&self, name: &str) -> u64 {
+        self.join_order.get(name).copied().unwrap_or(u64::MAX)
+    }
+
+    fn host_joined(&self) -> u64 {
+        self.join_order.get(self.current_host).copied().or(self.last_host_join).unwrap_or(u64::MAX)
+    }
+
+    /// 除当前房主外的候选人，只剩当前房主时仍然选他
+    fn candidates<'q>(&self, queue: &'q HostQueue) -> Vec<&'q String> {
+        let others: Vec<&String> = queue.players().iter().filter(|p| *p != self.current_host).collect();
+        if others.is_empty() { queue.players().iter().collect() } else { others }
+    }
+}
+
+/// 房主轮换策略。选出的房主需要排到队列最前面，这样 !queue 显示的顺序和实际一致
+pub trait RotationStrategy: Send {
+    fn mode(&self) -> RotationMode;
+
+    /// 返回下一位房主，队列为空时返回 None
+    fn next_host(&mut self, queue: &mut HostQueue, ctx: &RotationContext) -> Option<String>;
+
+    /// 为 true 时房间不设房主，由 bot 选图
+    fn bot_picks(&self) -> bool {
+        false
+    }
+}
+
+pub struct RoundRobin;
+
+impl RotationStrategy for RoundRobin {
+    fn mode(&self) -> RotationMode {
+        RotationMode::RoundRobin
+    }
+
+    fn next_host(&mut self, queue: &mut HostQueue, ctx: &RotationContext) -> Option<String> {
+        // 房主已经离开或退出了队列时，排在最前面的就是下一位房主，不需要再轮换
+        if queue.front().is_some_and(|front| front == ctx.current_host) {
+            queue.rotate();
+        }
+        queue.front().cloned()
+    }
+}
+
+/// 简单的 xorshift 随机数，不需要为此引入 rand
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new() -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        Self::with_seed(nanos)
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self { state: seed.max(1) }
+    }
+
+    fn next_index(&mut self, len: usize) -> usize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state % len as u64) as usize
+    }
+}
+
+impl RotationStrategy for Random {
+    fn mode(&self) -> RotationMode {
+        RotationMode::Random
+    }
+
+    fn next_host(&mut self, queue: &mut HostQueue, ctx: &RotationContext) -> Option<String> {
+        let candidates = ctx.candidates(queue);
+        if candidates.is_empty() {
+            return None;
+        }
+        let next = candidates[self.next_index(candidates.len())].clone();
+        queue.promote(&next);
+        Some(next)
+    }
+}
+
+pub struct FewestPicks;
+
+impl RotationStrategy for FewestPicks {
+    fn mode(&self) -> RotationMode {
+        RotationMode::FewestPicks
+    }
+
+    fn next_host(&mut self, queue: &mut HostQueue, ctx: &RotationContext) -> Option<String> {
+        // 次数相同时按队列顺序，min_by_key 返回第一个最小值
+        let next = ctx.candidates(queue).into_iter().min_by_key(|p| ctx.picks(p))?.clone();
+        queue.promote(&next);
+        Some(next)
+    }
+}
+
+pub struct JoinTime;
+
+impl RotationStrategy for JoinTime {
+    fn mode(&self) -> RotationMode {
+        RotationMode::JoinTime
+    }
+
+    fn next_host(&mut self, queue: &mut HostQueue, ctx: &RotationContext) -> Option<String> {
+        let current = ctx.host_joined();
+        let candidates = ctx.candidates(queue);
+        // 当前房主之后进房的最早一位，没有时从最早进房的玩家重新开始
+        let next = candidates.iter().filter(|p| ctx.joined(p) > current).min_by_key(|p| ctx.joined(p))
+            .or_else(|| candidates.iter().min_by_key(|p| ctx.joined(p)))?
+            .to_string();
+        queue.promote(&next);
+        Some(next)
+    }
+}
+
+pub struct NoHost;
+
+impl RotationStrategy for NoHost {
+    fn mode(&self) -> RotationMode {
+        RotationMode::NoHost
+    }
+
+    fn next_host(&mut self, _queue: &mut HostQueue, _ctx: &RotationContext) -> Option<String> {
+        None
+    }
+
+    fn bot_picks(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(names: &[&str]) -> HostQueue {
+        HostQueue::from_parts(names.iter().map(|n| n.to_string()).collect(), Vec::new())
+    }
+
+    fn join_order(values: &[(&str, u64)]) -> HashMap<String, u64> {
+        values.iter().map(|(n, v)| (n.to_string(), *v)).collect()
+    }
+
+    fn rotate(strategy: &mut dyn RotationStrategy, queue: &mut HostQueue, current: &str, picks: &HashMap<String, u32>, joined: &HashMap<String, u64>) -> Option<String> {
+        let ctx = RotationContext { current_host: current, pick_counts: picks, join_order: joined, last_host_join: None };
+        strategy.next_host(queue, &ctx)
+    }
+
+    #[test]
+    fn test_strategies() {
+        let none = HashMap::new();
+        let no_joins = HashMap::new();
+
+        let mut q = queue(&["a", "b", "c"]);
+        assert_eq!(rotate(&mut RoundRobin, &mut q, "a", &none, &no_joins).as_deref(), Some("b"));
+        // 房主已经离开时不再多轮换一次
+        assert_eq!(rotate(&mut RoundRobin, &mut q, "x", &none, &no_joins).as_deref(), Some("b"));
+        assert_eq!(rotate(&mut RoundRobin, &mut HostQueue::default(), "", &none, &no_joins), None);
+
+        let picks: HashMap<String, u32> = [("a", 2), ("b", 1)].iter().map(|(n, v)| (n.to_string(), *v)).collect();
+        let mut q = queue(&["a", "b", "c", "d"]);
+        assert_eq!(rotate(&mut FewestPicks, &mut q, "a", &picks, &no_joins).as_deref(), Some("c"));
+        assert_eq!(q.front().map(String::as_str), Some("c"));
+        // 当前房主不会连任，除非只剩他一个人
+        assert_eq!(rotate(&mut FewestPicks, &mut q, "c", &picks, &no_joins).as_deref(), Some("d"));
+        assert_eq!(rotate(&mut FewestPicks, &mut queue(&["a"]), "a", &picks, &no_joins).as_deref(), Some("a"));
+
+        // 进房顺序 c < a < b，与队列顺序无关
+        let joined = join_order(&[("c", 1), ("a", 2), ("b", 3)]);
+        let mut q = queue(&["a", "b", "c"]);
+        assert_eq!(rotate(&mut JoinTime, &mut q, "a", &none, &joined).as_deref(), Some("b"));
+        assert_eq!(rotate(&mut JoinTime, &mut q, "b", &none, &joined).as_deref(), Some("c"));
+        assert_eq!(rotate(&mut JoinTime, &mut q, "c", &none, &joined).as_deref(), Some("a"));
+        // 房主 a 已经离开，仍从他之后进房的 b 继续
+        let joined = join_order(&[("c", 1), ("b", 3)]);
+        let mut q = queue(&["b", "c"]);
+        let ctx = RotationContext { current_host: "a", pick_counts: &none, join_order: &joined, last_host_join: Some(2) };
+        assert_eq!(JoinTime.next_host(&mut q, &ctx).as_deref(), Some("b"));
+
+        let mut random = Random::with_seed(42);
+        let mut q = queue(&["a", "b", "c"]);
+        for _ in 0..20 {
+            let current = q.front().cloned().unwrap();
+            let next = rotate(&mut random, &mut q, &current, &none, &no_joins).unwrap();
+            assert_ne!(next, current);
+            assert_eq!(q.front(), Some(&next));
+            assert_eq!(q.len(), 3);
+        }
+
+        let mut q = queue(&["a", "b"]);
+        assert_eq!(rotate(&mut NoHost, &mut q, "a", &none, &no_joins), None);
+        assert!(NoHost.bot_picks());
+
+        assert_eq!(RotationMode::parse("Fewest_Picks"), Some(RotationMode::FewestPicks));
+        assert_eq!(RotationMode::parse("queue"), None);
+        assert_eq!(RotationMode::NoHost.strategy().mode(), RotationMode::NoHost);
+    }
+}
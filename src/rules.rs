@@ -0,0 +1,187 @@
+use crate::i18n::Lang;
+use crate::osu_api::Beatmap;
+use crate::pp_calculator;
+use serde::Deserialize;
+
+/// 房主选图的限制条件，未设置的条件不做检查
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MapRules {
+    pub min_star: Option<f32>,
+    pub max_star: Option<f32>,
+    /// 谱面最大长度，单位秒
+    pub max_length: Option<u64>,
+    /// 允许的谱面状态，例如 ranked、loved，为空时不限制
+    pub allowed_status: Vec<String>,
+    /// 允许的游戏模式 (mode_int)，为空时不限制
+    pub allowed_modes: Vec<u32>,
+    /// 同一房主违规多少次后自动跳过，0 表示不跳过
+    pub max_violations: u32,
+}
+
+impl MapRules {
+    /// 检查谱面是否符合规则，不符合时返回原因
+    pub fn check(&self, beatmap: &Beatmap, lang: Lang) -> Option<String> {
+        let stars = format!("{:.2}", beatmap.difficulty_rating);
+        if let Some(min_star) = self.min_star
+            && beatmap.difficulty_rating < min_star {
+            return Some(lang.t("rules.min_star", &[("stars", &stars), ("limit", &format!("{:.2}", min_star))]));
+        }
+        if let Some(max_star) = self.max_star
+            && beatmap.difficulty_rating > max_star {
+            return Some(lang.t("rules.max_star", &[("stars", &stars), ("limit", &format!("{:.2}", max_star))]));
+        }
+        if let Some(max_length) = self.max_length
+            && beatmap.total_length > max_length {
+            return Some(lang.t("rules.max_length", &[("length", &beatmap.total_length), ("limit", &max_length)]));
+        }
+        if !self.allowed_status.is_empty()
+            && !self.allowed_status.iter().any(|s| s.eq_ignore_ascii_case(&beatmap.status)) {
+            return Some(lang.t("rules.status", &[("status", &beatmap.status), ("allowed", &self.allowed_status.join("/"))]));
+        }
+        if !self.allowed_modes.is_empty() && !self.allowed_modes.contains(&beatmap.mode_int) {
+            return Some(lang.t("rules.mode", &[("mode", &beatmap.mode_int)]));
+        }
+        None
+    }
+
+    /// !setrules 的参数，例如 `max_star=6.5 status=ranked,loved`，值为 none 时取消该条件，reset 清空全部规则。
+    /// 有任何参数错误时不修改规则
+    pub fn update(&mut self, args: &str, lang: Lang) -> Result<(), String> {
+        let mut rules = self.clone();
+        for arg in args.split_whitespace() {
+            if arg.eq_ignore_ascii_case("reset") {
+                rules = MapRules::default();
+                continue;
+            }
+            let Some((key, value)) = arg.split_once('=') else {
+                return Err(lang.t("rules.bad_format", &[("arg", &arg)]));
+            };
+            let unset = value.eq_ignore_ascii_case("none");
+            let invalid = || lang.t("rules.bad_value", &[("key", &key), ("value", &value)]);
+            match key.to_lowercase().as_str() {
+                "min_star" => rules.min_star = if unset { None } else { Some(value.parse().map_err(|_| invalid())?) },
+                "max_star" => rules.max_star = if unset { None } else { Some(value.parse().map_err(|_| invalid())?) },
+                "max_length" => rules.max_length = if unset { None } else { Some(value.parse().map_err(|_| invalid())?) },
+                "status" => rules.allowed_status = if unset { Vec::new() } else { value.split(',').map(str::to_lowercase).collect() },
+                "modes" => rules.allowed_modes = if unset {
+                    Vec::new()
+                } else {
+                    value.split(',').map(|m| m.parse().map_err(|_| invalid())).collect::<Result<_, _>>()?
+                },
+                "violations" => rules.max_violations = if unset { 0 } else { value.parse().map_err(|_| invalid())? },
+                _ => return Err(lang.t("rules.unknown", &[("key", &key)])),
+            }
+        }
+        if let (Some(min), Some(max)) = (rules.min_star, rules.max_star)
+            && min > max {
+            return Err(lang.t("rules.min_over_max", &[("min", &format!("{:.2}", min)), ("max", &format!("{:.2}", max))]));
+        }
+        *self = rules;
+        Ok(())
+    }
+
+    pub fn describe(&self, lang: Lang) -> String {
+        let mut parts = Vec::new();
+        match (self.min_star, self.max_star) {
+            (Some(min), Some(max)) => parts.push(format!("{:.2}*-{:.2}*", min, max)),
+            (Some(min), None) => parts.push(format!(">={:.2}*", min)),
+            (None, Some(max)) => parts.push(format!("<={:.2}*", max)),
+            (None, None) => {}
+        }
+        if let Some(max_length) = self.max_length {
+            parts.push(format!("<={}s", max_length));
+        }
+        if !self.allowed_status.is_empty() {
+            parts.push(self.allowed_status.join("/"));
+        }
+        if !self.allowed_modes.is_empty() {
+            let modes: Vec<&str> = self.allowed_modes.iter().map(|mode| pp_calculator::mode_name(*mode)).collect();
+            parts.push(modes.join("/"));
+        }
+        if parts.is_empty() {
+            lang.t("rules.unlimited", &[])
+        } else {
+            parts.join(" | ")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osu_api::Beatmapset;
+
+    fn beatmap(difficulty_rating: f32, total_length: u64, status: &str, mode_int: u32) -> Beatmap {
+        Beatmap {
+            id: 1,
+            beatmapset_id: 1,
+            status: status.to_string(),
+            total_length,
+            version: String::new(),
+            difficulty_rating,
+            accuracy: 0.0,
+            ar: 0.0,
+            bpm: 0.0,
+            cs: 0.0,
+            drain: 0.0,
+            mode_int,
+            max_combo: 0,
+            beatmapset: Beatmapset {
+                artist: String::new(),
+                title: String::new(),
+                title_unicode: String::new(),
+                artist_unicode: String::new(),
+                submitted_date: String::new(),
+                ranked_date: None,
+            },
+            url: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_check_rules() {
+        let rules = MapRules {
+            min_star: Some(4.0),
+            max_star: Some(6.0),
+            max_length: Some(300),
+            allowed_status: vec!["ranked".to_string(), "loved".to_string()],
+            allowed_modes: vec![0],
+            max_violations: 0,
+        };
+
+        assert_eq!(rules.check(&beatmap(5.0, 200, "ranked", 0), Lang::ZhCn), None);
+        assert_eq!(rules.check(&beatmap(5.0, 200, "Loved", 0), Lang::ZhCn), None);
+        assert!(rules.check(&beatmap(3.5, 200, "ranked", 0), Lang::ZhCn).is_some());
+        assert!(rules.check(&beatmap(6.5, 200, "ranked", 0), Lang::ZhCn).is_some());
+        assert!(rules.check(&beatmap(5.0, 301, "ranked", 0), Lang::ZhCn).is_some());
+        assert!(rules.check(&beatmap(5.0, 200, "graveyard", 0), Lang::ZhCn).is_some());
+        assert!(rules.check(&beatmap(5.0, 200, "ranked", 3), Lang::ZhCn).is_some());
+        assert_eq!(MapRules::default().check(&beatmap(12.0, 9999, "wip", 2), Lang::ZhCn), None);
+    }
+
+    #[test]
+    fn test_update_rules() {
+        let mut rules = MapRules::default();
+        rules.update("min_star=4 max_star=6.5 status=Ranked,loved modes=0,3 violations=2", Lang::ZhCn).unwrap();
+        assert_eq!(rules.min_star, Some(4.0));
+        assert_eq!(rules.max_star, Some(6.5));
+        assert_eq!(rules.allowed_status, vec!["ranked", "loved"]);
+        assert_eq!(rules.allowed_modes, vec![0, 3]);
+        assert_eq!(rules.max_violations, 2);
+
+        rules.update("max_star=none max_length=300", Lang::ZhCn).unwrap();
+        assert_eq!(rules.max_star, None);
+        assert_eq!(rules.max_length, Some(300));
+
+        // 出错时整条指令都不生效
+        assert!(rules.update("max_length=120 min_star=abc", Lang::ZhCn).is_err());
+        assert!(rules.update("max_star=3", Lang::ZhCn).is_err());
+        assert!(rules.update("bpm=200", Lang::ZhCn).is_err());
+        assert_eq!(rules.max_length, Some(300));
+        assert_eq!(rules.describe(Lang::ZhCn), ">=4.00* | <=300s | ranked/loved | osu/mania");
+
+        rules.update("reset", Lang::ZhCn).unwrap();
+        assert_eq!(rules.describe(Lang::ZhCn), "无限制");
+    }
+}
@@ -0,0 +1,899 @@
+//! 离线的 irc 模拟器：用内存中的消息队列代替 irc.ppy.sh，由 [`FakeBancho`] 扮演 BanchoBot，
+//! 按脚本发出玩家进出、换图、开始/结束对局等消息，并像真实的 BanchoBot 一样回应 bot 发出的 !mp 指令
+
+use async_trait::async_trait;
+use irc::client::prelude::*;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::bot::MyBot;
+use crate::config::BotConfig;
+use crate::fake_osu_api::FakeOsuApi;
+use crate::room::Room;
+use crate::transport::{MessageSink, Transport};
+
+const BOT_NAME: &str = "ATRI";
+const ROOM_NAME: &str = "simulated room";
+const ROOM_ID: u32 = 1001;
+
+/// 模拟器使用的配置文件，Alice 是管理员
+pub const SIM_CONFIG: &str = r#"
+admins = ["Alice"]
+
+[connection]
+nickname = "ATRI"
+password = "irc password"
+
+[osu_api]
+fixture_dir = "fixtures"
+
+[outbox]
+rate = 0
+
+[[room]]
+name = "simulated room"
+password = "123"
+"#;
+
+/// bot 与模拟器共用的消息队列
+#[derive(Default)]
+struct Wire {
+    /// 发给 bot 的消息
+    incoming: Mutex<VecDeque<Message>>,
+    /// bot 发出的消息
+    outgoing: Mutex<Vec<Message>>,
+}
+
+impl MessageSink for Wire {
+    fn send(&self, message: Message) -> Result<(), Box<dyn Error>> {
+        self.outgoing.lock().unwrap().push(message);
+        Ok(())
+    }
+}
+
+/// 从队列中读取消息，队列为空时视为连接关闭
+struct FakeTransport(Arc<Wire>);
+
+#[async_trait(?Send)]
+impl Transport for FakeTransport {
+    async fn connect(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn identify(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn sink(&self) -> Arc<dyn MessageSink> {
+        self.0.clone()
+    }
+
+    async fn next_message(&mut self) -> Result<Option<Message>, Box<dyn Error>> {
+        Ok(self.0.incoming.lock().unwrap().pop_front())
+    }
+}
+
+/// 模拟的 BanchoBot 和房间状态
+pub struct FakeBancho {
+    wire: Arc<Wire>,
+    pub players: Vec<String>,
+    pub host: Option<String>,
+    pub beatmap_id: u32,
+    pub in_progress: bool,
+    /// bot 发到房间频道的消息
+    pub room_messages: Vec<String>,
+    /// bot 私聊玩家的消息 (玩家, 消息)
+    pub private_messages: Vec<(String, String)>,
+}
+
+impl FakeBancho {
+    fn channel(&self) -> String {
+        format!("#mp_{}", ROOM_ID)
+    }
+
+    /// 以 from 的身份向 target 发送一条消息
+    fn say(&self, from: &str, target: &str, text: &str) {
+        let message = format!(":{}!cho@ppy.sh PRIVMSG {} :{}", from, target, text)
+            .parse()
+            .expect("invalid simulated message");
+        self.wire.incoming.lock().unwrap().push_back(message);
+    }
+
+    fn announce(&self, text: &str) {
+        self.say("BanchoBot", &self.channel(), text);
+    }
+
+    /// 回应 bot 发出的消息，返回是否产生了新的消息
+    fn react(&mut self) -> bool {
+        let outgoing: Vec<Message> = self.wire.outgoing.lock().unwrap().drain(..).collect();
+        for message in &outgoing {
+            let Command::PRIVMSG(target, text) = &message.command else {
+                continue;
+            };
+            if target == "BanchoBot" {
+                if let Some(name) = text.strip_prefix("!mp make ") {
+                    self.say("BanchoBot", BOT_NAME, &format!("Created the tournament match https://osu.ppy.sh/mp/{} {}", ROOM_ID, name));
+                }
+            } else if *target == self.channel() {
+                self.room_messages.push(text.clone());
+                self.handle_mp_command(text);
+            } else {
+                self.private_messages.push((target.clone(), text.clone()));
+            }
+        }
+        !self.wire.incoming.lock().unwrap().is_empty()
+    }
+
+    fn handle_mp_command(&mut self, text: &str) {
+        let mut args = text.split_whitespace();
+        if args.next() != Some("!mp") {
+            return;
+        }
+        match args.next() {
+            Some("host") => {
+                let name = args.collect::<Vec<_>>().join(" ");
+                self.announce(&format!("Changed match host to {}", name));
+                self.host = Some(name);
+            }
+            Some("clearhost") => {
+                self.host = None;
+                self.announce("Cleared match host");
+            }
+            Some("map") => {
+                if let Some(id) = args.next().and_then(|id| id.parse().ok()) {
+                    self.beatmap_id = id;
+                    self.announce(&format!("Changed beatmap to https://osu.ppy.sh/b/{}", id));
+                }
+            }
+            Some("start") => {
+                self.in_progress = true;
+                self.announce("The match has started!");
+            }
+            Some("abort") => {
+                self.in_progress = false;
+                self.announce("Aborted the match");
+            }
+            Some("kick") => {
+                let name = args.collect::<Vec<_>>().join(" ");
+                if self.players.contains(&name) {
+                    self.players.retain(|p| *p != name);
+                    self.announce(&format!("Kicked {} from the match.", name));
+                }
+            }
+            Some("lock") => self.announce("Locked the match"),
+            Some("unlock") => self.announce("Unlocked the match"),
+            Some("close") => self.announce("Closed the match"),
+            Some("password") => self.announce("Changed the match password"),
+            Some("mods") => self.announce("Enabled FreeMod"),
+            Some("settings") => {
+                self.announce(&format!("Room name: {}, History: https://osu.ppy.sh/mp/{}", ROOM_NAME, ROOM_ID));
+                if self.beatmap_id != 0 {
+                    self.announce(&format!("Beatmap: https://osu.ppy.sh/b/{} Artist - Title [Diff]", self.beatmap_id));
+                }
+                for (i, name) in self.players.iter().enumerate() {
+                    let host = if self.host.as_deref() == Some(name) { " [Host]" } else { "" };
+                    self.announce(&format!("Slot {}  Not Ready https://osu.ppy.sh/u/{} {}{}", i + 1, i + 1, name, host));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+static NEXT_DATA_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// 一个 bot 加一个模拟的房间，所有操作都会处理到没有新消息为止，结果是确定的
+pub struct Simulator {
+    pub bot: MyBot,
+    pub bancho: FakeBancho,
+    data_dir: PathBuf,
+}
+
+impl Simulator {
+    /// 启动 bot 并完成建房，osu_api 提供换图时需要的谱面信息
+    pub async fn new(osu_api: FakeOsuApi) -> Self {
+        let data_dir = std::env::temp_dir().join(format!(
+            "irc_bot_sim_{}_{}",
+            std::process::id(),
+            NEXT_DATA_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        create_chart_db(&data_dir);
+
+        let config_file = data_dir.join("config.toml");
+        std::fs::write(&config_file, SIM_CONFIG).unwrap();
+        let mut config = BotConfig::load(Some(&config_file)).unwrap();
+        config.paths.data_dir = data_dir.clone();
+
+        let wire = Arc::new(Wire::default());
+        let settings = config.bot_settings(Some(config_file));
+        let bot = MyBot::new(BOT_NAME.to_string(), Box::new(FakeTransport(wire.clone())), Box::new(osu_api), settings)
+            .await
+            .unwrap();
+        let bancho = FakeBancho {
+            wire,
+            players: Vec::new(),
+            host: None,
+            beatmap_id: 0,
+            in_progress: false,
+            room_messages: Vec::new(),
+            private_messages: Vec::new(),
+        };
+
+        let mut sim = Simulator { bot, bancho, data_dir };
+        sim.bot.join_rooms().await.unwrap();
+        sim.run().await;
+        sim
+    }
+
+    /// 处理所有排队的消息，直到 bot 和 BanchoBot 都没有新消息
+    pub async fn run(&mut self) {
+        loop {
+            self.bot.process_messages().await.unwrap();
+            if !self.bancho.react() && !self.bot.finish_pending_results().await {
+                break;
+            }
+        }
+    }
+
+    pub async fn join(&mut self, name: &str) {
+        self.bancho.players.push(name.to_string());
+        let slot = self.bancho.players.len();
+        self.bancho.announce(&format!("{} joined in slot {}.", name, slot));
+        self.run().await;
+    }
+
+    pub async fn leave(&mut self, name: &str) {
+        self.bancho.players.retain(|p| p != name);
+        self.bancho.announce(&format!("{} left the game.", name));
+        self.run().await;
+    }
+
+    pub async fn chat(&mut self, name: &str, text: &str) {
+        self.bancho.say(name, &self.bancho.channel(), text);
+        self.run().await;
+    }
+
+    /// 经过 seconds 秒，触发这段时间内到期的计时器。需要在 start_paused 的测试中使用
+    pub async fn advance(&mut self, seconds: u64) {
+        tokio::time::advance(Duration::from_secs(seconds)).await;
+        self.bot.tick(Instant::now()).await;
+        self.run().await;
+    }
+
+    /// 玩家私聊 bot
+    pub async fn private_message(&mut self, name: &str, text: &str) {
+        self.bancho.say(name, BOT_NAME, text);
+        self.run().await;
+    }
+
+    /// 房主在游戏内换图
+    pub async fn host_changes_map(&mut self, beatmap_id: u32) {
+        self.bancho.beatmap_id = beatmap_id;
+        self.bancho.announce(&format!("Beatmap changed to: Artist - Title [Diff] (https://osu.ppy.sh/b/{})", beatmap_id));
+        self.run().await;
+    }
+
+    /// 所有人准备，bot 会发出 !mp start
+    pub async fn all_ready(&mut self) {
+        self.bancho.announce("All players are ready");
+        self.run().await;
+    }
+
+    /// 对局结束，results 为 (玩家, 分数, 是否 pass)
+    pub async fn finish(&mut self, results: &[(&str, u64, bool)]) {
+        for (name, score, passed) in results {
+            let result = if *passed { "PASSED" } else { "FAILED" };
+            self.bancho.announce(&format!("{} finished playing (Score: {}, {}).", name, score, result));
+        }
+        self.bancho.in_progress = false;
+        self.bancho.announce("The match has finished!");
+        self.run().await;
+    }
+
+    pub async fn play(&mut self, results: &[(&str, u64, bool)]) {
+        self.all_ready().await;
+        assert!(self.bancho.in_progress, "bot did not start the match");
+        self.finish(results).await;
+    }
+
+    /// 修改配置文件，之后可以用 !reload 或 SIGHUP 重新加载
+    pub fn write_config(&self, text: &str) {
+        std::fs::write(self.data_dir.join("config.toml"), text).unwrap();
+    }
+
+    /// 取出 bot 发到房间的消息
+    pub fn take_room_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.bancho.room_messages)
+    }
+
+    pub fn room<R>(&mut self, f: impl FnOnce(&Room) -> R) -> R {
+        let channel = self.bancho.channel();
+        let room = self.bot.rooms.take(&channel).expect("room not created");
+        let result = f(&room);
+        self.bot.rooms.insert(room);
+        result
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+/// 只有一张赛图的图池：MP5 S22 决赛 HD1 -> 4242
+fn create_chart_db(data_dir: &std::path::Path) {
+    let conn = rusqlite::Connection::open(data_dir.join("charts.sqlite")).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE charts (competition_name TEXT, season TEXT, pool_name TEXT, pool_index INTEGER,
+            chart_type TEXT, chart_type_index INTEGER, chart_id INTEGER);
+         INSERT INTO charts VALUES ('MP5', 'S22', '决赛', 1, 'HD', 1, 4242);",
+    )
+    .unwrap();
+}
+
+pub fn beatmap_json(beatmap_id: u32, total_length: u64) -> String {
+    format!(
+        r#"{{"id":{id},"beatmapset_id":{id},"status":"ranked","total_length":{len},"version":"Diff",
+        "difficulty_rating":5.0,"accuracy":8.0,"ar":9.0,"bpm":180.0,"cs":4.0,"drain":5.0,"mode_int":0,
+        "max_combo":500,"url":"https://osu.ppy.sh/b/{id}","beatmapset":{{"artist":"Artist","title":"Title",
+        "title_unicode":"Title","artist_unicode":"Artist","submitted_date":"2020-01-01T00:00:00Z",
+        "ranked_date":"2020-02-01T00:00:00Z"}}}}"#,
+        id = beatmap_id,
+        len = total_length
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osu_api;
+
+    async fn lobby(players: &[&str]) -> Simulator {
+        let mut sim = Simulator::new(FakeOsuApi::new()).await;
+        for player in players {
+            sim.join(player).await;
+        }
+        sim.take_room_messages();
+        sim
+    }
+
+    #[tokio::test]
+    async fn test_room_is_created_and_first_player_becomes_host() {
+        let mut sim = Simulator::new(FakeOsuApi::new()).await;
+        assert_eq!(sim.take_room_messages(), vec!["!mp password 123"]);
+
+        sim.join("Alice").await;
+        sim.join("Bob").await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Alice"));
+        let messages = sim.take_room_messages();
+        assert!(messages.contains(&"!mp host Alice".to_string()));
+        assert!(!messages.contains(&"!mp host Bob".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_host_rotates_after_each_match() {
+        let mut sim = lobby(&["Alice", "Bob", "Carol"]).await;
+
+        sim.play(&[("Alice", 1000, true), ("Bob", 2000, false)]).await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Bob"));
+        let messages = sim.take_room_messages();
+        assert!(messages.contains(&"本局结果: #1 Bob 2000 FAIL | #2 Alice 1000 PASS".to_string()), "{:?}", messages);
+
+        sim.play(&[]).await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Carol"));
+        sim.play(&[]).await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Alice"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_results_are_posted_after_background_pp_lookup() {
+        let mut api = FakeOsuApi::new();
+        api.insert_response(osu_api::user_info_path("Alice"), r#"{"id":1,"username":"Alice"}"#.to_string());
+        // api 中还没有这一局的成绩，重试几次后不带 pp 发出结果
+        api.insert_response(osu_api::user_recent_score_path(1, false), "[]".to_string());
+        let mut sim = lobby(&["Alice", "Bob"]).await;
+        sim.bot.osu_api = Box::new(api);
+
+        let started = tokio::time::Instant::now();
+        sim.play(&[("Alice", 1000, true)]).await;
+        assert!(started.elapsed() >= Duration::from_secs(10));
+        assert_eq!(sim.bancho.host.as_deref(), Some("Bob"));
+        let messages = sim.take_room_messages();
+        assert!(messages.contains(&"本局结果: #1 Alice 1000 PASS".to_string()), "{:?}", messages);
+        assert_eq!(sim.bot.stats_db.player_stats("Alice").unwrap().map(|stats| stats.passes), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_host_leaving_passes_host_on() {
+        let mut sim = lobby(&["Alice", "Bob", "Carol"]).await;
+        sim.leave("Alice").await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Bob"));
+        assert_eq!(sim.room(|room| room.host_queue.players().to_vec()), vec!["Bob", "Carol"]);
+    }
+
+    #[tokio::test]
+    async fn test_join_time_rotation_continues_after_host_leaves() {
+        let mut sim = lobby(&["Alice", "Bob", "Carol", "Dave"]).await;
+        sim.write_config(&format!("{}rotation = \"join_time\"\n", SIM_CONFIG));
+        sim.chat("Alice", "!reload").await;
+
+        sim.play(&[]).await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Bob"));
+        // Bob 离开后轮到比他晚进房的 Carol，而不是从 Alice 重新开始
+        sim.leave("Bob").await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Carol"));
+        sim.play(&[]).await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Dave"));
+    }
+
+    #[tokio::test]
+    async fn test_skip_and_abort_votes() {
+        let mut sim = lobby(&["Alice", "Bob", "Carol", "Dave"]).await;
+
+        sim.chat("Bob", "!skip").await;
+        assert_eq!(sim.take_room_messages(), vec!["skip 投票: 1 / 2"]);
+        sim.chat("Bob", "!skip").await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Alice"));
+        sim.chat("Carol", "!skip").await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Bob"));
+
+        sim.all_ready().await;
+        assert!(sim.bancho.in_progress);
+        sim.take_room_messages();
+        sim.chat("Alice", "!abort").await;
+        assert!(sim.bancho.in_progress);
+        sim.chat("Dave", "!abort").await;
+        assert!(!sim.bancho.in_progress);
+        assert!(sim.take_room_messages().contains(&"!mp abort".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_unvote_and_voters_leaving() {
+        let mut sim = lobby(&["Alice", "Bob", "Carol", "Dave", "Eve"]).await;
+
+        sim.chat("Bob", "!close").await;
+        sim.chat("Bob", "!unvote").await;
+        assert_eq!(sim.take_room_messages(), vec!["close 投票: 1 / 3", "Bob 撤回了 close 投票"]);
+        // 不在房间里的人不能投票
+        sim.chat("Zed", "!start").await;
+        assert!(sim.take_room_messages().is_empty());
+
+        sim.chat("Carol", "!start").await;
+        sim.chat("Dave", "!start").await;
+        assert_eq!(sim.take_room_messages(), vec!["start 投票: 1 / 3", "start 投票: 2 / 3"]);
+        // 有人离开后 4 人只需要 2 票
+        sim.leave("Eve").await;
+        assert!(sim.bancho.in_progress);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_auto_start_after_map_change() {
+        let mut api = FakeOsuApi::new();
+        api.insert_response(osu_api::beatmap_info_path(4242), beatmap_json(4242, 120));
+        api.insert_response(osu_api::beatmap_info_path(4343), beatmap_json(4343, 90));
+        let mut sim = lobby(&["Alice", "Bob", "Carol", "Dave"]).await;
+        sim.bot.osu_api = Box::new(api);
+
+        sim.host_changes_map(4242).await;
+        assert!(sim.take_room_messages().contains(&"120 秒后自动开始，所有人准备后立即开始".to_string()));
+        sim.advance(61).await;
+        assert_eq!(sim.take_room_messages(), vec!["还有 60 秒自动开始"]);
+
+        // !mp settings 里的当前谱面只恢复状态，不重新计时也不再发谱面信息
+        sim.bancho.announce("Beatmap: https://osu.ppy.sh/b/4242 Artist - Title [Diff]");
+        sim.run().await;
+        assert!(sim.take_room_messages().is_empty());
+        assert_eq!(sim.room(|room| (room.beatmap_id, room.host_violations)), (4242, 0));
+        sim.advance(30).await;
+        assert_eq!(sim.take_room_messages(), vec!["还有 30 秒自动开始"]);
+
+        // 再次换图时重新计时
+        sim.host_changes_map(4343).await;
+        sim.take_room_messages();
+        sim.advance(61).await;
+        assert_eq!(sim.take_room_messages(), vec!["还有 60 秒自动开始"]);
+
+        sim.chat("Bob", "!start").await;
+        assert_eq!(sim.take_room_messages(), vec!["start 投票: 1 / 2", "自动开始提前到 44 秒后"]);
+        sim.advance(35).await;
+        assert!(!sim.bancho.in_progress);
+        sim.advance(10).await;
+        assert!(sim.bancho.in_progress);
+        assert!(sim.room(|room| room.auto_start.is_none()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rule_violations_revert_the_map() {
+        let mut api = FakeOsuApi::new();
+        api.insert_response(osu_api::beatmap_info_path(4242), beatmap_json(4242, 400));
+        api.insert_response(osu_api::beatmap_info_path(4343), beatmap_json(4343, 90));
+        let mut sim = lobby(&["Alice", "Bob"]).await;
+        sim.bot.osu_api = Box::new(api);
+        sim.chat("Alice", "!setrules max_length=300").await;
+        sim.take_room_messages();
+
+        // 没有可以换回的谱面时不能开始
+        sim.host_changes_map(4242).await;
+        assert_eq!(sim.take_room_messages(), vec!["该谱面不符合房间规则: 长度 400s 超过 300s (规则: <=300s)"]);
+        sim.chat("Alice", "!start").await;
+        sim.chat("Bob", "!start").await;
+        assert!(!sim.bancho.in_progress);
+        assert_eq!(sim.take_room_messages().last().unwrap(), "当前谱面不符合房间规则，请房主换一张谱面后再开始");
+
+        sim.host_changes_map(4343).await;
+        sim.advance(30).await;
+        sim.take_room_messages();
+
+        // bot 换回谱面时不重新计时，也不再发谱面信息
+        sim.host_changes_map(4242).await;
+        assert_eq!(sim.bancho.beatmap_id, 4343);
+        assert_eq!(sim.take_room_messages(), vec![
+            "!mp map 4343".to_string(),
+            "该谱面不符合房间规则: 长度 400s 超过 300s (规则: <=300s)".to_string(),
+        ]);
+        assert_eq!(sim.room(|room| (room.beatmap_id, room.host_violations, room.pending_revert)), (4343, 2, None));
+        sim.advance(31).await;
+        assert_eq!(sim.take_room_messages(), vec!["还有 60 秒自动开始"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_afk_host_is_warned_then_skipped() {
+        let mut sim = lobby(&["Alice", "Bob", "Carol"]).await;
+
+        sim.advance(90).await;
+        assert_eq!(sim.take_room_messages(), vec!["Alice 请在 60 秒内选图，否则将跳过房主"]);
+        sim.advance(60).await;
+        // !mp 指令先于聊天消息发出
+        assert_eq!(sim.take_room_messages(), vec!["!mp host Bob", "Alice 长时间没有选图，跳过房主"]);
+
+        // Alice 又在当房主时离开，累计两次后下次轮到时跳过
+        sim.chat("Alice", "!sethost Alice").await;
+        sim.leave("Alice").await;
+        sim.join("Alice").await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Bob"));
+        sim.advance(150).await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Carol"));
+        sim.take_room_messages();
+        sim.advance(150).await;
+        let messages = sim.take_room_messages();
+        assert!(messages.contains(&"Alice 多次挂机或中途离开，这次跳过".to_string()), "{:?}", messages);
+        assert_eq!(sim.bancho.host.as_deref(), Some("Bob"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_queue_commands() {
+        let mut sim = lobby(&["Alice", "Bob", "Carol", "Dave"]).await;
+
+        sim.chat("Carol", "!queue position").await;
+        assert_eq!(sim.take_room_messages(), vec!["Carol 排在第 2 位，预计 6 分钟后轮到"]);
+
+        sim.chat("Bob", "!queue leave").await;
+        // 房主退出队列时交给下一位
+        sim.chat("Alice", "!queue leave").await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Carol"));
+        // !queue 有 5 秒冷却
+        sim.advance(5).await;
+        sim.chat("Bob", "!queue join").await;
+        assert_eq!(sim.room(|room| room.host_queue.players().to_vec()), vec!["Carol", "Dave", "Bob"]);
+        sim.take_room_messages();
+
+        sim.chat("Dave", "!queue move Bob 1").await;
+        assert_eq!(sim.take_room_messages(), vec!["你没有权限使用这个指令"]);
+        sim.chat("Alice", "!queue move Bob 1").await;
+        assert_eq!(sim.take_room_messages(), vec!["Bob 移动到了第 1 位"]);
+
+        sim.play(&[]).await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Bob"));
+        // 退出队列的玩家不会轮到
+        sim.play(&[]).await;
+        sim.play(&[]).await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Carol"));
+    }
+
+    #[tokio::test]
+    async fn test_admin_reloads_config_without_leaving_room() {
+        let mut sim = lobby(&["Alice", "Bob", "Carol", "Dave"]).await;
+        sim.write_config(&format!("{}\n[votes]\nratio = 0.2\n[messages]\nwelcome = \"hi {{player}}\"\n", SIM_CONFIG.replace("\"123\"", "\"456\"")));
+
+        sim.chat("Bob", "!reload").await;
+        assert_eq!(sim.take_room_messages(), vec!["你没有权限使用这个指令"]);
+
+        sim.chat("Alice", "!reload").await;
+        assert_eq!(sim.take_room_messages(), vec!["!mp password 456", "配置已重新加载"]);
+        sim.join("Eve").await;
+        assert_eq!(sim.take_room_messages(), vec!["hi Eve"]);
+        // 5 人 * 0.2，一票即可跳过
+        sim.chat("Carol", "!skip").await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Bob"));
+        sim.take_room_messages();
+
+        sim.write_config("[connection]\nnickname = \"\"");
+        sim.chat("Alice", "!reload").await;
+        let messages = sim.take_room_messages();
+        assert!(messages[0].starts_with("重新加载配置失败"), "{:?}", messages);
+        assert!(!messages[0].contains('\n'));
+    }
+
+    #[tokio::test]
+    async fn test_pick_sets_map_from_chart_pool() {
+        let mut api = FakeOsuApi::new();
+        api.insert_response(osu_api::beatmap_info_path(4242), beatmap_json(4242, 120));
+        api.insert_response(osu_api::beatmap_info_path(4343), beatmap_json(4343, 90));
+        let mut sim = Simulator::new(api).await;
+        sim.join("Alice").await;
+        sim.join("Bob").await;
+        sim.take_room_messages();
+
+        sim.chat("Bob", "!pick MP5 S22-1 HD1").await;
+        assert_eq!(sim.take_room_messages(), vec!["只有房主才能使用 !pick 哦"]);
+
+        sim.chat("Alice", "!pick MP5 S22-1 HD1").await;
+        assert_eq!(sim.bancho.beatmap_id, 4242);
+        assert!(sim.take_room_messages().contains(&"!mp map 4242".to_string()));
+        assert_eq!(sim.room(|room| (room.beatmap_id, room.beatmap_length)), (4242, 120));
+
+        sim.host_changes_map(4343).await;
+        assert_eq!(sim.room(|room| (room.beatmap_id, room.beatmap_length)), (4343, 90));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_skipped_host_pick_does_not_auto_start() {
+        let mut api = FakeOsuApi::new();
+        api.insert_response(osu_api::beatmap_info_path(4242), beatmap_json(4242, 120));
+        let mut sim = Simulator::new(api).await;
+        sim.join("Alice").await;
+        sim.join("Bob").await;
+        sim.chat("Alice", "!pick MP5 S22-1 HD1").await;
+        assert!(sim.room(|room| room.auto_start.is_some()));
+
+        sim.chat("Alice", "!skip").await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Bob"));
+        assert!(sim.room(|room| room.auto_start.is_none()));
+        sim.advance(130).await;
+        assert!(!sim.bancho.in_progress);
+    }
+
+    #[tokio::test]
+    async fn test_beatmaps_are_saved_in_maps_dir() {
+        let fixture_dir = std::env::temp_dir().join(format!("irc_bot_fixtures_{}", std::process::id()));
+        std::fs::create_dir_all(&fixture_dir).unwrap();
+        let osu = "osu file format v14\n\n[General]\nMode: 0\n\n[Difficulty]\nHPDrainRate:5\nCircleSize:4\nOverallDifficulty:8\nApproachRate:9\nSliderMultiplier:1.4\nSliderTickRate:1\n\n[TimingPoints]\n0,500,4,2,0,100,1,0\n\n[HitObjects]\n256,192,1000,1,0\n";
+        std::fs::write(osu_api::fixture_beatmap_file(&fixture_dir, 4242), osu).unwrap();
+        let mut api = FakeOsuApi::from_dir(fixture_dir.clone());
+        api.insert_response(osu_api::beatmap_info_path(4242), beatmap_json(4242, 120));
+        api.insert_response(osu_api::beatmap_info_path(4343), beatmap_json(4343, 120));
+        let mut sim = lobby(&["Alice"]).await;
+        sim.bot.osu_api = Box::new(api);
+
+        sim.host_changes_map(4242).await;
+        let path = sim.data_dir.join("maps").join("4242.osu");
+        assert!(path.exists());
+        assert_eq!(sim.room(|room| room.beatmap_path.clone()), path.to_string_lossy());
+        assert!(!sim.take_room_messages().iter().any(|m| m.contains("谱面文件")));
+
+        // 镜像下载失败时告诉玩家无法计算 pp
+        sim.host_changes_map(4343).await;
+        assert!(sim.take_room_messages().contains(&"找不到谱面文件，无法计算 pp".to_string()));
+        std::fs::remove_dir_all(fixture_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_no_host_rotation_picks_from_chart_pool() {
+        let mut api = FakeOsuApi::new();
+        api.insert_response(osu_api::beatmap_info_path(4242), beatmap_json(4242, 120));
+        let mut sim = Simulator::new(api).await;
+        sim.join("Alice").await;
+        sim.join("Bob").await;
+        sim.write_config(&format!("{}rotation = \"no_host\"\n", SIM_CONFIG));
+        sim.chat("Alice", "!reload").await;
+        sim.take_room_messages();
+
+        sim.play(&[]).await;
+        assert_eq!(sim.bancho.host, None);
+        assert_eq!(sim.bancho.beatmap_id, 4242);
+        let messages = sim.take_room_messages();
+        assert!(messages.contains(&"!mp clearhost".to_string()), "{:?}", messages);
+        assert!(messages.contains(&"当前谱面来自: MP5 S22 决赛(1) HD1".to_string()), "{:?}", messages);
+        assert_eq!(sim.room(|room| room.pick_counts.get("Alice").copied()), Some(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_host_rotation_skips_afk_check() {
+        let mut api = FakeOsuApi::new();
+        api.insert_response(osu_api::beatmap_info_path(4242), beatmap_json(4242, 120));
+        let mut sim = Simulator::new(api).await;
+        sim.join("Alice").await;
+        sim.join("Bob").await;
+        sim.write_config(&format!("{}rotation = \"no_host\"\n\n[auto_start]\ndelay = 0\n", SIM_CONFIG));
+        sim.chat("Alice", "!reload").await;
+        sim.play(&[]).await;
+        sim.take_room_messages();
+
+        sim.advance(200).await;
+        assert!(sim.take_room_messages().is_empty());
+        assert_eq!(sim.bancho.host, None);
+    }
+
+    #[tokio::test]
+    async fn test_admin_commands_in_room() {
+        let mut sim = lobby(&["Alice", "Bob", "Carol"]).await;
+
+        sim.chat("Bob", "!kick Carol").await;
+        assert_eq!(sim.take_room_messages(), vec!["你没有权限使用这个指令"]);
+
+        sim.chat("Alice", "!kick Carol").await;
+        assert_eq!(sim.take_room_messages(), vec!["!mp kick Carol"]);
+        assert_eq!(sim.bancho.players, vec!["Alice", "Bob"]);
+        assert_eq!(sim.room(|room| room.player_list.clone()), vec!["Alice", "Bob"]);
+
+        sim.chat("Alice", "!sethost bob").await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Bob"));
+        sim.chat("Alice", "!forceskip").await;
+        assert_eq!(sim.bancho.host.as_deref(), Some("Alice"));
+        sim.take_room_messages();
+
+        sim.chat("Alice", "!setrules max_star=6 max_length=abc").await;
+        assert!(sim.take_room_messages()[0].starts_with("规则没有修改"));
+        sim.chat("Alice", "!setrules max_star=6").await;
+        assert_eq!(sim.take_room_messages(), vec!["规则已更新: <=6.00*"]);
+
+        // 被封禁的玩家重新加入会被再次踢出，解除封禁后可以正常加入
+        sim.chat("Alice", "!ban Bob 2h 刷屏").await;
+        assert_eq!(sim.bancho.players, vec!["Alice"]);
+        sim.take_room_messages();
+        sim.join("Bob").await;
+        assert_eq!(sim.bancho.players, vec!["Alice"]);
+        assert!(sim.take_room_messages().iter().any(|m| m.starts_with("Bob 已被封禁")));
+
+        sim.chat("Alice", "!bans").await;
+        assert_eq!(sim.take_room_messages(), vec!["封禁列表: Bob (剩余 2小时0分钟, 原因: 刷屏, 由 Alice 封禁)"]);
+        sim.chat("Alice", "!unban Bob").await;
+        sim.join("Bob").await;
+        assert_eq!(sim.bancho.players, vec!["Alice", "Bob"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_help_and_usage() {
+        let mut sim = lobby(&["Alice", "Bob"]).await;
+
+        // 菜单由指令表生成，普通玩家看不到管理指令
+        sim.chat("Bob", "help").await;
+        let menu = sim.take_room_messages().join(" ");
+        assert!(menu.contains("!close 投票关闭房间") && menu.contains("!hello 打个招呼"));
+        assert!(menu.contains("!queue(!q)") && !menu.contains("!kick"));
+
+        sim.chat("Alice", "!h q").await;
+        sim.chat("Alice", "!help kick").await;
+        sim.chat("Alice", "!help nothing").await;
+        assert_eq!(sim.take_room_messages(), vec![
+            "用法: !queue [leave|join|position|move <玩家> <位置>] | 查看房主队列，leave/join 退出/重新加入队列，position 查看位置和预计等待时间，管理员可以用 move 调整玩家在队列中的位置 | 别名: !q",
+            "用法: !kick <玩家> | 踢出玩家 | 需要协管权限",
+            "没有指令 nothing，输入 !help 查看全部指令",
+        ]);
+
+        // 缺少参数或参数无效时回复用法
+        sim.chat("Alice", "!kick").await;
+        sim.chat("Bob", "!unvote everything").await;
+        assert_eq!(sim.take_room_messages(), vec![
+            "用法: !kick <玩家> | 踢出玩家 | 需要协管权限",
+            "用法: !unvote [abort|skip|close|start] | 撤回投票，不写时撤回全部",
+        ]);
+
+        // 私聊时只列出可以私聊使用的指令，英文回复也会翻译参数
+        sim.private_message("Alice", "!lang en").await;
+        sim.private_message("Alice", "!help ban").await;
+        assert_eq!(sim.bancho.private_messages.pop(), Some((
+            "Alice".to_string(),
+            "Usage: !ban <player> [duration] [reason] | Kick and keep a player out, duration like 30m/2h/7d, permanent when omitted | requires admin".to_string(),
+        )));
+        sim.advance(10).await;
+        sim.private_message("Bob", "!help").await;
+        let (_, menu) = sim.bancho.private_messages.pop().unwrap();
+        assert!(menu.starts_with("!lang 设置私聊回复的语言 | !help(!h)") && !menu.contains("!kick"));
+    }
+
+    #[tokio::test]
+    async fn test_score_commands_report_api_errors() {
+        let mut sim = lobby(&["Alice", "Bob"]).await;
+        for command in ["!re", "!pr", "!s"] {
+            sim.chat("Alice", command).await;
+            let messages = sim.take_room_messages();
+            assert_eq!(messages.len(), 1, "{:?}", messages);
+            assert!(messages[0].starts_with("获取玩家信息失败"), "{:?}", messages);
+        }
+        // 查询失败的玩家不会被缓存
+        assert!(sim.bot.player_info.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_command_cooldowns() {
+        let mut sim = lobby(&["Alice", "Bob"]).await;
+
+        // 冷却中第一次违规忽略，之后私聊警告，第五次违规禁言
+        for _ in 0..6 {
+            sim.chat("Bob", "!hello").await;
+        }
+        assert_eq!(sim.take_room_messages(), vec!["你好, Bob!"]);
+        let warnings: Vec<String> = sim.bancho.private_messages.drain(..).map(|(_, text)| text).collect();
+        assert_eq!(warnings, vec![
+            "!hello 还在冷却，请 30 秒后再试",
+            "!hello 还在冷却，请 30 秒后再试",
+            "!hello 还在冷却，请 30 秒后再试",
+            "指令发送太频繁，5 分钟内 bot 不会回应你的指令",
+        ]);
+        sim.chat("Bob", "!about").await;
+        sim.chat("Bob", "!kick Alice").await;
+        assert!(sim.take_room_messages().is_empty());
+
+        // 管理员不受限制，可以解除禁言
+        for _ in 0..3 {
+            sim.chat("Alice", "!hello").await;
+        }
+        assert_eq!(sim.take_room_messages(), vec!["你好, Alice!"; 3]);
+        sim.chat("Alice", "!unmute Bob").await;
+        assert_eq!(sim.take_room_messages(), vec!["已解除 Bob 的指令禁言"]);
+        sim.chat("Bob", "!about").await;
+        assert_eq!(sim.take_room_messages().len(), 1);
+
+        // 每人 10 秒内最多 5 条指令，超出后第一次忽略，第二次警告
+        sim.advance(10).await;
+        for command in ["!info", "!ttl", "!top", "!stats", "!help", "!pp", "!q"] {
+            sim.chat("Bob", command).await;
+        }
+        assert_eq!(sim.bancho.private_messages, vec![("Bob".to_string(), "指令发送太频繁，请稍后再试".to_string())]);
+        sim.advance(10).await;
+        sim.take_room_messages();
+        sim.chat("Bob", "!q").await;
+        assert_eq!(sim.take_room_messages().len(), 1);
+
+        // 没有权限的指令不计入限制
+        sim.advance(10).await;
+        for _ in 0..6 {
+            sim.chat("Bob", "!pick MP5 S22-1 HD1").await;
+            sim.chat("Bob", "!kick Alice").await;
+        }
+        assert!(sim.take_room_messages().contains(&"只有房主才能使用 !pick 哦".to_string()));
+        sim.chat("Bob", "!hello").await;
+        assert_eq!(sim.take_room_messages(), vec!["你好, Bob!"]);
+        assert_eq!(sim.bancho.private_messages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_admin_commands_by_private_message() {
+        let mut sim = lobby(&["Alice", "Bob"]).await;
+
+        sim.private_message("Bob", "!say hi").await;
+        assert!(sim.take_room_messages().is_empty());
+        assert_eq!(sim.bancho.private_messages.pop(), Some(("Bob".to_string(), "你没有权限使用这个指令".to_string())));
+
+        sim.private_message("Alice", "!say hello").await;
+        sim.private_message("Alice", "!lock").await;
+        assert_eq!(sim.take_room_messages(), vec!["hello", "!mp lock"]);
+        assert_eq!(sim.bancho.private_messages.pop(), Some(("Alice".to_string(), "房间位置已锁定".to_string())));
+
+        sim.private_message("Alice", "!kick #mp_1 Bob").await;
+        assert_eq!(sim.bancho.private_messages.pop(), Some(("Alice".to_string(), "没有找到房间 #mp_1".to_string())));
+        sim.private_message("Alice", "!kick #mp_1001 Bob").await;
+        assert_eq!(sim.bancho.players, vec!["Alice"]);
+
+        // 设置私聊语言后管理指令的回复改用英文，房间消息不变
+        sim.private_message("Alice", "!lang fr").await;
+        assert_eq!(sim.bancho.private_messages.pop(), Some(("Alice".to_string(), "不支持的语言 fr，可选: zh-CN en".to_string())));
+        sim.private_message("Alice", "!lang en").await;
+        assert_eq!(sim.bancho.private_messages.pop(), Some(("Alice".to_string(), "Private replies will now be in English".to_string())));
+        sim.private_message("Alice", "!lock").await;
+        assert_eq!(sim.bancho.private_messages.pop(), Some(("Alice".to_string(), "Slots unlocked".to_string())));
+        sim.private_message("Alice", "!lang default").await;
+        assert_eq!(sim.bancho.private_messages.pop(), Some(("Alice".to_string(), "已恢复使用房间默认语言 (中文)".to_string())));
+
+        sim.private_message("Alice", "!shutdown").await;
+        assert!(sim.bot.is_shutdown_requested());
+    }
+}
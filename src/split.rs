@@ -0,0 +1,98 @@
+/// Bancho 会截断过长的消息，irc 一行最多 512 字节，还要留出 PRIVMSG 和频道名的长度
+pub const MAX_MESSAGE_BYTES: usize = 450;
+
+/// 按 UTF-8 字节长度把过长的消息分成几条，优先在 `|` 和 `->` 后面分开，其次在空格处，
+/// 不会拆开 osu! 的 `[链接 文字]`。分开处行尾的 `|` 会被去掉
+pub fn split_message(text: &str, max_bytes: usize) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut rest = text.trim();
+    while rest.len() > max_bytes {
+        let (head, tail) = rest.split_at(split_point(rest, max_bytes));
+        let head = head.trim_end();
+        let head = head.strip_suffix('|').unwrap_or(head).trim_end();
+        if !head.is_empty() {
+            parts.push(head.to_string());
+        }
+        rest = tail.trim_start();
+    }
+    if !rest.is_empty() {
+        parts.push(rest.to_string());
+    }
+    parts
+}
+
+/// 前 max_bytes 字节内最合适的分割位置，至少包含一个字符
+fn split_point(text: &str, max_bytes: usize) -> usize {
+    let mut separator = None;
+    let mut space = None;
+    let mut link_start = None;
+    // 最后一个不在链接内的字符边界
+    let mut outside = 0;
+    let mut depth = 0usize;
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        if end > max_bytes {
+            break;
+        }
+        match c {
+            '[' => {
+                if depth == 0 {
+                    link_start = Some(i);
+                }
+                depth += 1;
+            }
+            ']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        if depth > 0 {
+            continue;
+        }
+        if c == '|' || (c == '>' && text[..i].ends_with('-')) {
+            separator = Some(end);
+        } else if c.is_whitespace() {
+            space = Some(end);
+        }
+        outside = end;
+    }
+    // 在链接中间到达长度上限时，链接整个放到下一条
+    let before_link = if depth > 0 { link_start.filter(|start| *start > 0) } else { None };
+    separator.or(space).or(before_link).unwrap_or(if outside > 0 {
+        outside
+    } else {
+        // 一个链接本身就超过上限，只能在字符边界处硬拆
+        text.char_indices().map(|(i, c)| i + c.len_utf8()).take_while(|end| *end <= max_bytes).last()
+            .unwrap_or_else(|| text.chars().next().map_or(text.len(), char::len_utf8))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_message() {
+        assert_eq!(split_message("短消息", MAX_MESSAGE_BYTES), vec!["短消息"]);
+
+        // 日文标题每个字占 3 字节，按字符数计算不会超过上限
+        let results: Vec<String> = (1..=20).map(|i| format!("#{} 夜に駆ける {}", i, 10000 + i)).collect();
+        let message = results.join(" | ");
+        assert!(message.chars().count() < MAX_MESSAGE_BYTES && message.len() > MAX_MESSAGE_BYTES);
+        let parts = split_message(&message, MAX_MESSAGE_BYTES);
+        assert_eq!(parts.len(), 2);
+        assert!(parts.iter().all(|part| part.len() <= MAX_MESSAGE_BYTES && !part.ends_with('|')));
+        assert!(parts[1].starts_with('#'));
+        assert_eq!(parts.join(" | "), message);
+
+        let queue = "房主队列: 千本桜 -> 紅蓮華 -> 残酷な天使のテーゼ";
+        assert_eq!(split_message(queue, 30), vec!["房主队列: 千本桜 ->", "紅蓮華 ->", "残酷な天使のテーゼ"]);
+
+        // 链接里的 | 和空格不会被分开，整个链接放到下一条
+        let link = "[https://osu.ppy.sh/b/75 夜に駆ける | YOASOBI [Insane]]";
+        assert_eq!(split_message(&format!("当前谱面:{}", link), 70), vec!["当前谱面:", link]);
+        assert_eq!(split_message(&format!("{} | 4.5*", link), 64), vec![link, "4.5*"]);
+
+        // 没有分隔符时在字符边界处拆开
+        let parts = split_message(&"漢".repeat(20), 10);
+        assert_eq!(parts, vec!["漢漢漢"; 6].into_iter().chain(["漢漢"]).collect::<Vec<_>>());
+    }
+}
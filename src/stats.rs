@@ -0,0 +1,264 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+
+use crate::i18n::Lang;
+use crate::permission::normalize_name;
+use crate::results::PlayerResult;
+
+/// 一局对局的记录，在 BanchoBot 宣布对局结束后写入
+#[derive(Debug, Clone)]
+pub struct MatchRecord {
+    pub room_id: u32,
+    pub beatmap_id: u32,
+    pub beatmap_name: String,
+    pub mods: String,
+    pub host: String,
+    pub results: Vec<PlayerResult>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerStats {
+    pub name: String,
+    pub plays: u32,
+    pub passes: u32,
+    /// 只统计 pass 且取到成绩的对局
+    pub avg_accuracy: Option<f64>,
+    pub hosted: u32,
+    /// 玩得最多的谱面及次数
+    pub most_played: Vec<(String, u32)>,
+}
+
+impl PlayerStats {
+    pub fn pass_rate(&self) -> f64 {
+        if self.plays == 0 {
+            0.0
+        } else {
+            self.passes as f64 / self.plays as f64 * 100.0
+        }
+    }
+
+    pub fn format(&self, lang: Lang) -> String {
+        let accuracy = self.avg_accuracy
+            .map(|acc| format!("{:.2}%", acc * 100.0))
+            .unwrap_or_else(|| "-".to_string());
+        let maps = if self.most_played.is_empty() {
+            "-".to_string()
+        } else {
+            self.most_played.iter()
+                .map(|(name, count)| format!("{} x{}", name, count))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        lang.t("stats.player", &[
+            ("player", &self.name),
+            ("plays", &self.plays),
+            ("passes", &self.passes),
+            ("rate", &format!("{:.1}", self.pass_rate())),
+            ("accuracy", &accuracy),
+            ("hosted", &self.hosted),
+            ("maps", &maps),
+        ])
+    }
+}
+
+pub struct StatsDatabase {
+    conn: Connection,
+}
+
+impl StatsDatabase {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, rusqlite::Error> {
+        Self::init(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, rusqlite::Error> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, rusqlite::Error> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS matches (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                room_id INTEGER NOT NULL,
+                beatmap_id INTEGER NOT NULL,
+                beatmap_name TEXT NOT NULL,
+                mods TEXT NOT NULL,
+                host TEXT NOT NULL,
+                played_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            );
+            CREATE TABLE IF NOT EXISTS match_scores (
+                match_id INTEGER NOT NULL REFERENCES matches(id),
+                player TEXT NOT NULL COLLATE NOCASE,
+                score INTEGER NOT NULL,
+                passed INTEGER NOT NULL,
+                accuracy REAL,
+                pp REAL
+            );
+            CREATE INDEX IF NOT EXISTS idx_match_scores_player ON match_scores(player);
+            CREATE TABLE IF NOT EXISTS player_langs (
+                player TEXT PRIMARY KEY COLLATE NOCASE,
+                lang TEXT NOT NULL
+            );",
+        )?;
+        Ok(Self { conn })
+    }
+
+    pub fn record_match(&mut self, record: &MatchRecord) -> Result<(), rusqlite::Error> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO matches (room_id, beatmap_id, beatmap_name, mods, host) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![record.room_id, record.beatmap_id, record.beatmap_name, record.mods, normalize_name(&record.host)],
+        )?;
+        let match_id = tx.last_insert_rowid();
+        for result in &record.results {
+            tx.execute(
+                "INSERT INTO match_scores (match_id, player, score, passed, accuracy, pp) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![match_id, normalize_name(&result.name), result.score as i64, result.passed, result.accuracy, result.pp],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// 没有任何记录时返回 None
+    pub fn player_stats(&self, name: &str) -> Result<Option<PlayerStats>, rusqlite::Error> {
+        let name = normalize_name(name);
+        let totals = self.conn.query_row(
+            "SELECT player, COUNT(*), SUM(passed), AVG(CASE WHEN passed THEN accuracy END)
+             FROM match_scores WHERE player = ?1 GROUP BY player",
+            params![&name],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?, row.get::<_, u32>(2)?, row.get::<_, Option<f64>>(3)?)),
+        ).optional()?;
+        let Some((name, plays, passes, avg_accuracy)) = totals else {
+            return Ok(None);
+        };
+
+        let hosted = self.conn.query_row(
+            "SELECT COUNT(*) FROM matches WHERE host = ?1 COLLATE NOCASE",
+            params![name],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT m.beatmap_name, COUNT(*) AS plays FROM match_scores s JOIN matches m ON s.match_id = m.id
+             WHERE s.player = ?1 GROUP BY m.beatmap_id ORDER BY plays DESC, MAX(m.id) DESC LIMIT 3",
+        )?;
+        let most_played = stmt
+            .query_map(params![name], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Some(PlayerStats { name, plays, passes, avg_accuracy, hosted, most_played }))
+    }
+
+    /// 玩家用 !lang 设置的私聊语言
+    pub fn player_lang(&self, name: &str) -> Result<Option<Lang>, rusqlite::Error> {
+        let code = self.conn.query_row(
+            "SELECT lang FROM player_langs WHERE player = ?1",
+            params![normalize_name(name)],
+            |row| row.get::<_, String>(0),
+        ).optional()?;
+        Ok(code.and_then(|code| Lang::parse(&code)))
+    }
+
+    /// lang 为 None 时删除设置，恢复使用房间语言
+    pub fn set_player_lang(&mut self, name: &str, lang: Option<Lang>) -> Result<(), rusqlite::Error> {
+        match lang {
+            Some(lang) => self.conn.execute(
+                "INSERT INTO player_langs (player, lang) VALUES (?1, ?2)
+                 ON CONFLICT(player) DO UPDATE SET lang = excluded.lang",
+                params![normalize_name(name), lang.code()],
+            )?,
+            None => self.conn.execute("DELETE FROM player_langs WHERE player = ?1", params![normalize_name(name)])?,
+        };
+        Ok(())
+    }
+
+    /// 游玩局数最多的玩家，返回 (玩家, 局数, pass 率)
+    pub fn top_players(&self, limit: u32) -> Result<Vec<(String, u32, f64)>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT player, COUNT(*) AS plays, SUM(passed) * 100.0 / COUNT(*) FROM match_scores
+             GROUP BY player ORDER BY plays DESC, player LIMIT ?1",
+        )?;
+        stmt.query_map(params![limit], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect()
+    }
+
+    /// 被玩得最多的谱面，返回 (谱面, 局数)
+    pub fn top_maps(&self, limit: u32) -> Result<Vec<(String, u32)>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT beatmap_name, COUNT(*) AS plays FROM matches
+             GROUP BY beatmap_id ORDER BY plays DESC, MAX(id) DESC LIMIT ?1",
+        )?;
+        stmt.query_map(params![limit], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect()
+    }
+}
+
+pub fn format_top(players: &[(String, u32, f64)], maps: &[(String, u32)], lang: Lang) -> String {
+    if players.is_empty() {
+        return lang.t("stats.top_empty", &[]);
+    }
+    let players = players.iter()
+        .enumerate()
+        .map(|(i, (name, plays, pass_rate))| lang.t("stats.top_player", &[
+            ("rank", &(i + 1)),
+            ("player", name),
+            ("plays", plays),
+            ("rate", &format!("{:.0}", pass_rate)),
+        ]))
+        .collect::<Vec<_>>()
+        .join(" | ");
+    let maps = maps.iter()
+        .map(|(name, plays)| format!("{} x{}", name, plays))
+        .collect::<Vec<_>>()
+        .join(", ");
+    lang.t("stats.top", &[("players", &players), ("maps", &maps)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, score: u64, passed: bool, accuracy: Option<f64>) -> PlayerResult {
+        PlayerResult { accuracy, ..PlayerResult::new(name.to_string(), score, passed) }
+    }
+
+    fn record(beatmap_id: u32, beatmap_name: &str, host: &str, results: Vec<PlayerResult>) -> MatchRecord {
+        MatchRecord {
+            room_id: 1,
+            beatmap_id,
+            beatmap_name: beatmap_name.to_string(),
+            mods: String::new(),
+            host: host.to_string(),
+            results,
+        }
+    }
+
+    #[test]
+    fn test_record_and_query_stats() {
+        let mut db = StatsDatabase::open_in_memory().unwrap();
+        db.record_match(&record(1, "Map A", "Alice", vec![
+            result("Alice", 900000, true, Some(0.98)),
+            result("Bob Smith", 300000, false, None),
+        ])).unwrap();
+        db.record_match(&record(1, "Map A", "Bob Smith", vec![
+            result("Alice", 800000, true, Some(0.94)),
+            result("Bob Smith", 700000, true, Some(0.90)),
+        ])).unwrap();
+        db.record_match(&record(2, "Map B", "Alice", vec![
+            result("Alice", 100000, false, None),
+        ])).unwrap();
+
+        let alice = db.player_stats("alice").unwrap().unwrap();
+        assert_eq!(alice.name, "Alice");
+        assert_eq!((alice.plays, alice.passes, alice.hosted), (3, 2, 2));
+        assert!((alice.avg_accuracy.unwrap() - 0.96).abs() < 1e-9);
+        assert_eq!(alice.most_played, vec![("Map A".to_string(), 2), ("Map B".to_string(), 1)]);
+        assert_eq!(db.player_stats("Carol").unwrap(), None);
+
+        let top = db.top_players(5).unwrap();
+        assert_eq!(top[0], ("Alice".to_string(), 3, 200.0 / 3.0));
+        assert_eq!(top[1], ("Bob_Smith".to_string(), 2, 50.0));
+        assert_eq!(db.player_stats("bob_smith").unwrap().unwrap().hosted, 1);
+        assert_eq!(db.top_maps(1).unwrap(), vec![("Map A".to_string(), 2)]);
+    }
+}
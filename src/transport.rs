@@ -0,0 +1,131 @@
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use irc::client::prelude::*;
+use irc::client::ClientStream;
+use std::error::Error;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::time::Instant;
+
+use crate::outbox::{Outbox, OutboxConfig, OutboxStats, Outgoing};
+use crate::split::{split_message, MAX_MESSAGE_BYTES};
+
+/// 发出 irc 消息的一端，真实连接为 irc 的 Sender，离线测试时为模拟器
+pub trait MessageSink {
+    fn send(&self, message: Message) -> Result<(), Box<dyn Error>>;
+}
+
+impl MessageSink for Sender {
+    fn send(&self, message: Message) -> Result<(), Box<dyn Error>> {
+        Ok(Sender::send(self, message)?)
+    }
+}
+
+/// 可以复制给各个房间的发送端，消息先进入限速队列，由 bot 按速率发给当前连接
+#[derive(Clone)]
+pub struct MessageSender(Arc<Mutex<Outbox>>);
+
+impl MessageSender {
+    pub fn new(outbox: Outbox) -> Self {
+        Self(Arc::new(Mutex::new(outbox)))
+    }
+
+    /// 过长的消息会分成几条发送
+    pub fn send_privmsg(&self, target: &str, message: &str) {
+        let parts = split_message(message, MAX_MESSAGE_BYTES).into_iter()
+            .map(|part| Outgoing::Privmsg { target: target.to_string(), text: part })
+            .collect();
+        self.push(parts);
+    }
+
+    pub fn send_join(&self, channel: &str) {
+        self.push(vec![Outgoing::Join(channel.to_string())]);
+    }
+
+    /// 把限速允许的消息发给 sink，返回下一条消息可以发送的时间，队列为空时返回 None
+    pub fn flush(&self, sink: &dyn MessageSink, now: Instant) -> Result<Option<Instant>, Box<dyn Error>> {
+        let mut outbox = self.outbox();
+        while let Some(message) = outbox.pop(now) {
+            let message: Message = match message {
+                Outgoing::Privmsg { target, text } => Command::PRIVMSG(target, text).into(),
+                Outgoing::Join(channel) => Command::JOIN(channel, None, None).into(),
+            };
+            sink.send(message)?;
+        }
+        Ok(outbox.next_send_at(now))
+    }
+
+    pub fn stats(&self) -> OutboxStats {
+        self.outbox().stats()
+    }
+
+    pub fn set_config(&self, config: OutboxConfig) {
+        self.outbox().set_config(config);
+    }
+
+    fn push(&self, parts: Vec<Outgoing>) {
+        if !self.outbox().push(parts.clone()) {
+            println!("合并重复消息: {:?}", parts);
+        }
+    }
+
+    fn outbox(&self) -> MutexGuard<'_, Outbox> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// bot 与 irc 服务器之间的连接
+#[async_trait(?Send)]
+pub trait Transport {
+    /// 建立新的连接，断线重连时也会调用
+    async fn connect(&mut self) -> Result<(), Box<dyn Error>>;
+    /// 登录并开始接收消息
+    fn identify(&mut self) -> Result<(), Box<dyn Error>>;
+    /// 当前连接的发送端，重连后需要重新获取
+    fn sink(&self) -> Arc<dyn MessageSink>;
+    /// 下一条收到的消息，连接关闭时返回 None
+    async fn next_message(&mut self) -> Result<Option<Message>, Box<dyn Error>>;
+}
+
+/// 通过 irc crate 连接 irc.ppy.sh
+pub struct IrcTransport {
+    config: Config,
+    client: Option<Client>,
+    stream: Option<ClientStream>,
+}
+
+impl IrcTransport {
+    pub fn new(config: Config) -> Self {
+        Self { config, client: None, stream: None }
+    }
+
+    fn client(&self) -> &Client {
+        self.client.as_ref().expect("IrcTransport is not connected")
+    }
+}
+
+#[async_trait(?Send)]
+impl Transport for IrcTransport {
+    async fn connect(&mut self) -> Result<(), Box<dyn Error>> {
+        self.stream = None;
+        self.client = Some(Client::from_config(self.config.clone()).await?);
+        Ok(())
+    }
+
+    fn identify(&mut self) -> Result<(), Box<dyn Error>> {
+        let client = self.client.as_mut().expect("IrcTransport is not connected");
+        client.identify()?;
+        self.stream = Some(client.stream()?);
+        Ok(())
+    }
+
+    fn sink(&self) -> Arc<dyn MessageSink> {
+        Arc::new(self.client().sender())
+    }
+
+    async fn next_message(&mut self) -> Result<Option<Message>, Box<dyn Error>> {
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(None);
+        };
+        Ok(stream.next().await.transpose()?)
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 玩家可以发起的投票
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VoteKind {
+    Abort,
+    Skip,
+    Close,
+    Start,
+}
+
+impl VoteKind {
+    pub const ALL: [VoteKind; 4] = [VoteKind::Abort, VoteKind::Skip, VoteKind::Close, VoteKind::Start];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VoteKind::Abort => "abort",
+            VoteKind::Skip => "skip",
+            VoteKind::Close => "close",
+            VoteKind::Start => "start",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name().eq_ignore_ascii_case(name.trim_start_matches(['!', '！'])))
+    }
+
+    /// 房主一个人就可以决定丢弃游戏或跳过自己
+    pub fn host_decides(self) -> bool {
+        matches!(self, VoteKind::Abort | VoteKind::Skip)
+    }
+}
+
+/// 一种投票的通过条件
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoteRule {
+    /// 投票人数达到房间人数的这个比例即通过
+    pub ratio: f64,
+    /// 至少需要的票数，房间人数更少时需要全部玩家同意
+    pub min_voters: usize,
+    /// 第一票之后超过这个时间没有通过则作废，为空时不过期
+    pub timeout: Option<Duration>,
+}
+
+impl Default for VoteRule {
+    fn default() -> Self {
+        Self { ratio: 0.5, min_voters: 1, timeout: None }
+    }
+}
+
+impl VoteRule {
+    /// players 人的房间通过需要的票数，至少一票
+    pub fn votes_needed(&self, players: usize) -> usize {
+        // 减去一点误差，避免 5 * 0.2 这样的乘积向上取整成 2
+        let by_ratio = (players as f64 * self.ratio - 1e-9).ceil().max(0.0) as usize;
+        by_ratio.max(self.min_voters).min(players).max(1)
+    }
+}
+
+/// 每种投票的通过条件，来自配置文件的 [votes]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VoteRules {
+    pub abort: VoteRule,
+    pub skip: VoteRule,
+    pub close: VoteRule,
+    pub start: VoteRule,
+}
+
+impl VoteRules {
+    pub fn get(&self, kind: VoteKind) -> &VoteRule {
+        match kind {
+            VoteKind::Abort => &self.abort,
+            VoteKind::Skip => &self.skip,
+            VoteKind::Close => &self.close,
+            VoteKind::Start => &self.start,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoteResult {
+    Passed,
+    Pending { votes: usize, needed: usize },
+}
+
+#[derive(Debug, Clone)]
+struct Vote {
+    voters: Vec<String>,
+    started_at: Instant,
+}
+
+/// 房间内进行中的投票，投票人为 player_list 中的玩家名
+#[derive(Debug, Clone, Default)]
+pub struct Votes {
+    active: HashMap<VoteKind, Vote>,
+}
+
+impl Votes {
+    /// 投一票，同一个人重复投票不会多计。通过后该投票被清空
+    pub fn cast(&mut self, kind: VoteKind, voter: &str, rule: &VoteRule, players: usize, now: Instant) -> VoteResult {
+        self.expire(kind, rule, now);
+        let vote = self.active.entry(kind).or_insert_with(|| Vote { voters: Vec::new(), started_at: now });
+        if !vote.voters.iter().any(|v| v == voter) {
+            vote.voters.push(voter.to_string());
+        }
+        self.check(kind, rule, players)
+    }
+
+    /// 撤回投票，kind 为空时撤回该玩家的全部投票，返回撤回了哪些投票
+    pub fn cancel(&mut self, voter: &str, kind: Option<VoteKind>) -> Vec<VoteKind> {
+        let mut cancelled = Vec::new();
+        for (k, vote) in self.active.iter_mut() {
+            if kind.is_none_or(|kind| kind == *k) && vote.voters.iter().any(|v| v == voter) {
+                vote.voters.retain(|v| v != voter);
+                cancelled.push(*k);
+            }
+        }
+        self.active.retain(|_, vote| !vote.voters.is_empty());
+        cancelled.sort_by_key(|k| VoteKind::ALL.iter().position(|a| a == k));
+        cancelled
+    }
+
+    /// 玩家离开房间后撤回他的票并按新的人数重新计算，返回因此通过的投票。已经超时的投票直接作废
+    pub fn remove_voter(&mut self, voter: &str, rules: &VoteRules, players: usize, now: Instant) -> Vec<VoteKind> {
+        self.cancel(voter, None);
+        for kind in VoteKind::ALL {
+            self.expire(kind, rules.get(kind), now);
+        }
+        let active: Vec<VoteKind> = VoteKind::ALL.into_iter().filter(|kind| self.active.contains_key(kind)).collect();
+        active.into_iter()
+            .filter(|kind| self.check(*kind, rules.get(*kind), players) == VoteResult::Passed)
+            .collect()
+    }
+
+    pub fn clear(&mut self, kind: VoteKind) {
+        self.active.remove(&kind);
+    }
+
+    pub fn clear_all(&mut self) {
+        self.active.clear();
+    }
+
+    pub fn voters(&self, kind: VoteKind) -> Vec<String> {
+        self.active.get(&kind).map(|vote| vote.voters.clone()).unwrap_or_default()
+    }
+
+    /// 从状态文件恢复，恢复的投票从现在开始计时
+    pub fn restore(&mut self, kind: VoteKind, voters: Vec<String>) {
+        if !voters.is_empty() {
+            self.active.insert(kind, Vote { voters, started_at: Instant::now() });
+        }
+    }
+
+    fn expire(&mut self, kind: VoteKind, rule: &VoteRule, now: Instant) {
+        if let (Some(timeout), Some(vote)) = (rule.timeout, self.active.get(&kind))
+            && now.duration_since(vote.started_at) >= timeout {
+            self.active.remove(&kind);
+        }
+    }
+
+    fn check(&mut self, kind: VoteKind, rule: &VoteRule, players: usize) -> VoteResult {
+        let votes = self.active.get(&kind).map_or(0, |vote| vote.voters.len());
+        let needed = rule.votes_needed(players);
+        if votes >= needed {
+            self.active.remove(&kind);
+            VoteResult::Passed
+        } else {
+            VoteResult::Pending { votes, needed }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_votes_needed() {
+        let rule = VoteRule::default();
+        // 旧的 len()/2 在一个人的房间里零票就能通过
+        assert_eq!(rule.votes_needed(1), 1);
+        assert_eq!(rule.votes_needed(4), 2);
+        assert_eq!(rule.votes_needed(5), 3);
+        assert_eq!(VoteRule { ratio: 0.2, ..rule }.votes_needed(5), 1);
+        assert_eq!(VoteRule { min_voters: 3, ..rule }.votes_needed(2), 2);
+        assert_eq!(VoteRule { min_voters: 3, ..rule }.votes_needed(4), 3);
+    }
+
+    #[test]
+    fn test_cast_cancel_expire_and_leave() {
+        let rules = VoteRules {
+            skip: VoteRule { timeout: Some(Duration::from_secs(60)), ..VoteRule::default() },
+            ..VoteRules::default()
+        };
+        let now = Instant::now();
+        let mut votes = Votes::default();
+
+        assert_eq!(votes.cast(VoteKind::Skip, "a", &rules.skip, 4, now), VoteResult::Pending { votes: 1, needed: 2 });
+        assert_eq!(votes.cast(VoteKind::Skip, "a", &rules.skip, 4, now), VoteResult::Pending { votes: 1, needed: 2 });
+        // 超时后重新计票
+        let later = now + Duration::from_secs(61);
+        assert_eq!(votes.cast(VoteKind::Skip, "b", &rules.skip, 4, later), VoteResult::Pending { votes: 1, needed: 2 });
+        assert_eq!(votes.cast(VoteKind::Skip, "c", &rules.skip, 4, later), VoteResult::Passed);
+        assert!(votes.voters(VoteKind::Skip).is_empty());
+
+        votes.cast(VoteKind::Start, "a", &rules.start, 4, now);
+        votes.cast(VoteKind::Close, "a", &rules.close, 4, now);
+        assert_eq!(votes.cancel("a", Some(VoteKind::Close)), vec![VoteKind::Close]);
+        assert_eq!(votes.cancel("a", None), vec![VoteKind::Start]);
+        assert!(votes.cancel("a", None).is_empty());
+
+        // 5 人需要 3 票，两人投票后有人离开，4 人只需要 2 票
+        votes.cast(VoteKind::Abort, "a", &rules.abort, 5, now);
+        votes.cast(VoteKind::Abort, "b", &rules.abort, 5, now);
+        assert_eq!(votes.remove_voter("e", &rules, 4, now), vec![VoteKind::Abort]);
+        // 离开的投票人不再计票
+        votes.cast(VoteKind::Start, "a", &rules.start, 4, now);
+        assert!(votes.remove_voter("a", &rules, 3, now).is_empty());
+        assert!(votes.voters(VoteKind::Start).is_empty());
+
+        // 超时的投票不会因为有人离开而通过
+        votes.cast(VoteKind::Skip, "a", &rules.skip, 3, now);
+        assert!(votes.remove_voter("b", &rules, 2, later).is_empty());
+        assert!(votes.voters(VoteKind::Skip).is_empty());
+
+        assert_eq!(VoteKind::parse("!Skip"), Some(VoteKind::Skip));
+        assert_eq!(VoteKind::parse("pick"), None);
+    }
+}